@@ -0,0 +1,115 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+
+use bufr_decoder::filter::ElementSelection;
+use bufr_decoder::{BitReader, BufrDecoder};
+
+const ROWS: usize = 16;
+const ROW_BYTES: usize = 16384;
+
+// Big-endian bit packing of Section 4
+struct BitWriter {
+    bytes: Vec<u8>,
+    bits: usize,
+}
+
+impl BitWriter {
+    fn put(&mut self, value: u64, width: usize) {
+        for i in (0..width).rev() {
+            if self.bits.is_multiple_of(8) {
+                self.bytes.push(0);
+            }
+            if (value >> i) & 1 == 1 {
+                *self.bytes.last_mut().unwrap() |= 0x80 >> (self.bits % 8);
+            }
+            self.bits += 1;
+        }
+    }
+}
+
+fn descriptor(fxy: &str) -> [u8; 2] {
+    let parts: Vec<u8> = fxy.split('-').map(|p| p.parse().unwrap()).collect();
+    [(parts[0] << 6) | parts[1], parts[2]]
+}
+
+// OPERA polar stereographic composite (3-01-192, 3-01-193, 3-21-206) of ROWS x ROW_BYTES bytes
+fn radar_message() -> Vec<u8> {
+    let mut data = BitWriter { bytes: Vec::new(), bits: 0 };
+    for (value, width) in [(2024, 12), (12, 4), (28, 6), (12, 5), (0, 6)] {
+        data.put(value, width);
+    }
+    for (lat, lon) in [(5100, 200), (5100, 206), (5097, 206), (5097, 200)] {
+        data.put(lat + 9000, 15);
+        data.put(lon + 18000, 16);
+    }
+    for (value, width) in [(1, 5), (18000, 15), (18000, 16), (100, 16), (100, 16), (ROW_BYTES as u64, 12), (ROWS as u64, 12)] {
+        data.put(value, width);
+    }
+    for (value, width) in [(6378137, 26), (6356752, 26), (18000, 16), (18000, 15), (33554432, 26), (33554432, 26), (13500, 15), (13500, 15)] {
+        data.put(value, width);
+    }
+    data.put(3200, 14);
+    data.put(5, 7);
+    data.put(0, 8);
+    data.put(ROWS as u64, 16);
+    let mut seed = 12345u64;
+    for _ in 0..ROWS {
+        data.put(ROW_BYTES as u64, 16);
+        for _ in 0..ROW_BYTES {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            data.put(seed >> 56, 8);
+        }
+    }
+    let mut section4 = data.bytes;
+    if !section4.len().is_multiple_of(2) {
+        section4.push(0);
+    }
+
+    let mut message = b"BUFR\0\0\0\x04".to_vec();
+    // Section 1, edition 4 : centre 247, master 16, local 8, 2024-12-28 12:00:00
+    message.extend([0, 0, 22, 0, 0, 247, 0, 0, 0, 0, 0, 0, 0, 16, 8, 0x07, 0xe8, 12, 28, 12, 0, 0]);
+    let descriptors: Vec<u8> = ["3-01-192", "3-01-193", "0-21-198", "0-21-199", "3-21-206"].iter().flat_map(|d| descriptor(d)).collect();
+    let length_3 = 7 + descriptors.len() + descriptors.len() % 2;
+    message.extend([0, 0, length_3 as u8, 0, 0, 1, 0x80]);
+    message.extend(&descriptors);
+    if !descriptors.len().is_multiple_of(2) {
+        message.push(0);
+    }
+    let length_4 = section4.len() + 4;
+    message.extend([(length_4 >> 16) as u8, (length_4 >> 8) as u8, length_4 as u8, 0]);
+    message.extend(&section4);
+    message.extend(b"7777");
+    let total = message.len();
+    message[4..7].copy_from_slice(&[(total >> 16) as u8, (total >> 8) as u8, total as u8]);
+    message
+}
+
+fn decoder(selection: Option<&[&str]>) -> BufrDecoder {
+    let mut decoder = BufrDecoder::with_tables(concat!(env!("CARGO_MANIFEST_DIR"), "/../tables"));
+    decoder.select(selection.map(ElementSelection::new));
+    decoder
+}
+
+fn bench_selection(c: &mut Criterion) {
+    let message = radar_message();
+    let mut group = c.benchmark_group("radar");
+    group.throughput(Throughput::Bytes(message.len() as u64));
+    let cases: [(&str, Option<&[&str]>); 3] = [
+        ("all elements", None),
+        ("image", Some(&["0-04-*", "0-30-021", "0-30-022", "0-30-197", "0-30-198"])),
+        ("dimensions", Some(&["0-04-*", "0-30-021", "0-30-022"])),
+    ];
+    for (name, selection) in cases {
+        let mut decoder = decoder(selection);
+        group.bench_function(name, |b| {
+            b.iter_batched(
+                || BitReader::new(message.as_slice()),
+                |mut reader| decoder.decode_bufr_message(&mut reader, 8).unwrap().unwrap(),
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_selection);
+criterion_main!(benches);
@@ -0,0 +1,35 @@
+#![no_main]
+// Arbitrary bytes listed, then decoded strictly and leniently with the tables of the repository.
+// Seeded with the conformance corpus : cargo fuzz run decode_message ../tests/data
+use std::cell::RefCell;
+use std::path::Path;
+
+use libfuzzer_sys::fuzz_target;
+
+use bufr_decoder::options::{DecodeOptions, Strictness};
+use bufr_decoder::{skip_rest, BitReader, BufrDecoder};
+
+thread_local! {
+    // kept from one input to the next so the tables are only loaded once
+    static DECODER: RefCell<BufrDecoder> =
+        RefCell::new(BufrDecoder::with_tables(Path::new(env!("CARGO_MANIFEST_DIR")).join("..").join("..").join("tables").to_string_lossy()));
+}
+
+fuzz_target!(|data: &[u8]| {
+    DECODER.with(|decoder| {
+        let mut decoder = decoder.borrow_mut();
+
+        let mut reader = BitReader::new(data);
+        while let Ok(Some(header)) = decoder.read_header(&mut reader, 8) {
+            if skip_rest(&mut reader, &header).is_err() {
+                break;
+            }
+        }
+
+        for strictness in [Strictness::Strict, Strictness::Lenient] {
+            decoder.set_options(DecodeOptions { strictness, ..DecodeOptions::default() });
+            let mut reader = BitReader::new(data);
+            while let Ok(Some(_)) = decoder.decode_bufr_message(&mut reader, 8) {}
+        }
+    });
+});
@@ -0,0 +1,20 @@
+#![no_main]
+// Arbitrary bytes read as a Table B and a Table D, then every sequence expanded
+use libfuzzer_sys::fuzz_target;
+
+use bufr_decoder::expand::{expand, DEFAULT_MAX_DEPTH, DEFAULT_MAX_NODES};
+use bufr_decoder::tables::{read_table_b, read_table_d};
+
+fuzz_target!(|data: &[u8]| {
+    let mut diagnostics = Vec::new();
+    let _ = read_table_b(data, "fuzz_b.csv", &mut diagnostics);
+    let Ok(table_d) = read_table_d(data, "fuzz_d.csv", &mut diagnostics) else {
+        return;
+    };
+    for sequence in table_d.keys() {
+        let _ = expand(&[sequence.clone()], &[&table_d], DEFAULT_MAX_DEPTH, DEFAULT_MAX_NODES);
+    }
+    for diagnostic in &diagnostics {
+        let _ = diagnostic.to_string();
+    }
+});
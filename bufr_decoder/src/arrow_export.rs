@@ -0,0 +1,201 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::Write;
+use std::sync::Arc;
+
+use arrow::array::{ArrayRef, Float64Array, PrimitiveDictionaryBuilder, StringArray, UInt32Array};
+use arrow::datatypes::{DataType, Field, Int32Type, Int64Type, Schema, SchemaRef};
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+
+use crate::export::{column_names, columns_of, keyed, Column, ColumnKey};
+use crate::units::Unit;
+use crate::DataValue;
+
+pub const DEFAULT_BATCH_SIZE: usize = 65536;
+
+enum ColumnData {
+    Number(Vec<Option<f64>>),
+    Code(Vec<Option<i64>>), // code table entries, dictionary encoded
+    Text(Vec<Option<String>>),
+}
+
+impl ColumnData {
+    fn new(unit: &Unit) -> ColumnData {
+        match unit {
+            Unit::Character => ColumnData::Text(Vec::new()),
+            Unit::CodeTable => ColumnData::Code(Vec::new()),
+            _ => ColumnData::Number(Vec::new()),
+        }
+    }
+
+    fn data_type(&self) -> DataType {
+        match self {
+            ColumnData::Number(_) => DataType::Float64,
+            ColumnData::Code(_) => DataType::Dictionary(Box::new(DataType::Int32), Box::new(DataType::Int64)),
+            ColumnData::Text(_) => DataType::Utf8,
+        }
+    }
+
+    fn push(&mut self, value: Option<&DataValue>) {
+        match self {
+            ColumnData::Number(v) => v.push(value.and_then(|d| d.value)),
+            ColumnData::Code(v) => v.push(value.and_then(|d| d.value).map(|c| c as i64)),
+            ColumnData::Text(v) => v.push(value.and_then(|d| d.text.clone())),
+        }
+    }
+
+    fn take(&mut self) -> ArrayRef {
+        match self {
+            ColumnData::Number(v) => Arc::new(Float64Array::from(std::mem::take(v))),
+            ColumnData::Code(v) => {
+                let mut builder = PrimitiveDictionaryBuilder::<Int32Type, Int64Type>::new();
+                for code in std::mem::take(v) {
+                    match code {
+                        Some(code) => {
+                            builder.append_value(code);
+                        }
+                        None => builder.append_null(),
+                    }
+                }
+                Arc::new(builder.finish())
+            }
+            ColumnData::Text(v) => Arc::new(StringArray::from(std::mem::take(v))),
+        }
+    }
+}
+
+// Accumulates the subsets of successive messages into Arrow RecordBatches of `batch_size` rows.
+// As for the CSV export, the schema is given by the first message : one nullable column per
+// element occurrence, after the message and subset numbers.
+pub struct BatchBuilder {
+    batch_size: usize,
+    selection: Option<Vec<String>>,
+    columns: Vec<Column>,
+    schema: Option<SchemaRef>,
+    messages: Vec<u32>,
+    subsets: Vec<u32>,
+    data: Vec<ColumnData>,
+    message: u32,
+}
+
+impl BatchBuilder {
+    // `selection` : FXY descriptors to keep, all elements when None
+    pub fn new(batch_size: usize, selection: Option<Vec<String>>) -> Self {
+        BatchBuilder {
+            batch_size: batch_size.max(1),
+            selection,
+            columns: Vec::new(),
+            schema: None,
+            messages: Vec::new(),
+            subsets: Vec::new(),
+            data: Vec::new(),
+            message: 0,
+        }
+    }
+
+    pub fn schema(&self) -> Option<SchemaRef> {
+        self.schema.clone()
+    }
+
+    fn init_schema(&mut self, subsets: &[Vec<DataValue>]) {
+        self.columns = columns_of(subsets, self.selection.as_deref());
+        self.data = self.columns.iter().map(|c| ColumnData::new(&c.unit)).collect();
+        let mut fields = vec![Field::new("message", DataType::UInt32, false), Field::new("subset", DataType::UInt32, false)];
+        for ((column, name), data) in self.columns.iter().zip(column_names(&self.columns, false)).zip(&self.data) {
+            let metadata = HashMap::from([
+                ("bufr_descriptor".to_string(), column.key.0.clone()),
+                ("units".to_string(), column.unit.to_string()),
+            ]);
+            fields.push(Field::new(name, data.data_type(), true).with_metadata(metadata));
+        }
+        self.schema = Some(Arc::new(Schema::new(fields)));
+    }
+
+    fn batch(&mut self) -> Result<RecordBatch, Box<dyn Error>> {
+        let schema = self.schema.clone().ok_or("No schema before the first message")?;
+        let mut arrays: Vec<ArrayRef> = vec![
+            Arc::new(UInt32Array::from(std::mem::take(&mut self.messages))),
+            Arc::new(UInt32Array::from(std::mem::take(&mut self.subsets))),
+        ];
+        arrays.extend(self.data.iter_mut().map(ColumnData::take));
+        Ok(RecordBatch::try_new(schema, arrays)?)
+    }
+
+    // Adds the subsets of one message, returns the batches completed meanwhile
+    pub fn push_subsets(&mut self, subsets: &[Vec<DataValue>]) -> Result<Vec<RecordBatch>, Box<dyn Error>> {
+        self.message += 1;
+        if self.schema.is_none() && !subsets.is_empty() {
+            self.init_schema(subsets);
+        } else if !subsets.is_empty() {
+            let layout = columns_of(subsets, self.selection.as_deref());
+            if layout.iter().map(|c| &c.key).ne(self.columns.iter().map(|c| &c.key)) {
+                log::warn!("Message {} : elements differ from the columns of the first message, some are dropped or left empty", self.message);
+            }
+        }
+        let mut batches = Vec::new();
+        for (index, subset) in subsets.iter().enumerate() {
+            let values: HashMap<ColumnKey, &DataValue> = keyed(subset).into_iter().collect();
+            self.messages.push(self.message);
+            self.subsets.push(index as u32 + 1);
+            for (column, data) in self.columns.iter().zip(self.data.iter_mut()) {
+                data.push(values.get(&column.key).copied());
+            }
+            if self.messages.len() == self.batch_size {
+                batches.push(self.batch()?);
+            }
+        }
+        Ok(batches)
+    }
+
+    // Last, incomplete, batch
+    pub fn finish(&mut self) -> Result<Option<RecordBatch>, Box<dyn Error>> {
+        if self.messages.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(self.batch()?))
+    }
+}
+
+// Parquet file (Snappy compressed) written batch after batch
+pub struct ParquetExporter<W: Write + Send> {
+    builder: BatchBuilder,
+    output: Option<W>,
+    writer: Option<ArrowWriter<W>>,
+}
+
+impl<W: Write + Send> ParquetExporter<W> {
+    pub fn new(output: W, batch_size: usize, selection: Option<Vec<String>>) -> Self {
+        ParquetExporter { builder: BatchBuilder::new(batch_size, selection), output: Some(output), writer: None }
+    }
+
+    fn write_batches(&mut self, batches: Vec<RecordBatch>) -> Result<(), Box<dyn Error>> {
+        for batch in batches {
+            if self.writer.is_none() {
+                let output = self.output.take().ok_or("Parquet output already closed")?;
+                let properties = WriterProperties::builder().set_compression(Compression::SNAPPY).build();
+                self.writer = Some(ArrowWriter::try_new(output, batch.schema(), Some(properties))?);
+            }
+            self.writer.as_mut().unwrap().write(&batch)?;
+        }
+        Ok(())
+    }
+
+    pub fn write_subsets(&mut self, subsets: &[Vec<DataValue>]) -> Result<(), Box<dyn Error>> {
+        let batches = self.builder.push_subsets(subsets)?;
+        self.write_batches(batches)
+    }
+
+    // Writes the last batch and the Parquet footer
+    pub fn close(mut self) -> Result<(), Box<dyn Error>> {
+        if let Some(batch) = self.builder.finish()? {
+            self.write_batches(vec![batch])?;
+        }
+        if let Some(writer) = self.writer.take() {
+            writer.close()?;
+        }
+        Ok(())
+    }
+}
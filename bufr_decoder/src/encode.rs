@@ -0,0 +1,330 @@
+use std::error::Error;
+use std::ops::Range;
+
+use crate::error::BufrError;
+use crate::expand::{self, Node, DEFAULT_MAX_DEPTH, DEFAULT_MAX_NODES};
+use crate::tables::TableSet;
+use crate::units::Unit;
+use crate::{check_width, table_b_field, DataValue, Header};
+
+// One element value, in transmission order. Delayed replication factors are
+// values too, as in the subsets returned by the decoder.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Number(f64),
+    Text(String),
+    Missing,
+}
+
+impl From<&DataValue> for Value {
+    fn from(value: &DataValue) -> Self {
+        match (&value.text, value.value) {
+            (Some(text), _) => Value::Text(text.clone()),
+            (None, Some(number)) => Value::Number(number),
+            (None, None) => Value::Missing,
+        }
+    }
+}
+
+// Big-endian bit packing
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    bits: u64,
+}
+
+impl BitWriter {
+    fn put(&mut self, value: u64, width: u32) {
+        for i in (0..width).rev() {
+            if self.bits.is_multiple_of(8) {
+                self.bytes.push(0);
+            }
+            if (value >> i) & 1 == 1 {
+                *self.bytes.last_mut().unwrap() |= 0x80 >> (self.bits % 8);
+            }
+            self.bits += 1;
+        }
+    }
+
+    // Bits `bits` of `source`, numbered from its first octet
+    fn copy(&mut self, source: &[u8], bits: Range<u64>) {
+        for bit in bits {
+            let octet = source[(bit / 8) as usize];
+            self.put(((octet >> (7 - bit % 8)) & 1) as u64, 1);
+        }
+    }
+
+    // Completed octets, zero padded to an even number if `even`
+    fn finish(mut self, even: bool) -> Vec<u8> {
+        if even && !self.bytes.len().is_multiple_of(2) {
+            self.bytes.push(0);
+        }
+        self.bytes
+    }
+}
+
+fn put_bytes(out: &mut Vec<u8>, value: u32, size: usize) {
+    out.extend(&value.to_be_bytes()[4 - size..]);
+}
+
+// Table C operators in force, as in the decoder
+#[derive(Default)]
+struct Operators {
+    width_plus: i32,
+    scale_plus: i32,
+    new_width: u32,
+}
+
+struct SubsetEncoder<'a> {
+    tables: &'a TableSet,
+    values: &'a [Value],
+    next: usize,
+    operators: Operators,
+    data: &'a mut BitWriter,
+}
+
+impl SubsetEncoder<'_> {
+    fn next_value(&mut self, descriptor: &str) -> Result<&Value, Box<dyn Error>> {
+        let value = self.values.get(self.next).ok_or_else(|| format!("No value left for {}", descriptor))?;
+        self.next += 1;
+        Ok(value)
+    }
+
+    // Writes the next value as `descriptor`, returns it when numeric
+    fn element(&mut self, descriptor: &str) -> Result<Option<f64>, Box<dyn Error>> {
+        let entry = self.tables.entry_b(descriptor).ok_or_else(|| format!("{} not found in Table B", descriptor))?;
+        let mut width: u32 = table_b_field(entry, descriptor, "Data_width_bits")?;
+        if self.operators.new_width != 0 {
+            width = self.operators.new_width;
+        }
+        width = width.saturating_add_signed(self.operators.width_plus);
+        let scale = table_b_field::<i32>(entry, descriptor, "Scale")? + self.operators.scale_plus;
+        let reference: i64 = table_b_field(entry, descriptor, "Ref_Val")?;
+        let character = Unit::parse(entry.get("Unit").map(String::as_str).unwrap_or("")).is_character();
+        check_width(descriptor, width, character, self.operators.width_plus, self.operators.new_width)?;
+
+        let value = self.next_value(descriptor)?.clone();
+        let number = match (&value, character) {
+            (Value::Text(text), true) => {
+                let size = width as usize / 8;
+                if text.len() > size {
+                    return Err(From::from(format!("{:?} longer than the {} characters of {}", text, size, descriptor)));
+                }
+                for byte in text.bytes().chain(std::iter::repeat(b' ')).take(size) {
+                    self.data.put(byte as u64, 8);
+                }
+                None
+            }
+            (Value::Missing, true) => {
+                for _ in 0..width / 8 {
+                    self.data.put(0xff, 8);
+                }
+                None
+            }
+            (Value::Missing, false) => {
+                if descriptor.starts_with("0-31-") {
+                    return Err(From::from(format!("Missing replication factor {}", descriptor)));
+                }
+                self.data.put(u64::MAX, width);
+                None
+            }
+            (Value::Number(number), false) => {
+                let raw = (number * 10f64.powi(scale)).round() as i64 - reference;
+                // all bits set is reserved for missing values, except for class 31
+                let max = (1i64 << width) - if descriptor.starts_with("0-31-") { 1 } else { 2 };
+                if raw < 0 || raw > max {
+                    return Err(From::from(format!("{} out of range for {} ({} bits, scale {}, reference {})", number, descriptor, width, scale, reference)));
+                }
+                self.data.put(raw as u64, width);
+                Some(*number)
+            }
+            (_, true) => return Err(From::from(format!("{} expects a character string", descriptor))),
+            (Value::Text(text), false) => return Err(From::from(format!("{} expects a number, not {:?}", descriptor, text))),
+        };
+        Ok(number)
+    }
+
+    fn operator(&mut self, descriptor: &str) -> Result<(), Box<dyn Error>> {
+        let (_, x, y) = expand::fxy(descriptor).ok_or_else(|| format!("Malformed operator {}", descriptor))?;
+        let y = y as i32;
+        match x {
+            1 => self.operators.width_plus = if y == 0 { 0 } else { y - 128 },
+            2 => self.operators.scale_plus = if y == 0 { 0 } else { y - 128 },
+            8 => self.operators.new_width = 8 * y as u32,
+            _ => return Err(From::from(format!("Operator {} not supported by the encoder", descriptor))),
+        }
+        Ok(())
+    }
+
+    fn nodes(&mut self, nodes: &[Node]) -> Result<(), Box<dyn Error>> {
+        for node in nodes {
+            match node {
+                Node::Element(descriptor) => {
+                    self.element(descriptor)?;
+                }
+                Node::Sequence { children, .. } => self.nodes(children)?,
+                Node::Replication { count, factor, children, .. } => {
+                    let count = match factor {
+                        Some(factor) => self.element(factor)?.unwrap_or(0.0) as u32,
+                        None => *count,
+                    };
+                    for _ in 0..count {
+                        self.nodes(children)?;
+                    }
+                }
+                Node::Operator(descriptor) => self.operator(descriptor)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+fn section1(header: &Header) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut section = Vec::new();
+    let flags = if header.sect2 { 0x80 } else { 0 };
+    match header.edition {
+        3 => {
+            put_bytes(&mut section, 18, 3);
+            for byte in [
+                header.master_table,
+                header.sub_centre,
+                header.centre,
+                header.update_sequence,
+                flags,
+                header.category,
+                header.subcategory,
+                header.master_table_version,
+                header.local_table_version,
+                header.full_year() % 100,
+                header.month,
+                header.day,
+                header.hour,
+                header.minute,
+                0,
+            ] {
+                put_bytes(&mut section, byte, 1);
+            }
+        }
+        4 => {
+            put_bytes(&mut section, 22, 3);
+            put_bytes(&mut section, header.master_table, 1);
+            put_bytes(&mut section, header.centre, 2);
+            put_bytes(&mut section, header.sub_centre, 2);
+            for byte in [header.update_sequence, flags, header.category, header.subcategory, header.local_subcategory, header.master_table_version, header.local_table_version] {
+                put_bytes(&mut section, byte, 1);
+            }
+            put_bytes(&mut section, header.full_year(), 2);
+            for byte in [header.month, header.day, header.hour, header.minute, header.second] {
+                put_bytes(&mut section, byte, 1);
+            }
+        }
+        edition => return Err(From::from(format!("Cannot encode BUFR edition {}", edition))),
+    }
+    Ok(section)
+}
+
+fn section3(header: &Header, number_of_subsets: usize) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut descriptors = Vec::with_capacity(2 * header.descriptors.len());
+    for descriptor in &header.descriptors {
+        let (f, x, y) = expand::fxy(descriptor).filter(|(f, x, y)| *f < 4 && *x < 64 && *y < 256).ok_or_else(|| format!("Malformed descriptor {:?}", descriptor))?;
+        descriptors.push((f << 6) | x);
+        descriptors.push(y as u8);
+    }
+    // edition 3 sections have an even number of octets
+    let padding = if header.edition < 4 { (7 + descriptors.len()) % 2 } else { 0 };
+    let mut section = Vec::new();
+    put_bytes(&mut section, (7 + descriptors.len() + padding) as u32, 3);
+    section.push(0);
+    put_bytes(&mut section, number_of_subsets as u32, 2);
+    section.push(if header.observed { 0x80 } else { 0 });
+    section.extend(descriptors);
+    section.extend(std::iter::repeat_n(0, padding));
+    Ok(section)
+}
+
+// Edition 3 or 4 message of `subsets`, uncompressed and without Section 2. Section 1 and the
+// descriptors come from `header`, the lengths and number of subsets are computed.
+pub fn encode_message(header: &Header, tables: &TableSet, subsets: &[Vec<Value>]) -> Result<Vec<u8>, Box<dyn Error>> {
+    if header.compressed {
+        return Err(From::from("Compressed data is not supported by the encoder"));
+    }
+    if header.sect2 {
+        return Err(From::from("Section 2 is not supported by the encoder"));
+    }
+    if subsets.len() > u16::MAX as usize {
+        return Err(From::from(format!("Too many subsets ({})", subsets.len())));
+    }
+    let section1 = section1(header)?;
+    let section3 = section3(header, subsets.len())?;
+
+    let tree = expand::expand(&header.descriptors, &tables.tables_d(), DEFAULT_MAX_DEPTH, DEFAULT_MAX_NODES)?;
+    let mut data = BitWriter::default();
+    for (index, values) in subsets.iter().enumerate() {
+        let mut encoder = SubsetEncoder { tables, values, next: 0, operators: Operators::default(), data: &mut data };
+        // table errors are returned as they are, the others tell the subset
+        encoder.nodes(&tree).map_err(|e| match e.downcast::<BufrError>() {
+            Ok(e) => e as Box<dyn Error>,
+            Err(e) => From::from(format!("Subset {} : {}", index + 1, e)),
+        })?;
+        if encoder.next < values.len() {
+            return Err(From::from(format!("Subset {} : {} value(s) left over", index + 1, values.len() - encoder.next)));
+        }
+    }
+    let data = data.finish(header.edition < 4);
+    if data.len() + 4 >= 1 << 24 {
+        return Err(From::from("Section 4 longer than 16 MiB"));
+    }
+
+    let total_length = 8 + section1.len() + section3.len() + 4 + data.len() + 4;
+    let mut message = b"BUFR".to_vec();
+    put_bytes(&mut message, total_length as u32, 3);
+    message.push(header.edition as u8);
+    message.extend(section1);
+    message.extend(section3);
+    put_bytes(&mut message, (data.len() + 4) as u32, 3);
+    message.push(0);
+    message.extend(data);
+    message.extend(b"7777");
+    Ok(message)
+}
+
+fn length_at(message: &[u8], offset: usize) -> Result<usize, Box<dyn Error>> {
+    let octets = message.get(offset..offset + 3).ok_or("Message shorter than its sections")?;
+    Ok((octets[0] as usize) << 16 | (octets[1] as usize) << 8 | octets[2] as usize)
+}
+
+// Copy of the uncompressed `message` keeping only the subsets `keep` (from 0, in this order).
+// Sections 0 to 2 are copied, Section 3 gets the new number of subsets and Section 4 the
+// bits `subset_bits` (BufrDecoder::subset_bits) of the kept subsets.
+pub fn extract_subsets(message: &[u8], header: &Header, subset_bits: &[Range<u64>], keep: &[usize]) -> Result<Vec<u8>, Box<dyn Error>> {
+    if header.compressed {
+        return Err(From::from("Subsets can only be extracted from uncompressed data"));
+    }
+    let start_3 = 8 + length_at(message, 8)? + if header.sect2 { length_at(message, 8 + length_at(message, 8)?)? } else { 0 };
+    let start_4 = start_3 + length_at(message, start_3)?;
+    if start_4 < start_3 + 7 {
+        return Err(From::from("Section 3 shorter than 7 octets"));
+    }
+    let length_4 = length_at(message, start_4)?;
+    let section4 = message.get(start_4..start_4 + length_4).ok_or("Message shorter than its sections")?;
+
+    let mut data = BitWriter::default();
+    for index in keep {
+        let bits = subset_bits.get(*index).ok_or_else(|| format!("No subset {}", index + 1))?;
+        if bits.end > length_4 as u64 * 8 {
+            return Err(From::from(format!("Subset {} ends after Section 4", index + 1)));
+        }
+        data.copy(section4, bits.clone());
+    }
+    let data = data.finish(header.edition < 4);
+
+    let mut extracted = message[..start_4].to_vec();
+    extracted[start_3 + 4..start_3 + 6].copy_from_slice(&(keep.len() as u16).to_be_bytes());
+    put_bytes(&mut extracted, (data.len() + 4) as u32, 3);
+    extracted.push(0);
+    extracted.extend(data);
+    extracted.extend(b"7777");
+    let total_length = extracted.len() as u32;
+    extracted[4..7].copy_from_slice(&total_length.to_be_bytes()[1..]);
+    Ok(extracted)
+}
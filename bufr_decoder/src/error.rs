@@ -0,0 +1,219 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+use crate::expand::ExpandError;
+use crate::options::Limit;
+
+// Where a decoding error happened, each field filled in when known
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Location {
+    pub message: Option<usize>,    // from 1, counted by the decoder
+    pub byte_offset: Option<u64>,  // in the input
+    pub bit_offset: Option<u64>,   // from the start of Section 4
+    pub path: Vec<String>,         // sequences and replications down to the descriptor being decoded
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(message) = self.message {
+            parts.push(format!("message {}", message));
+        }
+        if let Some(byte_offset) = self.byte_offset {
+            parts.push(format!("byte {}", byte_offset));
+        }
+        if let Some(bit_offset) = self.bit_offset {
+            parts.push(format!("bit {} of Section 4", bit_offset));
+        }
+        if !self.path.is_empty() {
+            parts.push(format!("in {}", self.path.join(" > ")));
+        }
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+#[derive(Debug)]
+pub enum BufrError {
+    // end of the input in the middle of a message
+    Truncated { at: Location },
+    BadMagic { found: [u8; 4], at: Location },
+    BadEndMarker { found: [u8; 4], at: Location },
+    UnsupportedEdition { edition: u32, at: Location },
+    InvalidLength { section: u8, length: u32, at: Location },
+    UnknownDescriptor { descriptor: String, at: Location },
+    // malformed, or of the wrong kind for what is asked
+    InvalidDescriptor { descriptor: String, reason: String, at: Location },
+    TableNotFound { path: PathBuf, reason: String, at: Location },
+    InvalidTableEntry { descriptor: String, field: &'static str, at: Location },
+    InvalidOperator { descriptor: String, at: Location },
+    InvalidReplication { descriptor: String, reason: String, at: Location },
+    // more than the padding left unread at the end of Section 4
+    LeftoverBits { bits: u64, at: Location },
+    // options::Limits
+    LimitExceeded { limit: Limit, value: u64, max: u64, at: Location },
+    // more values than Section 4 can reasonably hold
+    TooManyValues { max: u64, at: Location },
+    // compressed data increments wider than 32 bits
+    InvalidIncrement { descriptor: String, bits: u32, at: Location },
+    Expand { source: ExpandError, at: Location },
+    Io { source: io::Error, at: Location },
+}
+
+impl BufrError {
+    pub fn location(&self) -> &Location {
+        match self {
+            BufrError::Truncated { at }
+            | BufrError::BadMagic { at, .. }
+            | BufrError::BadEndMarker { at, .. }
+            | BufrError::UnsupportedEdition { at, .. }
+            | BufrError::InvalidLength { at, .. }
+            | BufrError::UnknownDescriptor { at, .. }
+            | BufrError::InvalidDescriptor { at, .. }
+            | BufrError::TableNotFound { at, .. }
+            | BufrError::InvalidTableEntry { at, .. }
+            | BufrError::InvalidOperator { at, .. }
+            | BufrError::InvalidReplication { at, .. }
+            | BufrError::InvalidIncrement { at, .. }
+            | BufrError::LeftoverBits { at, .. }
+            | BufrError::TooManyValues { at, .. }
+            | BufrError::LimitExceeded { at, .. }
+            | BufrError::Expand { at, .. }
+            | BufrError::Io { at, .. } => at,
+        }
+    }
+
+    pub fn location_mut(&mut self) -> &mut Location {
+        match self {
+            BufrError::Truncated { at }
+            | BufrError::BadMagic { at, .. }
+            | BufrError::BadEndMarker { at, .. }
+            | BufrError::UnsupportedEdition { at, .. }
+            | BufrError::InvalidLength { at, .. }
+            | BufrError::UnknownDescriptor { at, .. }
+            | BufrError::InvalidDescriptor { at, .. }
+            | BufrError::TableNotFound { at, .. }
+            | BufrError::InvalidTableEntry { at, .. }
+            | BufrError::InvalidOperator { at, .. }
+            | BufrError::InvalidReplication { at, .. }
+            | BufrError::InvalidIncrement { at, .. }
+            | BufrError::LeftoverBits { at, .. }
+            | BufrError::TooManyValues { at, .. }
+            | BufrError::LimitExceeded { at, .. }
+            | BufrError::Expand { at, .. }
+            | BufrError::Io { at, .. } => at,
+        }
+    }
+
+    // Fills in the parts of the location not known where the error was raised
+    pub(crate) fn at(mut self, location: Location) -> Self {
+        let at = self.location_mut();
+        at.message = at.message.or(location.message);
+        at.byte_offset = at.byte_offset.or(location.byte_offset);
+        at.bit_offset = at.bit_offset.or(location.bit_offset);
+        if at.path.is_empty() {
+            at.path = location.path;
+        }
+        self
+    }
+}
+
+impl fmt::Display for BufrError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BufrError::Truncated { .. } => write!(f, "End of file reached")?,
+            BufrError::BadMagic { found, .. } => write!(f, "Not a BUFR message, found {:?}", String::from_utf8_lossy(found))?,
+            BufrError::BadEndMarker { found, .. } => write!(f, "End of message \"7777\" expected, found {:?}", String::from_utf8_lossy(found))?,
+            BufrError::UnsupportedEdition { edition, .. } => write!(f, "Unsupported BUFR edition {}", edition)?,
+            BufrError::InvalidLength { section, length, .. } => write!(f, "Invalid length {} of section {}", length, section)?,
+            BufrError::UnknownDescriptor { descriptor, .. } => write!(f, "Descriptor {} not found in the tables", descriptor)?,
+            BufrError::InvalidDescriptor { descriptor, reason, .. } => write!(f, "Invalid descriptor {} : {}", descriptor, reason)?,
+            BufrError::TableNotFound { path, reason, .. } => write!(f, "Unable to read table {} : {}", path.display(), reason)?,
+            BufrError::InvalidTableEntry { descriptor, field, .. } => write!(f, "Invalid {} for {} in Table B", field, descriptor)?,
+            BufrError::InvalidOperator { descriptor, .. } => write!(f, "Invalid operator {}", descriptor)?,
+            BufrError::InvalidReplication { descriptor, reason, .. } => write!(f, "Invalid replication {} : {}", descriptor, reason)?,
+            BufrError::InvalidIncrement { descriptor, bits, .. } => write!(f, "Increments of {} bits for {}", bits, descriptor)?,
+            BufrError::LeftoverBits { bits, .. } => write!(f, "{} bits left over at the end of Section 4", bits)?,
+            BufrError::LimitExceeded { limit, value, max, .. } => write!(f, "{} {} exceeds the limit of {}", limit, value, max)?,
+            BufrError::TooManyValues { max, .. } => write!(f, "More than {} values decoded from Section 4", max)?,
+            BufrError::Expand { source, .. } => write!(f, "{}", source)?,
+            BufrError::Io { source, .. } => write!(f, "{}", source)?,
+        }
+        let at = self.location();
+        if *at != Location::default() {
+            write!(f, " ({})", at)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for BufrError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            BufrError::Expand { source, .. } => Some(source),
+            BufrError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+// Conditions that don't stop the decoding, kept on the decoded message (BufrDecoder::warnings)
+// and logged through the `log` facade
+#[derive(Debug)]
+pub enum Warning {
+    // the local tables are optional, their descriptors will be unknown
+    LocalTableNotFound { path: PathBuf, reason: String },
+    // CCITT IA5 element that isn't valid text, decoded as missing
+    NonPrintableText { descriptor: String, at: Location },
+    // error kept when decoding leniently (see options::Strictness)
+    Nonconformity(BufrError),
+}
+
+impl Warning {
+    pub fn location(&self) -> Option<&Location> {
+        match self {
+            Warning::LocalTableNotFound { .. } => None,
+            Warning::NonPrintableText { at, .. } => Some(at),
+            Warning::Nonconformity(error) => Some(error.location()),
+        }
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Warning::LocalTableNotFound { path, reason } => write!(f, "Unable to read local table {} : {}", path.display(), reason),
+            Warning::NonPrintableText { descriptor, at } => write!(f, "Non-printable CCITT IA5 data for {} ({})", descriptor, at),
+            Warning::Nonconformity(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl From<io::Error> for BufrError {
+    fn from(source: io::Error) -> Self {
+        if source.kind() == io::ErrorKind::UnexpectedEof {
+            BufrError::Truncated { at: Location::default() }
+        } else {
+            BufrError::Io { source, at: Location::default() }
+        }
+    }
+}
+
+// Limits::max_depth and max_descriptors are enforced while expanding
+impl From<ExpandError> for BufrError {
+    fn from(source: ExpandError) -> Self {
+        match source {
+            ExpandError::TooDeep { path, max_depth } => BufrError::LimitExceeded {
+                limit: Limit::Depth,
+                value: path.len() as u64,
+                max: max_depth as u64,
+                at: Location { path, ..Location::default() },
+            },
+            ExpandError::TooLarge { max_nodes } => {
+                BufrError::LimitExceeded { limit: Limit::Descriptors, value: max_nodes as u64 + 1, max: max_nodes as u64, at: Location::default() }
+            }
+            source => BufrError::Expand { source, at: Location::default() },
+        }
+    }
+}
@@ -0,0 +1,301 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::tables::DicoD;
+
+// Maximum nesting of Table D sequences / replications accepted by default
+pub const DEFAULT_MAX_DEPTH: usize = 32;
+// Maximum number of nodes of an expansion tree accepted by default, replications counted once
+pub const DEFAULT_MAX_NODES: usize = 1 << 20;
+
+// Expansion tree of a descriptor list, in transmission order
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node {
+    // F = 0 : element descriptor (Table B)
+    Element(String),
+    // F = 1 : replication of `children`, `count` times or as many times as the
+    // delayed replication `factor` element read from the data says
+    Replication { descriptor: String, count: u32, factor: Option<String>, children: Vec<Node> },
+    // F = 2 : operator descriptor (Table C)
+    Operator(String),
+    // F = 3 : Table D sequence
+    Sequence { descriptor: String, children: Vec<Node> },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExpandError {
+    UnknownSequence(String),
+    Cycle(Vec<String>),
+    TooDeep { path: Vec<String>, max_depth: usize },
+    ReplicationOutOfRange { descriptor: String, expected: usize, found: usize },
+    BadDescriptor(String),
+    TooLarge { max_nodes: usize },
+}
+
+impl fmt::Display for ExpandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExpandError::UnknownSequence(desc) => write!(f, "sequence {} not found in Table D", desc),
+            ExpandError::Cycle(path) => write!(f, "recursive Table D sequence : {}", path.join(" > ")),
+            ExpandError::TooDeep { path, max_depth } => write!(f, "descriptor nesting deeper than {} : {}", max_depth, path.join(" > ")),
+            ExpandError::ReplicationOutOfRange { descriptor, expected, found } => {
+                write!(f, "replication {} covers {} descriptors but only {} follow", descriptor, expected, found)
+            }
+            ExpandError::BadDescriptor(desc) => write!(f, "malformed descriptor {:?}", desc),
+            ExpandError::TooLarge { max_nodes } => write!(f, "expansion larger than {} descriptors", max_nodes),
+        }
+    }
+}
+
+impl Error for ExpandError {}
+
+// Split a "F-XX-YYY" descriptor into its numeric parts
+pub fn fxy(descriptor: &str) -> Option<(u8, u8, u16)> {
+    let mut parts = descriptor.split('-');
+    let f = parts.next()?.parse().ok()?;
+    let x = parts.next()?.parse().ok()?;
+    let y = parts.next()?.parse().ok()?;
+    if parts.next().is_some() {
+        return None;
+    }
+    Some((f, x, y))
+}
+
+// Expands descriptor lists against a set of D tables (looked up in order, local first)
+// and keeps the result per unexpanded list, so a file with thousands of identical
+// messages only expands its descriptors once
+pub struct Expander {
+    pub max_depth: usize,
+    pub max_nodes: usize,
+    cache: HashMap<Vec<String>, Rc<Vec<Node>>>,
+}
+
+impl Default for Expander {
+    fn default() -> Self {
+        Expander::new(DEFAULT_MAX_DEPTH, DEFAULT_MAX_NODES)
+    }
+}
+
+impl Expander {
+    pub fn new(max_depth: usize, max_nodes: usize) -> Self {
+        Expander { max_depth, max_nodes, cache: HashMap::new() }
+    }
+
+    // To be called whenever the D tables change
+    pub fn clear(&mut self) {
+        self.cache.clear();
+    }
+
+    pub fn expand_cached(&mut self, descriptors: &[String], tables_d: &[&DicoD]) -> Result<Rc<Vec<Node>>, ExpandError> {
+        if let Some(tree) = self.cache.get(descriptors) {
+            return Ok(Rc::clone(tree));
+        }
+        let tree = Rc::new(expand(descriptors, tables_d, self.max_depth, self.max_nodes)?);
+        self.cache.insert(descriptors.to_vec(), Rc::clone(&tree));
+        Ok(tree)
+    }
+}
+
+pub fn expand(descriptors: &[String], tables_d: &[&DicoD], max_depth: usize, max_nodes: usize) -> Result<Vec<Node>, ExpandError> {
+    let mut path = Vec::new();
+    expand_list(descriptors, tables_d, max_depth, &mut path, &mut (0, max_nodes))
+}
+
+// `count` : (nodes expanded so far, maximum)
+fn expand_list(descriptors: &[String], tables_d: &[&DicoD], max_depth: usize, path: &mut Vec<String>, count: &mut (usize, usize)) -> Result<Vec<Node>, ExpandError> {
+    let mut nodes = Vec::with_capacity(descriptors.len());
+    let mut index = 0;
+    while index < descriptors.len() {
+        count.0 += 1;
+        if count.0 > count.1 {
+            return Err(ExpandError::TooLarge { max_nodes: count.1 });
+        }
+        let descriptor = &descriptors[index];
+        let (f, x, y) = fxy(descriptor).ok_or_else(|| ExpandError::BadDescriptor(descriptor.clone()))?;
+        index += 1;
+        match f {
+            0 => nodes.push(Node::Element(descriptor.clone())),
+            2 => nodes.push(Node::Operator(descriptor.clone())),
+            1 => {
+                // delayed replication : the factor descriptor follows and is not counted in X
+                let factor = if y == 0 {
+                    let factor = descriptors.get(index).ok_or_else(|| ExpandError::ReplicationOutOfRange {
+                        descriptor: descriptor.clone(),
+                        expected: x as usize + 1,
+                        found: 0,
+                    })?;
+                    index += 1;
+                    Some(factor.clone())
+                } else {
+                    None
+                };
+                let end = index + x as usize;
+                if end > descriptors.len() {
+                    return Err(ExpandError::ReplicationOutOfRange {
+                        descriptor: descriptor.clone(),
+                        expected: x as usize,
+                        found: descriptors.len() - index,
+                    });
+                }
+                path.push(descriptor.clone());
+                if path.len() > max_depth {
+                    return Err(ExpandError::TooDeep { path: path.clone(), max_depth });
+                }
+                let children = expand_list(&descriptors[index..end], tables_d, max_depth, path, count)?;
+                path.pop();
+                nodes.push(Node::Replication { descriptor: descriptor.clone(), count: y as u32, factor, children });
+                index = end;
+            }
+            _ => {
+                if path.contains(descriptor) {
+                    let mut cycle = path.clone();
+                    cycle.push(descriptor.clone());
+                    return Err(ExpandError::Cycle(cycle));
+                }
+                let elements = tables_d
+                    .iter()
+                    .find_map(|table| table.get(descriptor))
+                    .ok_or_else(|| ExpandError::UnknownSequence(descriptor.clone()))?;
+                path.push(descriptor.clone());
+                if path.len() > max_depth {
+                    return Err(ExpandError::TooDeep { path: path.clone(), max_depth });
+                }
+                let children = expand_list(elements, tables_d, max_depth, path, count)?;
+                path.pop();
+                nodes.push(Node::Sequence { descriptor: descriptor.clone(), children });
+            }
+        }
+    }
+    Ok(nodes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(descriptors: &[&str]) -> Vec<String> {
+        descriptors.iter().map(|d| d.to_string()).collect()
+    }
+
+    fn table(sequences: &[(&str, &[&str])]) -> DicoD {
+        sequences.iter().map(|(key, elements)| (key.to_string(), list(elements))).collect()
+    }
+
+    fn element(descriptor: &str) -> Node {
+        Node::Element(descriptor.to_string())
+    }
+
+    #[test]
+    fn transmission_order() {
+        let d = table(&[("3-01-001", &["0-01-001", "0-01-002"]), ("3-01-002", &["0-04-001", "3-01-001", "2-01-130", "0-12-101"])]);
+        let tree = expand(&list(&["0-05-001", "3-01-002", "0-06-001"]), &[&d], DEFAULT_MAX_DEPTH, DEFAULT_MAX_NODES).unwrap();
+        let sequence = |descriptor: &str, children| Node::Sequence { descriptor: descriptor.to_string(), children };
+        assert_eq!(
+            tree,
+            [
+                element("0-05-001"),
+                sequence(
+                    "3-01-002",
+                    vec![
+                        element("0-04-001"),
+                        sequence("3-01-001", vec![element("0-01-001"), element("0-01-002")]),
+                        Node::Operator("2-01-130".to_string()),
+                        element("0-12-101"),
+                    ]
+                ),
+                element("0-06-001"),
+            ]
+        );
+    }
+
+    #[test]
+    fn replications() {
+        // 1-02-000 : delayed, the factor follows and isn't one of the 2 replicated descriptors
+        let tree = expand(&list(&["1-02-000", "0-31-001", "0-07-004", "0-12-101", "1-01-003", "0-01-001", "0-02-001"]), &[], 2, 100).unwrap();
+        assert_eq!(
+            tree,
+            [
+                Node::Replication {
+                    descriptor: "1-02-000".to_string(),
+                    count: 0,
+                    factor: Some("0-31-001".to_string()),
+                    children: vec![element("0-07-004"), element("0-12-101")],
+                },
+                Node::Replication { descriptor: "1-01-003".to_string(), count: 3, factor: None, children: vec![element("0-01-001")] },
+                element("0-02-001"),
+            ]
+        );
+    }
+
+    #[test]
+    fn replication_out_of_range() {
+        let error = expand(&list(&["1-03-002", "0-01-001", "0-01-002"]), &[], DEFAULT_MAX_DEPTH, DEFAULT_MAX_NODES).unwrap_err();
+        assert_eq!(error, ExpandError::ReplicationOutOfRange { descriptor: "1-03-002".to_string(), expected: 3, found: 2 });
+        let error = expand(&list(&["0-01-001", "1-01-000"]), &[], DEFAULT_MAX_DEPTH, DEFAULT_MAX_NODES).unwrap_err();
+        assert_eq!(error, ExpandError::ReplicationOutOfRange { descriptor: "1-01-000".to_string(), expected: 2, found: 0 });
+        let error = expand(&list(&["1-01-000", "0-31-001"]), &[], DEFAULT_MAX_DEPTH, DEFAULT_MAX_NODES).unwrap_err();
+        assert_eq!(error, ExpandError::ReplicationOutOfRange { descriptor: "1-01-000".to_string(), expected: 1, found: 0 });
+    }
+
+    #[test]
+    fn cycles() {
+        let d = table(&[("3-01-001", &["0-01-001", "3-01-002"]), ("3-01-002", &["3-01-001"])]);
+        let error = expand(&list(&["3-01-001"]), &[&d], DEFAULT_MAX_DEPTH, DEFAULT_MAX_NODES).unwrap_err();
+        assert_eq!(error, ExpandError::Cycle(list(&["3-01-001", "3-01-002", "3-01-001"])));
+        // the same sequence twice side by side isn't a cycle
+        let d = table(&[("3-01-001", &["0-01-001"]), ("3-01-002", &["3-01-001", "3-01-001"])]);
+        assert!(expand(&list(&["3-01-002"]), &[&d], DEFAULT_MAX_DEPTH, DEFAULT_MAX_NODES).is_ok());
+    }
+
+    #[test]
+    fn limits() {
+        let d = table(&[("3-01-001", &["3-01-002"]), ("3-01-002", &["1-01-002", "0-01-001"])]);
+        assert!(expand(&list(&["3-01-001"]), &[&d], 3, DEFAULT_MAX_NODES).is_ok());
+        let error = expand(&list(&["3-01-001"]), &[&d], 2, DEFAULT_MAX_NODES).unwrap_err();
+        assert_eq!(error, ExpandError::TooDeep { path: list(&["3-01-001", "3-01-002", "1-01-002"]), max_depth: 2 });
+        // 3-01-001, 3-01-002, 1-01-002 and 0-01-001 : the replicated element counted once
+        assert!(expand(&list(&["3-01-001"]), &[&d], 3, 4).is_ok());
+        assert_eq!(expand(&list(&["3-01-001"]), &[&d], 3, 3).unwrap_err(), ExpandError::TooLarge { max_nodes: 3 });
+    }
+
+    #[test]
+    fn bad_and_unknown_descriptors() {
+        let error = expand(&list(&["0-01"]), &[], DEFAULT_MAX_DEPTH, DEFAULT_MAX_NODES).unwrap_err();
+        assert_eq!(error, ExpandError::BadDescriptor("0-01".to_string()));
+        let error = expand(&list(&["3-01-001"]), &[&DicoD::new()], DEFAULT_MAX_DEPTH, DEFAULT_MAX_NODES).unwrap_err();
+        assert_eq!(error, ExpandError::UnknownSequence("3-01-001".to_string()));
+    }
+
+    #[test]
+    fn local_tables_first() {
+        let local = table(&[("3-01-001", &["0-01-002"])]);
+        let master = table(&[("3-01-001", &["0-01-001"]), ("3-01-002", &["0-01-003"])]);
+        let tree = expand(&list(&["3-01-001", "3-01-002"]), &[&local, &master], DEFAULT_MAX_DEPTH, DEFAULT_MAX_NODES).unwrap();
+        assert_eq!(
+            tree,
+            [
+                Node::Sequence { descriptor: "3-01-001".to_string(), children: vec![element("0-01-002")] },
+                Node::Sequence { descriptor: "3-01-002".to_string(), children: vec![element("0-01-003")] },
+            ]
+        );
+    }
+
+    #[test]
+    fn cache() {
+        let d = table(&[("3-01-001", &["0-01-001"])]);
+        let mut expander = Expander::default();
+        let first = expander.expand_cached(&list(&["3-01-001"]), &[&d]).unwrap();
+        let second = expander.expand_cached(&list(&["3-01-001"]), &[&d]).unwrap();
+        assert!(Rc::ptr_eq(&first, &second));
+        let other = expander.expand_cached(&list(&["3-01-001", "0-01-002"]), &[&d]).unwrap();
+        assert!(!Rc::ptr_eq(&first, &other));
+        // the cache is per descriptor list : changed tables are only seen after clear()
+        let changed = table(&[("3-01-001", &["0-01-009"])]);
+        assert!(Rc::ptr_eq(&first, &expander.expand_cached(&list(&["3-01-001"]), &[&changed]).unwrap()));
+        expander.clear();
+        let tree = expander.expand_cached(&list(&["3-01-001"]), &[&changed]).unwrap();
+        assert_eq!(*tree, [Node::Sequence { descriptor: "3-01-001".to_string(), children: vec![element("0-01-009")] }]);
+    }
+}
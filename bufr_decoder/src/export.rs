@@ -0,0 +1,158 @@
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::io::Write;
+
+use crate::tables::canonical_fxy;
+use crate::units::Unit;
+use crate::DataValue;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsvOptions {
+    pub delimiter: u8,                // b',' for CSV, b'\t' for TSV
+    pub columns: Option<Vec<String>>, // FXY descriptors to keep, in this order
+    pub units_in_header: bool,        // "Temperature/air temperature (K)"
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        CsvOptions { delimiter: b',', columns: None, units_in_header: false }
+    }
+}
+
+// (descriptor, occurrence in the subset starting at 1)
+pub(crate) type ColumnKey = (String, usize);
+
+pub(crate) struct Column {
+    pub key: ColumnKey,
+    pub description: String,
+    pub unit: Unit,
+}
+
+// One row per subset, streamed message after message. The columns are those of
+// the first message written : elements only present in later messages are dropped
+// (with a warning) and the ones they lack are left empty.
+pub struct CsvExporter<W: Write> {
+    writer: csv::Writer<W>,
+    options: CsvOptions,
+    columns: Option<Vec<Column>>,
+    message: usize,
+}
+
+pub(crate) fn keyed(subset: &[DataValue]) -> Vec<(ColumnKey, &DataValue)> {
+    let mut occurrences: HashMap<&str, usize> = HashMap::new();
+    subset
+        .iter()
+        .map(|value| {
+            let occurrence = occurrences.entry(&value.descriptor).or_insert(0);
+            *occurrence += 1;
+            ((value.descriptor.clone(), *occurrence), value)
+        })
+        .collect()
+}
+
+fn format_value(value: &DataValue) -> String {
+    match (&value.text, value.value) {
+        (Some(text), _) => text.clone(),
+        (None, Some(number)) => number.to_string(),
+        (None, None) => String::new(),
+    }
+}
+
+// Columns of the elements of `subsets` in order of appearance, restricted to `selection` if any
+pub(crate) fn columns_of(subsets: &[Vec<DataValue>], selection: Option<&[String]>) -> Vec<Column> {
+    let mut columns: Vec<Column> = Vec::new();
+    let mut seen: HashSet<ColumnKey> = HashSet::new();
+    for subset in subsets {
+        for (key, value) in keyed(subset) {
+            if seen.insert(key.clone()) {
+                columns.push(Column { key, description: value.description.clone(), unit: value.unit.clone() });
+            }
+        }
+    }
+    if let Some(selection) = selection {
+        let mut selected = Vec::new();
+        for descriptor in selection {
+            let descriptor = canonical_fxy(descriptor).unwrap_or_else(|| descriptor.clone());
+            let mut matching: Vec<Column> = Vec::new();
+            let mut rest = Vec::new();
+            for column in columns {
+                if column.key.0 == descriptor {
+                    matching.push(column);
+                } else {
+                    rest.push(column);
+                }
+            }
+            matching.sort_by_key(|c| c.key.1);
+            selected.extend(matching);
+            columns = rest;
+        }
+        columns = selected;
+    }
+    columns
+}
+
+// Table B description, numbered when the element occurs several times in a subset
+pub(crate) fn column_names(columns: &[Column], units_in_header: bool) -> Vec<String> {
+    let mut repeated: HashMap<&str, usize> = HashMap::new();
+    let mut descriptors_of: HashMap<&str, Vec<&str>> = HashMap::new();
+    for column in columns {
+        let max = repeated.entry(&column.key.0).or_insert(0);
+        *max = (*max).max(column.key.1);
+        let descriptors = descriptors_of.entry(&column.description).or_default();
+        if !descriptors.contains(&column.key.0.as_str()) {
+            descriptors.push(&column.key.0);
+        }
+    }
+    let mut header = Vec::with_capacity(columns.len());
+    for column in columns {
+        let mut name = column.description.clone();
+        if descriptors_of[column.description.as_str()].len() > 1 {
+            // two descriptors sharing a description (local and master tables)
+            name = format!("{} [{}]", name, column.key.0);
+        }
+        if repeated[column.key.0.as_str()] > 1 {
+            name = format!("{} #{}", name, column.key.1);
+        }
+        if units_in_header && !column.unit.is_character() {
+            name = format!("{} ({})", name, column.unit);
+        }
+        header.push(name);
+    }
+    header
+}
+
+impl<W: Write> CsvExporter<W> {
+    pub fn new(writer: W, options: CsvOptions) -> Self {
+        let writer = csv::WriterBuilder::new().delimiter(options.delimiter).from_writer(writer);
+        CsvExporter { writer, options, columns: None, message: 0 }
+    }
+
+    // Rows of every subset of one message
+    pub fn write_subsets(&mut self, subsets: &[Vec<DataValue>]) -> Result<(), Box<dyn Error>> {
+        self.message += 1;
+        if self.columns.is_none() {
+            let columns = columns_of(subsets, self.options.columns.as_deref());
+            let mut header = vec!["message".to_string(), "subset".to_string()];
+            header.extend(column_names(&columns, self.options.units_in_header));
+            self.writer.write_record(&header)?;
+            self.columns = Some(columns);
+        }
+        let columns = self.columns.as_ref().unwrap();
+        let layout = columns_of(subsets, self.options.columns.as_deref());
+        if layout.iter().map(|c| &c.key).ne(columns.iter().map(|c| &c.key)) {
+            log::warn!("Message {} : elements differ from the columns of the first message, some are dropped or left empty", self.message);
+        }
+        for (index, subset) in subsets.iter().enumerate() {
+            let values: HashMap<ColumnKey, &DataValue> = keyed(subset).into_iter().collect();
+            let mut row = vec![self.message.to_string(), (index + 1).to_string()];
+            row.extend(columns.iter().map(|c| values.get(&c.key).map(|v| format_value(v)).unwrap_or_default()));
+            self.writer.write_record(&row)?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
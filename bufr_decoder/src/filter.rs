@@ -0,0 +1,248 @@
+use std::collections::HashSet;
+
+use crate::tables::canonical_fxy;
+use crate::Header;
+
+// Reference time of a message, or a bound of a filter on it. "YYYY", "YYYY-MM" ... up to
+// "YYYY-MM-DDTHH:MM:SS" cover a whole period, a bound is its first or last second.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ReferenceTime {
+    pub year: u32,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+}
+
+fn days_in_month(year: u32, month: u32) -> u32 {
+    match month {
+        2 if year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400)) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+impl ReferenceTime {
+    pub fn of(header: &Header) -> Self {
+        ReferenceTime { year: header.full_year(), month: header.month, day: header.day, hour: header.hour, minute: header.minute, second: header.second }
+    }
+
+    // First second of the period `text`
+    pub fn since(text: &str) -> Result<Self, String> {
+        Self::parse(text, false)
+    }
+
+    // Last second of the period `text`
+    pub fn until(text: &str) -> Result<Self, String> {
+        Self::parse(text, true)
+    }
+
+    fn parse(text: &str, end: bool) -> Result<Self, String> {
+        const SHAPE: &[u8] = b"dddd-dd-ddTdd:dd:dd";
+        let malformed = || format!("{:?} is not a time YYYY[-MM[-DD[THH[:MM[:SS]]]]]", text);
+        let bytes = text.as_bytes();
+        let well_formed = [4, 7, 10, 13, 16, 19].contains(&bytes.len())
+            && bytes.iter().zip(SHAPE).all(|(c, shape)| if *shape == b'd' { c.is_ascii_digit() } else { c == shape || (*shape == b'T' && *c == b' ') });
+        if !well_formed {
+            return Err(malformed());
+        }
+        let field = |start: usize, default: u32| text.get(start..start + 2).map_or(default, |f| f.parse().unwrap());
+        let year = text[..4].parse().unwrap();
+        let month = field(5, if end { 12 } else { 1 });
+        if !(1..=12).contains(&month) {
+            return Err(malformed());
+        }
+        let day = field(8, if end { days_in_month(year, month) } else { 1 });
+        let time = ReferenceTime { year, month, day, hour: field(11, if end { 23 } else { 0 }), minute: field(14, if end { 59 } else { 0 }), second: field(17, if end { 59 } else { 0 }) };
+        if day == 0 || day > days_in_month(year, month) || time.hour > 23 || time.minute > 59 || time.second > 59 {
+            return Err(malformed());
+        }
+        Ok(time)
+    }
+}
+
+// Criteria on Sections 0 to 3, None meaning any value. Both time bounds are inclusive.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HeaderFilter {
+    pub category: Option<u32>,
+    pub centre: Option<u32>,
+    pub sub_centre: Option<u32>,
+    pub master_table_version: Option<u32>,
+    pub local_table_version: Option<u32>,
+    pub since: Option<ReferenceTime>,
+    pub until: Option<ReferenceTime>,
+    pub min_subsets: Option<u32>,
+    pub max_subsets: Option<u32>,
+}
+
+impl HeaderFilter {
+    pub fn is_empty(&self) -> bool {
+        *self == HeaderFilter::default()
+    }
+
+    pub fn matches(&self, header: &Header) -> bool {
+        let same = |wanted: Option<u32>, value: u32| wanted.is_none_or(|w| w == value);
+        let time = ReferenceTime::of(header);
+        same(self.category, header.category)
+            && same(self.centre, header.centre)
+            && same(self.sub_centre, header.sub_centre)
+            && same(self.master_table_version, header.master_table_version)
+            && same(self.local_table_version, header.local_table_version)
+            && self.since.is_none_or(|since| time >= since)
+            && self.until.is_none_or(|until| time <= until)
+            && self.min_subsets.is_none_or(|min| header.number_of_subsets >= min)
+            && self.max_subsets.is_none_or(|max| header.number_of_subsets <= max)
+    }
+}
+
+// Elements to decode : FXY descriptors, or patterns on the descriptor or its Table B description
+// (case insensitive, '*' matching any run of characters, a substring without '*')
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ElementSelection {
+    pub descriptors: HashSet<String>,
+    pub patterns: Vec<String>,
+}
+
+// Two pointers : on a mismatch the last '*' absorbs one more character, no backtracking further
+fn glob(pattern: &[char], text: &[char]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    let mut star = None; // pattern position after the last '*', text position it matched up to
+    while t < text.len() {
+        if pattern.get(p) == Some(&'*') {
+            p += 1;
+            star = Some((p, t));
+        } else if pattern.get(p) == Some(&text[t]) {
+            p += 1;
+            t += 1;
+        } else if let Some((after, matched)) = star {
+            p = after;
+            t = matched + 1;
+            star = Some((after, t));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+impl ElementSelection {
+    // Items looking like "F-XX-YYY" are descriptors, the others patterns
+    pub fn new<S: AsRef<str>>(items: &[S]) -> Self {
+        let mut selection = ElementSelection::default();
+        for item in items {
+            let item = item.as_ref().trim();
+            // canonical, as the decoder names them : 0-1-1 selects 0-01-001
+            if let Some(descriptor) = canonical_fxy(item) {
+                selection.descriptors.insert(descriptor);
+            } else {
+                selection.patterns.push(item.to_lowercase());
+            }
+        }
+        selection
+    }
+
+    pub fn matches(&self, descriptor: &str, description: &str) -> bool {
+        if self.descriptors.contains(descriptor) {
+            return true;
+        }
+        let description = description.to_lowercase();
+        self.patterns.iter().any(|pattern| {
+            if pattern.contains('*') {
+                let pattern: Vec<char> = pattern.chars().collect();
+                [descriptor, description.as_str()].iter().any(|text| glob(&pattern, &text.chars().collect::<Vec<_>>()))
+            } else {
+                description.contains(pattern.as_str())
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(time: &str) -> Header {
+        let time = ReferenceTime::since(time).unwrap();
+        Header {
+            edition: 4,
+            centre: 85,
+            category: 0,
+            number_of_subsets: 3,
+            year: time.year,
+            month: time.month,
+            day: time.day,
+            hour: time.hour,
+            minute: time.minute,
+            second: time.second,
+            ..Header::default()
+        }
+    }
+
+    #[test]
+    fn periods() {
+        let since = ReferenceTime::since("2024-02").unwrap();
+        let until = ReferenceTime::until("2024-02").unwrap();
+        assert_eq!((since.month, since.day, since.hour, since.minute, since.second), (2, 1, 0, 0, 0));
+        assert_eq!((until.month, until.day, until.hour, until.minute, until.second), (2, 29, 23, 59, 59));
+        assert_eq!(ReferenceTime::until("2023-02").unwrap().day, 28);
+        assert_eq!(ReferenceTime::until("1900-02").unwrap().day, 28);
+        assert_eq!(ReferenceTime::until("2024").unwrap(), ReferenceTime::since("2024-12-31T23:59:59").unwrap());
+        assert_eq!(ReferenceTime::since("2024-12-28 12:15").unwrap(), ReferenceTime::since("2024-12-28T12:15:00").unwrap());
+    }
+
+    #[test]
+    fn malformed_times() {
+        for text in ["2024-1", "24", "2024-13", "2024-00", "2023-02-29", "2024-04-31", "2024-01-01T24", "2024-01-01T12:60", "2024-01-01T12:00:60", "2024-01-01T", "2024/01", "2024-01-01T12:00:00Z", ""] {
+            assert!(ReferenceTime::since(text).is_err(), "{:?}", text);
+            assert!(ReferenceTime::until(text).is_err(), "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn time_bounds_are_inclusive() {
+        let filter = HeaderFilter { since: ReferenceTime::since("2024-02-29T18").ok(), until: ReferenceTime::until("2024-03").ok(), ..HeaderFilter::default() };
+        assert!(!filter.matches(&header("2024-02-29T17:59:59")));
+        assert!(filter.matches(&header("2024-02-29T18:00:00")));
+        assert!(filter.matches(&header("2024-03-31T23:59:59")));
+        assert!(!filter.matches(&header("2024-04-01T00:00:00")));
+        // compared as times, not as strings
+        let filter = HeaderFilter { since: ReferenceTime::since("2024-10").ok(), ..HeaderFilter::default() };
+        assert!(!filter.matches(&header("2024-09-30T23:59:59")));
+        assert!(filter.matches(&header("2024-10-01")));
+    }
+
+    fn globbed(pattern: &str, text: &str) -> bool {
+        glob(&pattern.chars().collect::<Vec<_>>(), &text.chars().collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn wildcards() {
+        for (pattern, text) in [("*", ""), ("*", "wind"), ("wind*", "wind speed"), ("*speed", "wind speed"), ("w*d*d", "wind speed"), ("**a", "a"), ("0-11-*", "0-11-002")] {
+            assert!(globbed(pattern, text), "{:?} {:?}", pattern, text);
+        }
+        for (pattern, text) in [("", "a"), ("a", ""), ("wind", "wind speed"), ("*x*", "wind speed"), ("w*d*dd", "wind speed"), ("0-12-*", "0-11-002")] {
+            assert!(!globbed(pattern, text), "{:?} {:?}", pattern, text);
+        }
+    }
+
+    #[test]
+    fn pathological_pattern() {
+        // exponential with backtracking at every '*'
+        let text = "a".repeat(10_000);
+        assert!(!globbed("*a*a*a*a*a*a*a*a*a*a*b", &text));
+        assert!(globbed("*a*a*a*a*a*a*a*a*a*a*", &text));
+    }
+
+    #[test]
+    fn section_1_criteria() {
+        let message = header("2024-02-29T18:30");
+        assert!(HeaderFilter::default().matches(&message));
+        assert!(HeaderFilter { centre: Some(85), category: Some(0), ..HeaderFilter::default() }.matches(&message));
+        assert!(!HeaderFilter { centre: Some(98), ..HeaderFilter::default() }.matches(&message));
+        assert!(!HeaderFilter { centre: Some(85), category: Some(2), ..HeaderFilter::default() }.matches(&message));
+        assert!(HeaderFilter { min_subsets: Some(3), max_subsets: Some(3), ..HeaderFilter::default() }.matches(&message));
+        assert!(!HeaderFilter { min_subsets: Some(4), ..HeaderFilter::default() }.matches(&message));
+    }
+}
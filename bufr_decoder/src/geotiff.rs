@@ -0,0 +1,209 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::projection::{GridGeometry, ProjectionKind};
+use crate::radar::RadarImage;
+
+// TIFF field types
+const SHORT: u16 = 3;
+const LONG: u16 = 4;
+const ASCII: u16 = 2;
+const DOUBLE: u16 = 12;
+
+// GeoTIFF tags
+const MODEL_PIXEL_SCALE: u16 = 33550;
+const MODEL_TIEPOINT: u16 = 33922;
+const GEO_KEY_DIRECTORY: u16 = 34735;
+const GEO_DOUBLE_PARAMS: u16 = 34736;
+const GEO_ASCII_PARAMS: u16 = 34737;
+const GDAL_NODATA: u16 = 42113;
+
+// User-defined value of the GeoTIFF specification
+const USER_DEFINED: u16 = 32767;
+
+struct Entry {
+    tag: u16,
+    kind: u16,
+    count: u32,
+    bytes: Vec<u8>,
+}
+
+fn shorts(tag: u16, values: &[u16]) -> Entry {
+    Entry { tag, kind: SHORT, count: values.len() as u32, bytes: values.iter().flat_map(|v| v.to_le_bytes()).collect() }
+}
+
+fn long(tag: u16, value: u32) -> Entry {
+    Entry { tag, kind: LONG, count: 1, bytes: value.to_le_bytes().to_vec() }
+}
+
+fn doubles(tag: u16, values: &[f64]) -> Entry {
+    Entry { tag, kind: DOUBLE, count: values.len() as u32, bytes: values.iter().flat_map(|v| v.to_le_bytes()).collect() }
+}
+
+fn ascii(tag: u16, text: &str) -> Entry {
+    let mut bytes = text.as_bytes().to_vec();
+    bytes.push(0);
+    Entry { tag, kind: ASCII, count: bytes.len() as u32, bytes }
+}
+
+// GeoKeyDirectory with its double and ascii parameters
+#[derive(Default)]
+struct GeoKeys {
+    keys: Vec<[u16; 4]>,
+    doubles: Vec<f64>,
+    ascii: String,
+}
+
+impl GeoKeys {
+    fn short(&mut self, key: u16, value: u16) {
+        self.keys.push([key, 0, 1, value]);
+    }
+
+    fn double(&mut self, key: u16, value: f64) {
+        self.keys.push([key, GEO_DOUBLE_PARAMS, 1, self.doubles.len() as u16]);
+        self.doubles.push(value);
+    }
+
+    fn ascii(&mut self, key: u16, text: &str) {
+        self.keys.push([key, GEO_ASCII_PARAMS, text.len() as u16 + 1, self.ascii.len() as u16]);
+        self.ascii.push_str(text);
+        self.ascii.push('|');
+    }
+
+    fn directory(&mut self) -> Vec<u16> {
+        self.keys.sort_by_key(|k| k[0]);
+        let mut directory = vec![1, 1, 0, self.keys.len() as u16];
+        directory.extend(self.keys.iter().flatten());
+        directory
+    }
+}
+
+fn geo_keys(geometry: &GridGeometry) -> GeoKeys {
+    let projection = &geometry.projection;
+    let mut keys = GeoKeys::default();
+    keys.short(1025, 1); // GTRasterTypeGeoKey : PixelIsArea
+    keys.short(2048, USER_DEFINED); // GeographicTypeGeoKey
+    keys.ascii(2049, "BUFR ellipsoid");
+    keys.short(2050, USER_DEFINED); // GeogGeodeticDatumGeoKey
+    keys.short(2051, 8901); // GeogPrimeMeridianGeoKey : Greenwich
+    keys.short(2054, 9102); // GeogAngularUnitsGeoKey : degree
+    keys.short(2056, USER_DEFINED); // GeogEllipsoidGeoKey
+    keys.double(2057, projection.ellipsoid.a);
+    keys.double(2058, projection.ellipsoid.b);
+    if projection.kind == ProjectionKind::Geographic {
+        keys.short(1024, 2); // GTModelTypeGeoKey : geographic
+        return keys;
+    }
+    keys.short(1024, 1); // GTModelTypeGeoKey : projected
+    keys.short(3072, USER_DEFINED); // ProjectedCSTypeGeoKey
+    keys.short(3074, USER_DEFINED); // ProjectionGeoKey
+    keys.short(3076, 9001); // ProjLinearUnitsGeoKey : metre
+    match projection.kind {
+        ProjectionKind::PolarStereographic => {
+            keys.ascii(1026, "BUFR polar stereographic");
+            // natural origin at the pole and the scale factor there, rather than the latitude
+            // of true scale in ProjNatOriginLatGeoKey that only some readers understand
+            keys.short(3075, 15); // CT_PolarStereographic
+            keys.double(3081, projection.lat_0.signum() * 90.0); // ProjNatOriginLatGeoKey
+            keys.double(3095, projection.lon_0); // ProjStraightVertPoleLongGeoKey
+            keys.double(3092, projection.pole_scale()); // ProjScaleAtNatOriginGeoKey
+            keys.double(3082, projection.x_0);
+            keys.double(3083, projection.y_0);
+        }
+        _ => {
+            keys.ascii(1026, "BUFR Lambert conformal conic");
+            keys.short(3075, 8); // CT_LambertConfConic_2SP
+            keys.double(3078, projection.lat_1);
+            keys.double(3079, projection.lat_2);
+            keys.double(3084, projection.lon_0);
+            keys.double(3085, projection.lat_0);
+            keys.double(3086, projection.x_0);
+            keys.double(3087, projection.y_0);
+        }
+    }
+    keys
+}
+
+// Single band Float32 GeoTIFF, one strip, rows north to south
+pub fn write_geotiff<W: Write>(writer: &mut W, image: &RadarImage, geometry: &GridGeometry) -> Result<(), Box<dyn Error>> {
+    if image.nx != geometry.nx || image.ny != geometry.ny {
+        return Err(From::from(format!("Image is {} x {} but grid geometry is {} x {}", image.ny, image.nx, geometry.ny, geometry.nx)));
+    }
+    let nodata = image.nodata;
+    let pixels: Vec<u8> = image
+        .data
+        .iter()
+        .map(|v| if v.is_nan() { nodata } else { *v })
+        .flat_map(|v| (v as f32).to_le_bytes())
+        .collect();
+
+    let mut keys = geo_keys(geometry);
+    let mut entries = vec![
+        long(256, image.nx as u32),       // ImageWidth
+        long(257, image.ny as u32),       // ImageLength
+        shorts(258, &[32]),               // BitsPerSample
+        shorts(259, &[1]),                // Compression : none
+        shorts(262, &[1]),                // PhotometricInterpretation : black is zero
+        long(273, 0),                     // StripOffsets, set below
+        shorts(277, &[1]),                // SamplesPerPixel
+        long(278, image.ny as u32),       // RowsPerStrip
+        long(279, pixels.len() as u32),   // StripByteCounts
+        shorts(284, &[1]),                // PlanarConfiguration
+        shorts(339, &[3]),                // SampleFormat : IEEE float
+        doubles(MODEL_PIXEL_SCALE, &[geometry.dx, geometry.dy, 0.0]),
+        doubles(MODEL_TIEPOINT, &[0.0, 0.0, 0.0, geometry.x_min, geometry.y_max, 0.0]),
+        shorts(GEO_KEY_DIRECTORY, &keys.directory()),
+        doubles(GEO_DOUBLE_PARAMS, &keys.doubles),
+        ascii(GEO_ASCII_PARAMS, &keys.ascii),
+        ascii(GDAL_NODATA, &if nodata.is_nan() { "nan".to_string() } else { nodata.to_string() }),
+    ];
+    entries.sort_by_key(|e| e.tag);
+
+    // header, IFD, then the values too large for an entry, then the pixels
+    let ifd_size = 2 + entries.len() * 12 + 4;
+    let mut offset = 8 + ifd_size;
+    let mut extra = Vec::new();
+    let mut ifd = Vec::with_capacity(ifd_size);
+    ifd.extend((entries.len() as u16).to_le_bytes());
+    let data_offset = {
+        let large: usize = entries.iter().filter(|e| e.bytes.len() > 4).map(|e| e.bytes.len() + e.bytes.len() % 2).sum();
+        (offset + large) as u32
+    };
+    for entry in entries.iter_mut() {
+        if entry.tag == 273 {
+            entry.bytes = data_offset.to_le_bytes().to_vec();
+        }
+        ifd.extend(entry.tag.to_le_bytes());
+        ifd.extend(entry.kind.to_le_bytes());
+        ifd.extend(entry.count.to_le_bytes());
+        if entry.bytes.len() <= 4 {
+            let mut inline = entry.bytes.clone();
+            inline.resize(4, 0);
+            ifd.extend(inline);
+        } else {
+            ifd.extend((offset as u32).to_le_bytes());
+            extra.extend(&entry.bytes);
+            if entry.bytes.len() % 2 == 1 {
+                extra.push(0);
+            }
+            offset += entry.bytes.len() + entry.bytes.len() % 2;
+        }
+    }
+    ifd.extend(0u32.to_le_bytes()); // no next IFD
+
+    writer.write_all(b"II")?;
+    writer.write_all(&42u16.to_le_bytes())?;
+    writer.write_all(&8u32.to_le_bytes())?;
+    writer.write_all(&ifd)?;
+    writer.write_all(&extra)?;
+    writer.write_all(&pixels)?;
+    writer.flush()?;
+    Ok(())
+}
+
+pub fn save_geotiff(path: &Path, image: &RadarImage, geometry: &GridGeometry) -> Result<(), Box<dyn Error>> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_geotiff(&mut writer, image, geometry)
+}
@@ -0,0 +1,98 @@
+use std::rc::Rc;
+
+use serde_json::{json, Map, Value};
+
+use crate::{BufrDecoder, DataValue, DecodedNode, Header};
+
+fn element(descriptor: &str, value: Option<&DataValue>) -> Value {
+    match value {
+        Some(v) => {
+            let data = match (&v.text, v.value) {
+                (Some(text), _) => json!(text),
+                (None, Some(number)) => json!(number),
+                (None, None) => Value::Null,
+            };
+            json!({
+                "type": "element",
+                "fxy": descriptor,
+                "name": v.description,
+                "unit": v.unit.to_string(),
+                "value": data,
+                "missing": v.text.is_none() && v.value.is_none(),
+            })
+        }
+        // descriptor not found in the tables, or 2-03 new reference value
+        None => json!({ "type": "element", "fxy": descriptor, "name": Value::Null, "unit": Value::Null, "value": Value::Null, "missing": true }),
+    }
+}
+
+fn node(decoded: &DecodedNode, values: &[DataValue]) -> Value {
+    match decoded {
+        DecodedNode::Element { descriptor, value } => element(descriptor, value.and_then(|i| values.get(i))),
+        DecodedNode::Sequence { descriptor, children } => json!({
+            "type": "sequence",
+            "fxy": descriptor,
+            "children": nodes(children, values),
+        }),
+        DecodedNode::Replication { descriptor, factor, repetitions } => {
+            let factor = factor.and_then(|i| values.get(i)).map(|v| element(&v.descriptor, Some(v))).unwrap_or(Value::Null);
+            json!({
+                "type": "replication",
+                "fxy": descriptor,
+                "factor": factor,
+                "count": repetitions.len(),
+                "repetitions": repetitions.iter().map(|r| nodes(r, values)).collect::<Vec<_>>(),
+            })
+        }
+        DecodedNode::Operator(descriptor) => json!({ "type": "operator", "fxy": descriptor }),
+    }
+}
+
+fn nodes(decoded: &[DecodedNode], values: &[DataValue]) -> Value {
+    Value::Array(decoded.iter().map(|n| node(n, values)).collect())
+}
+
+pub fn sections(header: &Header) -> Value {
+    let mut section1 = Map::new();
+    section1.insert("master_table".into(), json!(header.master_table));
+    section1.insert("centre".into(), json!(header.centre));
+    section1.insert("sub_centre".into(), json!(header.sub_centre));
+    section1.insert("update_sequence".into(), json!(header.update_sequence));
+    section1.insert("optional_section".into(), json!(header.sect2));
+    section1.insert("category".into(), json!(header.category));
+    section1.insert("subcategory".into(), json!(header.subcategory));
+    if header.edition >= 4 {
+        section1.insert("local_subcategory".into(), json!(header.local_subcategory));
+    }
+    section1.insert("master_table_version".into(), json!(header.master_table_version));
+    section1.insert("local_table_version".into(), json!(header.local_table_version));
+    section1.insert(
+        "reference_time".into(),
+        json!(header.reference_time()),
+    );
+    json!({
+        "edition": header.edition,
+        "total_length": header.total_length,
+        "section1": section1,
+        "section3": {
+            "number_of_subsets": header.number_of_subsets,
+            "observed": header.observed,
+            "compressed": header.compressed,
+            "descriptors": header.descriptors,
+        },
+    })
+}
+
+// One decoded message : section metadata, unexpanded descriptors and the data of every subset
+// as a tree. Object keys are sorted so the output can be diffed between versions.
+pub fn message(header: &Header, subsets: &[Vec<DataValue>], trees: &[Rc<Vec<DecodedNode>>]) -> Value {
+    let mut message = sections(header);
+    let data: Vec<Value> = trees.iter().zip(subsets).map(|(tree, values)| nodes(tree, values)).collect();
+    message["subsets"] = Value::Array(data);
+    message
+}
+
+// Last message decoded by `decoder`
+pub fn last_message(decoder: &BufrDecoder) -> Option<Value> {
+    Some(message(decoder.header()?, decoder.subsets(), decoder.trees()))
+}
@@ -0,0 +1,1559 @@
+use std::fmt::{self, Write as _};
+use std::io::Read;
+use std::ops::Range;
+use std::error::Error;
+use std::collections::HashMap;
+use std::path::Path;
+use std::rc::Rc;
+
+#[cfg(feature = "arrow")]
+pub mod arrow_export;
+pub mod expand;
+pub mod encode;
+pub mod error;
+pub mod export;
+pub mod filter;
+pub mod geotiff;
+pub mod json;
+pub mod netcdf;
+pub mod options;
+pub mod projection;
+pub mod radar;
+pub mod tables;
+pub mod units;
+
+use error::{BufrError, Location, Warning};
+use expand::{Expander, Node};
+use filter::ElementSelection;
+use options::{DecodeOptions, Limit, Strictness};
+use tables::{DicoB, DicoD, TableDiagnostic, TableFiles};
+use units::Unit;
+
+pub struct BitReader<R: Read> {
+    input: R,
+    accumulator: u8,
+    bcount: u8,
+    read: usize,
+    total_read: usize,
+    position: u64, // bits consumed since the reader was created
+    messages: usize, // messages started, for the location of errors
+}
+
+impl<R: Read> BitReader<R> {
+    pub fn new(input: R) -> Self {
+        BitReader {
+            input,
+            accumulator: 0,
+            bcount: 0,
+            read: 0,
+            total_read: 0,
+            position: 0,
+            messages: 0,
+        }
+    }
+
+    fn _readbit(&mut self) -> Result<u8, BufrError> {
+        if self.bcount == 0 {
+            let mut buffer = [0];
+            let bytes_read = self.input.read(&mut buffer)?;
+            if bytes_read == 0 {
+                return Err(BufrError::Truncated { at: Location::default() });
+            }
+            self.accumulator = buffer[0];
+            self.bcount = 8;
+            self.read = bytes_read;
+        }
+        let rv = (self.accumulator & (1 << (self.bcount - 1))) >> (self.bcount - 1);
+        self.bcount -= 1;
+        self.position += 1;
+        Ok(rv)
+    }
+
+    pub fn read_bits(&mut self, n: u32) -> Result<u32, BufrError> {
+        self.total_read += 1;
+        let mut v: u32 = 0;
+        for _ in 0..n {
+            v = (v << 1) | (self._readbit()? as u32);
+        }
+        Ok(v)
+    }
+
+    // Next byte on a byte boundary, None at the end of the input
+    pub fn next_byte(&mut self) -> Result<Option<u8>, BufrError> {
+        if self.bcount != 0 {
+            return Ok(Some(self.read_bits(8)? as u8));
+        }
+        let mut buffer = [0];
+        if self.input.read(&mut buffer)? == 0 {
+            return Ok(None);
+        }
+        self.total_read += 1;
+        self.position += 8;
+        Ok(Some(buffer[0]))
+    }
+
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    // Messages started (skipped, read or decoded) since the reader was created
+    pub fn messages(&self) -> usize {
+        self.messages
+    }
+
+    pub fn skip_bits(&mut self, mut n: u64) -> Result<(), BufrError> {
+        // end of the current byte, then whole bytes without going through the accumulator
+        while n > 0 && self.bcount > 0 {
+            self._readbit()?;
+            n -= 1;
+        }
+        let mut bytes = n / 8;
+        let mut buffer = [0u8; 256];
+        while bytes > 0 {
+            let chunk = bytes.min(buffer.len() as u64) as usize;
+            self.input.read_exact(&mut buffer[..chunk])?; // UnexpectedEof becomes BufrError::Truncated
+            self.position += chunk as u64 * 8;
+            bytes -= chunk as u64;
+        }
+        for _ in 0..n % 8 {
+            self._readbit()?;
+        }
+        Ok(())
+    }
+}
+
+// Skip a whole message using the total length of Section 0, returns that length
+// or None at the end of the input
+pub fn skip_message<R: Read>(reader: &mut BitReader<R>) -> Result<Option<u32>, BufrError> {
+    let Some(first) = reader.next_byte()? else {
+        return Ok(None);
+    };
+    reader.messages += 1;
+    let magic = (first as u32) << 24 | reader.read_bits(24)?;
+    if magic != 0x42554652 {
+        return Err(BufrError::BadMagic { found: magic.to_be_bytes(), at: Location::default() });
+    }
+    let total_length = reader.read_bits(24)?;
+    if total_length < 8 {
+        return Err(BufrError::InvalidLength { section: 0, length: total_length, at: Location::default() });
+    }
+    reader.skip_bits((total_length as u64 - 7) * 8)?; // edition octet included
+    Ok(Some(total_length))
+}
+
+// Raw octets of the next message, from "BUFR" to "7777", None at the end of the input
+pub fn read_message<R: Read>(reader: &mut BitReader<R>) -> Result<Option<Vec<u8>>, BufrError> {
+    let Some(first) = reader.next_byte()? else {
+        return Ok(None);
+    };
+    reader.messages += 1;
+    let magic = (first as u32) << 24 | reader.read_bits(24)?;
+    if magic != 0x42554652 {
+        return Err(BufrError::BadMagic { found: magic.to_be_bytes(), at: Location::default() });
+    }
+    let total_length = reader.read_bits(24)?;
+    if total_length < 8 {
+        return Err(BufrError::InvalidLength { section: 0, length: total_length, at: Location::default() });
+    }
+    let mut message = Vec::with_capacity(total_length.min(1 << 16) as usize); // grows with what is actually read
+    message.extend(magic.to_be_bytes());
+    message.extend(&total_length.to_be_bytes()[1..]);
+    for _ in 7..total_length {
+        message.push(reader.read_bits(8)? as u8);
+    }
+    Ok(Some(message))
+}
+
+// Message of an in-memory input (a memory-mapped file), its octets borrowed from "BUFR" to "7777"
+#[derive(Debug, Clone, Copy)]
+pub struct Message<'a> {
+    pub index: usize, // from 1
+    pub offset: u64,  // octets from the start of the input
+    pub bytes: &'a [u8],
+}
+
+impl<'a> Message<'a> {
+    // Reader over the message alone, offsets and locations still those of the whole input
+    pub fn reader(&self) -> BitReader<&'a [u8]> {
+        BitReader { position: self.offset * 8, messages: self.index - 1, ..BitReader::new(self.bytes) }
+    }
+}
+
+// Messages of `input` one after the other, using only Section 0 (see read_message).
+// Nothing is copied; the iteration ends after the first error.
+pub fn messages(input: &[u8]) -> Messages<'_> {
+    Messages { input, offset: 0, index: 0 }
+}
+
+pub struct Messages<'a> {
+    input: &'a [u8],
+    offset: usize,
+    index: usize,
+}
+
+impl<'a> Iterator for Messages<'a> {
+    type Item = Result<Message<'a>, BufrError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let rest = self.input.get(self.offset..).filter(|rest| !rest.is_empty())?;
+        self.index += 1;
+        let at = Location { message: Some(self.index), byte_offset: Some(self.offset as u64), ..Location::default() };
+        let error = match *rest {
+            [b'B', b'U', b'F', b'R', l0, l1, l2, ..] => {
+                let total_length = u32::from_be_bytes([0, l0, l1, l2]);
+                match rest.get(..total_length as usize) {
+                    Some(bytes) if total_length >= 8 => {
+                        let message = Message { index: self.index, offset: self.offset as u64, bytes };
+                        self.offset += bytes.len();
+                        return Some(Ok(message));
+                    }
+                    Some(_) => BufrError::InvalidLength { section: 0, length: total_length, at },
+                    None => BufrError::Truncated { at: Location { byte_offset: Some(self.input.len() as u64), ..at } },
+                }
+            }
+            [a, b, c, d, ..] if &[a, b, c, d] != b"BUFR" => BufrError::BadMagic { found: [a, b, c, d], at },
+            _ => BufrError::Truncated { at: Location { byte_offset: Some(self.input.len() as u64), ..at } },
+        };
+        self.offset = self.input.len();
+        Some(Err(error))
+    }
+}
+
+// CCITT IA5 text, trailing blanks and NULs removed
+fn bits_to_bytes(bytes: &[u8]) -> Result<String, Box<dyn Error>> {
+    let result = String::from_utf8(bytes.to_vec())?;
+    Ok(result.trim_end_matches([' ', '\0']).to_string())
+}
+
+// Numbers are read on 32 bits at most and characters on whole octets : another width comes
+// from the 2-01 (`width_plus`) or 2-08 (`new_width`) operator in force, or else from Table B
+pub(crate) fn check_width(descriptor: &str, width: u32, character: bool, width_plus: i32, new_width: u32) -> Result<(), BufrError> {
+    if if character { width.is_multiple_of(8) } else { width <= 32 } {
+        return Ok(());
+    }
+    let at = Location::default();
+    Err(if width_plus != 0 {
+        BufrError::InvalidOperator { descriptor: format!("2-01-{:03} ({} bits for {})", width_plus + 128, width, descriptor), at }
+    } else if new_width != 0 {
+        BufrError::InvalidOperator { descriptor: format!("2-08-{:03} ({} bits for {})", new_width / 8, width, descriptor), at }
+    } else {
+        BufrError::InvalidTableEntry { descriptor: descriptor.to_string(), field: "Data_width_bits", at }
+    })
+}
+
+// Numeric column of a Table B entry, a missing or malformed one stops the decoding
+pub(crate) fn table_b_field<T: std::str::FromStr>(entry: &HashMap<String, String>, descriptor: &str, field: &'static str) -> Result<T, BufrError> {
+    entry
+        .get(field)
+        .and_then(|v| v.trim().parse().ok())
+        .ok_or_else(|| BufrError::InvalidTableEntry { descriptor: descriptor.to_string(), field, at: Location::default() })
+}
+
+// Section 3 descriptor : F on 2 bits, X on 6 bits, Y on 8 bits
+fn bytes_desc(high: u8, low: u8) -> String {
+    format!("{}-{:02}-{:03}", high >> 6, high & 0x3f, low)
+}
+
+// One decoded element of a subset, in transmission order
+#[derive(Debug, Clone, PartialEq)]
+pub struct DataValue {
+    pub descriptor: String,
+    pub description: String,
+    pub unit: Unit,
+    pub value: Option<f64>,   // None when missing (all bits set to 1)
+    pub text: Option<String>, // CCITT IA5 elements
+}
+
+// What one descriptor read from Section 4, for the dump mode
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceEntry {
+    pub subset: usize,   // from 1, 0 for compressed data (all the subsets)
+    pub bit_offset: u64, // from the start of Section 4
+    pub descriptor: String,
+    pub description: String,
+    pub unit: Unit,
+    pub width: u32, // after 2-01 / 2-08
+    pub raw: Option<u32>, // None for CCITT IA5 and unknown descriptors
+    pub scale: f64, // after 2-02
+    pub reference: f64, // after 2-03
+    pub value: Option<f64>,
+    pub text: Option<String>,
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let raw = self.raw.map(|r| r.to_string()).unwrap_or_else(|| "-".to_string());
+        let value = match (&self.text, self.value, self.raw) {
+            (Some(text), _, _) => format!("\"{}\"", text),
+            (None, Some(value), _) => format!("{} {}", value, self.unit),
+            (None, None, Some(_)) => "MISSING".to_string(),
+            (None, None, None) => String::new(),
+        };
+        write!(
+            f,
+            "{:>5} {:>8} {:>3} {} {:>10} {:>4} {:>11} {:<24} {}",
+            self.subset, self.bit_offset, self.width, self.descriptor, raw, self.scale, self.reference, value, self.description
+        )
+    }
+}
+
+// Values decoded from one message can't exceed MAX_VALUES + MAX_VALUES_PER_BIT x the bits of
+// Section 4 : compression and zero width elements can't blow a small message up in memory
+const MAX_VALUES: u64 = 1 << 20;
+const MAX_VALUES_PER_BIT: u64 = 64;
+
+// Decoded values keyed by Table B description
+pub type Datas = HashMap<String, Vec<f64>>;
+
+// Sections 0 to 3 of the last decoded message
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Header {
+    pub offset: u64, // position of "BUFR" in the input, in bytes
+    pub total_length: u32,
+    pub edition: u32,
+    pub master_table: u32,
+    pub centre: u32,
+    pub sub_centre: u32,
+    pub update_sequence: u32,
+    pub sect2: bool,
+    pub category: u32,
+    pub subcategory: u32,
+    pub local_subcategory: u32, // edition 4 only
+    pub master_table_version: u32,
+    pub local_table_version: u32,
+    pub year: u32,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32, // edition 4 only
+    pub number_of_subsets: u32,
+    pub observed: bool,
+    pub compressed: bool,
+    pub descriptors: Vec<String>, // unexpanded Section 3 list
+}
+
+impl Header {
+    pub fn is_supported(&self) -> bool {
+        (2..=4).contains(&self.edition)
+    }
+
+    // Editions before 4 only carry the year of the century
+    pub fn full_year(&self) -> u32 {
+        match (self.edition, self.year) {
+            (4.., year) => year,
+            (_, year) if year <= 50 => 2000 + year,
+            (_, year) if year <= 100 => 1900 + year,
+            (_, year) => year,
+        }
+    }
+
+    pub fn reference_time(&self) -> String {
+        format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}", self.full_year(), self.month, self.day, self.hour, self.minute, self.second)
+    }
+}
+
+// Skip what is left of the message `header` was read from
+pub fn skip_rest<R: Read>(reader: &mut BitReader<R>, header: &Header) -> Result<(), BufrError> {
+    let end = (header.offset + header.total_length as u64) * 8;
+    reader.skip_bits(end.saturating_sub(reader.position()))
+}
+
+// Decoded subset following the expansion tree, elements point into the subset values
+#[derive(Debug, Clone, PartialEq)]
+pub enum DecodedNode {
+    Element { descriptor: String, value: Option<usize> }, // None for unknown descriptors and 2-03 reference values
+    Sequence { descriptor: String, children: Vec<DecodedNode> },
+    Replication { descriptor: String, factor: Option<usize>, repetitions: Vec<Vec<DecodedNode>> },
+    Operator(String),
+}
+
+// Text output on stdout. A closed pipe (bufr_decoder decode ... | head) isn't a decoding
+// error : the lines are lost and the CLI stops on its own next write
+macro_rules! ecrit {
+    ($($arg:tt)*) => {{
+        use std::io::Write as _;
+        let _ = writeln!(std::io::stdout().lock(), $($arg)*);
+    }};
+}
+
+// Section level output, silenced with BufrDecoder::affiche_sections(false)
+macro_rules! affiche {
+    ($self:ident, $($arg:tt)*) => {
+        if $self.affiche_sections {
+            ecrit!($($arg)*);
+        }
+    };
+}
+
+pub struct BufrDecoder {
+    dir_path_table: String,
+    table_files: TableFiles,
+    affiche_descriptors: bool,
+    affiche_sections: bool,
+    dico_m_b: DicoB,
+    dico_m_d: DicoD,
+    dico_l_b: DicoB,
+    dico_l_d: DicoD,
+    tables_loaded: Option<(u32, u32, u32)>, // (master version, centre, local version) of the tables in memory
+    expander: Expander,
+    datas_total: Datas, // Store decoded data
+    datas_unites: HashMap<String, Unit>,
+    datas_subsets: Vec<Vec<DataValue>>, // ordered values of each subset of the last message
+    datas_trees: Vec<Rc<Vec<DecodedNode>>>, // one per subset, the same for all the compressed subsets
+    header: Option<Header>,
+    bit_width_plus: i32,
+    bit_scale_plus: i32,
+    bit_ref_changed: bool,
+    bit_new_ref: HashMap<String, f64>,
+    bit_ref_bits: u32, // non zero while 2-03-YYY reads new reference values on YYY bits
+    bit_new_width: u32,
+    trace: Option<Vec<TraceEntry>>, // Some when dumping
+    compressed: bool, // Section 4 of the current message holds compressed data
+    selection: Option<ElementSelection>,
+    selected: HashMap<String, bool>, // selection.matches for each descriptor met, until the tables change
+    start_4: u64,
+    end_4: u64, // bit after Section 4, as given by its length
+    values: u64, // decoded in the current message
+    subset_bits: Vec<Range<u64>>, // bits of each uncompressed subset, from the start of Section 4
+    path: Vec<String>, // descriptors being decoded
+    options: DecodeOptions,
+    warnings: Vec<Warning>, // of the last message
+}
+
+impl BufrDecoder {
+    // Decoder of the tables of `dir_path_table`, named as TableFiles::default(), without any report
+    pub fn with_tables(dir_path_table: impl Into<String>) -> Self {
+        let files = TableFiles::default();
+        let mut decoder = BufrDecoder::new(dir_path_table.into(), files.master_b, files.master_d, files.local_b, files.local_d, false);
+        decoder.affiche_sections(false);
+        decoder
+    }
+
+    pub fn new(dir_path_table: String, fic_tab_b: String, fic_tab_d: String, fic_local_tab_b: String, fic_local_tab_d: String, affiche_descriptors: bool) -> Self {
+        BufrDecoder {
+            dir_path_table,
+            table_files: TableFiles { master_b: fic_tab_b, master_d: fic_tab_d, local_b: fic_local_tab_b, local_d: fic_local_tab_d },
+            affiche_descriptors,
+            affiche_sections: true,
+            dico_m_b: HashMap::new(),
+            dico_m_d: HashMap::new(),
+            dico_l_b: HashMap::new(),
+            dico_l_d: HashMap::new(),
+            tables_loaded: None,
+            expander: Expander::default(),
+            datas_total: HashMap::new(), // Initialize data storage
+            datas_unites: HashMap::new(),
+            datas_subsets: Vec::new(),
+            datas_trees: Vec::new(),
+            header: None,
+            bit_width_plus: 0,
+            bit_scale_plus: 0,
+            bit_ref_changed: false,
+            bit_new_ref: HashMap::new(),
+            bit_ref_bits: 0,
+            bit_new_width: 0,
+            trace: None,
+            compressed: false,
+            selection: None,
+            selected: HashMap::new(),
+            start_4: 0,
+            end_4: 0,
+            values: 0,
+            subset_bits: Vec::new(),
+            path: Vec::new(),
+            options: DecodeOptions::default(),
+            warnings: Vec::new(),
+        }
+    }
+
+    fn descri(&self, desc: &str) -> Option<&HashMap<String, String>> {
+        if let Some(r) = self.dico_l_b.get(desc) {
+            if self.affiche_descriptors {
+                ecrit!("{} : {:?}", desc, r);
+            }
+            Some(r)
+        } else if let Some(r) = self.dico_l_d.get(desc) {
+            if self.affiche_descriptors {
+                ecrit!("{} : {:?}", desc, r);
+            }
+            None // D table returns Vec<String>, not HashMap, so return None here and handle D table lookups differently if needed
+        } else if let Some(r) = self.dico_m_b.get(desc) {
+            if self.affiche_descriptors {
+                ecrit!("{} : {:?}", desc, r);
+            }
+            Some(r)
+        } else if let Some(r) = self.dico_m_d.get(desc) {
+            if self.affiche_descriptors {
+                ecrit!("{} : {:?}", desc, r);
+            }
+            None // Same as above for master D table
+        } else {
+            if self.affiche_descriptors {
+                ecrit!("{} UNKNOWN", desc);
+            }
+            None
+        }
+    }
+
+    // Returns the decoded value, None when the descriptor is unknown
+    fn simple_desc<R: Read>(&mut self, desc_elt: &str, reader: &mut BitReader<R>) -> Result<Option<f64>, BufrError> {
+        let selected = self.is_selected(desc_elt);
+        if let Some(descript_elt) = self.descri(desc_elt) {
+            let mut longueur: u32 = table_b_field(descript_elt, desc_elt, "Data_width_bits")?; // Get data width from descriptor
+            if self.bit_new_width != 0 {
+                longueur = self.bit_new_width;
+            }
+            longueur = longueur.saturating_add_signed(self.bit_width_plus);
+
+            if !selected && !desc_elt.starts_with("0-31-") {
+                // skipped by its width, the replication factors are still needed to walk the tree
+                reader.skip_bits(longueur as u64)?;
+                return Ok(None);
+            }
+
+            let description = descript_elt.get("Description").unwrap_or(&String::from("No Description")).clone();
+            if self.affiche_descriptors {
+                ecrit!("longueur : {}, Description : {}", longueur, description);
+            }
+
+            let scale: f64 = table_b_field::<f64>(descript_elt, desc_elt, "Scale")? + self.bit_scale_plus as f64;
+            let mut ref_val: f64 = table_b_field(descript_elt, desc_elt, "Ref_Val")?;
+
+            if self.bit_ref_changed {
+                if let Some(new_ref) = self.bit_new_ref.get(desc_elt) {
+                    ref_val = *new_ref;
+                }
+            }
+
+            let unit = Unit::of_element(desc_elt, descript_elt.get("Unit").map(String::as_str).unwrap_or(""));
+            let bit_offset = reader.position() - self.start_4;
+            let mut raw = None;
+            let (value, text) = if unit.is_character() {
+                self.check_width(desc_elt, longueur, true)?;
+                self.check_limit(Limit::StringLength, longueur as u64 / 8)?;
+                let mut bytes = Vec::with_capacity(longueur as usize / 8);
+                for _ in 0..longueur / 8 {
+                    bytes.push(reader.read_bits(8)? as u8);
+                }
+                if bytes.iter().all(|b| *b == 0xff) {
+                    (None, None)
+                } else {
+                    match bits_to_bytes(&bytes) {
+                        Ok(byte_str) => {
+                            affiche!(self, "  \"{}\"", byte_str);
+                            (None, Some(byte_str))
+                        }
+                        Err(_) => {
+                            let at = self.location(reader);
+                            self.warn(Warning::NonPrintableText { descriptor: desc_elt.to_string(), at });
+                            (None, None)
+                        }
+                    }
+                }
+            } else {
+                self.check_width(desc_elt, longueur, false)?;
+                let tot_bits = reader.read_bits(longueur)?;
+                raw = Some(tot_bits);
+                if !selected {
+                    return Ok(Some((tot_bits as f64 + ref_val) / 10f64.powf(scale))); // replication factor
+                }
+                // all bits set means missing, except for the replication factors of class 31
+                let missing = longueur > 0 && longueur <= 32 && tot_bits as u64 == (1u64 << longueur) - 1 && !desc_elt.starts_with("0-31-");
+                let val_data = (tot_bits as f64 + ref_val) / 10f64.powf(scale);
+                if self.affiche_descriptors {
+                    ecrit!("  = {} {}", if missing { "MISSING".to_string() } else { val_data.to_string() }, unit);
+                }
+                (if missing { None } else { Some(val_data) }, None)
+            };
+
+            if let Some(trace) = self.trace.as_mut() {
+                trace.push(TraceEntry {
+                    subset: self.datas_subsets.len(),
+                    bit_offset,
+                    descriptor: desc_elt.to_string(),
+                    description: description.clone(),
+                    unit: unit.clone(),
+                    width: longueur,
+                    raw,
+                    scale,
+                    reference: ref_val,
+                    value,
+                    text: text.clone(),
+                });
+            }
+            self.count_values(1)?;
+            self.datas_total.entry(description.clone()).or_default().push(value.unwrap_or(f64::NAN));
+            self.datas_unites.entry(description.clone()).or_insert(unit.clone());
+            if let Some(subset) = self.datas_subsets.last_mut() {
+                subset.push(DataValue { descriptor: desc_elt.to_string(), description, unit, value, text });
+            }
+            return Ok(value);
+        }
+        // nothing can be read past it : its width is unknown
+        let bit_offset = reader.position() - self.start_4;
+        let subset = self.datas_subsets.len();
+        if let Some(trace) = self.trace.as_mut() {
+            trace.push(TraceEntry {
+                subset,
+                bit_offset,
+                descriptor: desc_elt.to_string(),
+                description: "UNKNOWN".to_string(),
+                unit: Unit::parse(""),
+                width: 0,
+                raw: None,
+                scale: 0.0,
+                reference: 0.0,
+                value: None,
+                text: None,
+            });
+        }
+        self.nonconformity(BufrError::UnknownDescriptor { descriptor: desc_elt.to_string(), at: Location::default() }, reader)?;
+        Ok(None)
+    }
+
+    // Compressed data : one element for every subset at once, R0 on the element width, NBINC on
+    // 6 bits, then an increment of NBINC bits (NBINC octets for CCITT IA5) per subset unless NBINC is 0.
+    // Returns the value of the first subset.
+    fn compressed_desc<R: Read>(&mut self, desc_elt: &str, reader: &mut BitReader<R>) -> Result<Option<f64>, BufrError> {
+        let selected = self.is_selected(desc_elt);
+        let Some(descript_elt) = self.descri(desc_elt) else {
+            self.nonconformity(BufrError::UnknownDescriptor { descriptor: desc_elt.to_string(), at: Location::default() }, reader)?;
+            return Ok(None);
+        };
+        let mut longueur: u32 = table_b_field(descript_elt, desc_elt, "Data_width_bits")?;
+        if self.bit_new_width != 0 {
+            longueur = self.bit_new_width;
+        }
+        longueur = longueur.saturating_add_signed(self.bit_width_plus);
+        let description = descript_elt.get("Description").unwrap_or(&String::from("No Description")).clone();
+        let scale: f64 = table_b_field::<f64>(descript_elt, desc_elt, "Scale")? + self.bit_scale_plus as f64;
+        let mut ref_val: f64 = table_b_field(descript_elt, desc_elt, "Ref_Val")?;
+        if self.bit_ref_changed {
+            if let Some(new_ref) = self.bit_new_ref.get(desc_elt) {
+                ref_val = *new_ref;
+            }
+        }
+        let unit = Unit::of_element(desc_elt, descript_elt.get("Unit").map(String::as_str).unwrap_or(""));
+        let replication_factor = desc_elt.starts_with("0-31-");
+        let subsets = self.datas_subsets.len();
+        let bit_offset = reader.position() - self.start_4;
+
+        let mut raw = None;
+        let mut values: Vec<(Option<f64>, Option<String>)> = Vec::with_capacity(subsets);
+        if unit.is_character() {
+            let at = self.location(reader);
+            let mut non_printable = false;
+            let mut text = |bytes: &[u8]| {
+                if bytes.iter().all(|b| *b == 0xff) {
+                    (None, None)
+                } else {
+                    let text = bits_to_bytes(bytes).ok();
+                    non_printable |= text.is_none();
+                    (None, text)
+                }
+            };
+            self.check_width(desc_elt, longueur, true)?;
+            self.check_limit(Limit::StringLength, longueur as u64 / 8)?;
+            let mut r0 = Vec::with_capacity(longueur as usize / 8);
+            for _ in 0..longueur / 8 {
+                r0.push(reader.read_bits(8)? as u8);
+            }
+            let nbinc = reader.read_bits(6)?;
+            if nbinc == 0 {
+                values.resize(subsets, text(&r0));
+            } else {
+                let mut bytes = Vec::with_capacity(nbinc as usize);
+                for _ in 0..subsets {
+                    bytes.clear();
+                    for _ in 0..nbinc {
+                        bytes.push(reader.read_bits(8)? as u8);
+                    }
+                    values.push(text(&bytes));
+                }
+            }
+            if non_printable {
+                self.warn(Warning::NonPrintableText { descriptor: desc_elt.to_string(), at });
+            }
+        } else {
+            self.check_width(desc_elt, longueur, false)?;
+            let r0 = reader.read_bits(longueur)?;
+            raw = Some(r0);
+            let nbinc = reader.read_bits(6)?;
+            if nbinc > 32 {
+                return Err(BufrError::InvalidIncrement { descriptor: desc_elt.to_string(), bits: nbinc, at: Location::default() });
+            }
+            // all bits set means missing, except for the replication factors of class 31
+            let all_set = |value: u32, bits: u32| bits > 0 && bits <= 32 && value as u64 == (1u64 << bits) - 1 && !replication_factor;
+            let value = |increment: u32| Some((r0 as f64 + increment as f64 + ref_val) / 10f64.powf(scale));
+            if nbinc == 0 {
+                values.resize(subsets, (if all_set(r0, longueur) { None } else { value(0) }, None));
+            } else {
+                for _ in 0..subsets {
+                    let increment = reader.read_bits(nbinc)?;
+                    values.push((if all_set(r0, longueur) || all_set(increment, nbinc) { None } else { value(increment) }, None));
+                }
+            }
+            if replication_factor && values.windows(2).any(|pair| pair[0] != pair[1]) {
+                return Err(BufrError::InvalidReplication {
+                    descriptor: desc_elt.to_string(),
+                    reason: "factor differs between compressed subsets".to_string(),
+                    at: Location::default(),
+                });
+            }
+        }
+        if self.affiche_descriptors {
+            ecrit!("longueur : {}, Description : {}", longueur, description);
+            ecrit!("  = {:?} {}", values, unit);
+        }
+
+        let first = values.first().and_then(|v| v.0);
+        if !selected {
+            return Ok(first);
+        }
+        if let Some(trace) = self.trace.as_mut() {
+            trace.push(TraceEntry {
+                subset: 0,
+                bit_offset,
+                descriptor: desc_elt.to_string(),
+                description: description.clone(),
+                unit: unit.clone(),
+                width: longueur,
+                raw,
+                scale,
+                reference: ref_val,
+                value: first,
+                text: values.first().and_then(|v| v.1.clone()),
+            });
+        }
+        self.count_values(subsets as u64)?;
+        for (subset, (value, text)) in self.datas_subsets.iter_mut().zip(values) {
+            subset.push(DataValue { descriptor: desc_elt.to_string(), description: description.clone(), unit: unit.clone(), value, text });
+        }
+        Ok(first)
+    }
+
+    fn count_values(&mut self, values: u64) -> Result<(), BufrError> {
+        self.values += values;
+        self.check_limit(Limit::Values, self.values)?;
+        let max = MAX_VALUES + MAX_VALUES_PER_BIT * (self.end_4 - self.start_4);
+        if self.values > max {
+            return Err(BufrError::TooManyValues { max, at: Location::default() });
+        }
+        Ok(())
+    }
+
+    // Ordered values of each subset of the last decoded message
+    pub fn subsets(&self) -> &[Vec<DataValue>] {
+        &self.datas_subsets
+    }
+
+    // Bits of each subset in Section 4 of the last message, empty for compressed data
+    pub fn subset_bits(&self) -> &[Range<u64>] {
+        &self.subset_bits
+    }
+
+    // Same subsets as trees of sequences, replications and operators
+    pub fn trees(&self) -> &[Rc<Vec<DecodedNode>>] {
+        &self.datas_trees
+    }
+
+    pub fn header(&self) -> Option<&Header> {
+        self.header.as_ref()
+    }
+
+    // Only keep the elements matching `selection`, the others are skipped without being decoded
+    pub fn select(&mut self, selection: Option<ElementSelection>) {
+        self.selection = selection;
+        self.selected.clear();
+    }
+
+    fn is_selected(&mut self, descriptor: &str) -> bool {
+        let Some(selection) = &self.selection else {
+            return true;
+        };
+        if let Some(selected) = self.selected.get(descriptor) {
+            return *selected;
+        }
+        let description = self
+            .dico_l_b
+            .get(descriptor)
+            .or_else(|| self.dico_m_b.get(descriptor))
+            .and_then(|entry| entry.get("Description"))
+            .map(String::as_str)
+            .unwrap_or("");
+        let selected = selection.matches(descriptor, description);
+        self.selected.insert(descriptor.to_string(), selected);
+        selected
+    }
+
+    // Record a TraceEntry for every descriptor read from Section 4
+    pub fn enable_trace(&mut self, enabled: bool) {
+        self.trace = if enabled { Some(Vec::new()) } else { None };
+    }
+
+    // Trace of the last decoded message, empty unless enabled
+    pub fn trace(&self) -> &[TraceEntry] {
+        self.trace.as_deref().unwrap_or(&[])
+    }
+
+    // Print or not (default) each descriptor as it is decoded
+    pub fn affiche_descriptors(&mut self, affiche: bool) {
+        self.affiche_descriptors = affiche;
+    }
+
+    // Print (default) or not the section by section report of each message
+    pub fn affiche_sections(&mut self, affiche: bool) {
+        self.affiche_sections = affiche;
+    }
+
+    // Decoded values of one element converted to `target` (e.g. K -> degC, m/s -> knots, Pa -> hPa),
+    // None if the element was not decoded or its unit can't be converted
+    pub fn datas_converted(&self, description: &str, target: &Unit) -> Option<Vec<f64>> {
+        let unit = self.datas_unites.get(description)?;
+        if !unit.is_convertible_to(target) {
+            return None;
+        }
+        self.datas_total.get(description)?.iter().map(|v| unit.convert(*v, target)).collect()
+    }
+
+    pub fn datas_unit(&self, description: &str) -> Option<&Unit> {
+        self.datas_unites.get(description)
+    }
+
+    fn reset_operators(&mut self) {
+        self.bit_width_plus = 0;
+        self.bit_scale_plus = 0;
+        self.bit_ref_changed = false;
+        self.bit_new_ref.clear();
+        self.bit_ref_bits = 0;
+        self.bit_new_width = 0;
+    }
+
+    fn check_width(&self, descriptor: &str, width: u32, character: bool) -> Result<(), BufrError> {
+        check_width(descriptor, width, character, self.bit_width_plus, self.bit_new_width)
+    }
+
+    fn descri_table_c(&mut self, descriptor: &str) -> Result<(), BufrError> {
+        let (_, x, new_ref) = expand::fxy(descriptor).ok_or_else(|| BufrError::InvalidOperator { descriptor: descriptor.to_string(), at: Location::default() })?;
+        let new_ref = new_ref as i32;
+        match x {
+            1 => { // change data width
+                self.bit_width_plus = if new_ref == 0 { 0 } else { new_ref - 128 };
+            },
+            2 => { // change scale
+                self.bit_scale_plus = if new_ref == 0 { 0 } else { new_ref - 128 };
+            },
+            3 => { // change reference value
+                if new_ref == 255 {
+                    // end of the list of new reference values, they stay in force until 2-03-000
+                    self.bit_ref_bits = 0;
+                } else if new_ref > 32 {
+                    return Err(BufrError::InvalidOperator { descriptor: descriptor.to_string(), at: Location::default() });
+                } else if new_ref > 0 {
+                    // the following element descriptors carry their new reference value on new_ref bits
+                    self.bit_ref_changed = true;
+                    self.bit_ref_bits = new_ref as u32;
+                } else {
+                    self.bit_ref_changed = false;
+                    self.bit_ref_bits = 0;
+                    self.bit_new_ref.clear();
+                }
+            },
+            8 => { // change bit width
+                self.bit_new_width = if new_ref == 0 { 0 } else { 8 * new_ref as u32 };
+            },
+            _ => {}
+        }
+        Ok(())
+    }
+
+    // New reference value for `descriptor`, sign in the leftmost bit
+    fn new_reference_value<R: Read>(&mut self, descriptor: &str, reader: &mut BitReader<R>) -> Result<(), BufrError> {
+        let ybits = self.bit_ref_bits;
+        let bit_offset = reader.position() - self.start_4;
+        let result = reader.read_bits(ybits)?;
+        let ref_val = if result >= 2u32.pow(ybits - 1) {
+            -((result - 2u32.pow(ybits - 1)) as f64)
+        } else {
+            result as f64
+        };
+        if self.compressed {
+            // same new reference value for every subset
+            let nbinc = reader.read_bits(6)?;
+            reader.skip_bits(nbinc as u64 * self.datas_subsets.len() as u64)?;
+        }
+        self.bit_new_ref.insert(descriptor.to_string(), ref_val);
+        let subset = if self.compressed { 0 } else { self.datas_subsets.len() };
+        if let Some(trace) = self.trace.as_mut() {
+            trace.push(TraceEntry {
+                subset,
+                bit_offset,
+                descriptor: descriptor.to_string(),
+                description: "New reference value (2-03)".to_string(),
+                unit: Unit::parse(""),
+                width: ybits,
+                raw: Some(result),
+                scale: 0.0,
+                reference: 0.0,
+                value: Some(ref_val),
+                text: None,
+            });
+        }
+        Ok(())
+    }
+
+    // Index in the current subset of the value pushed by simple_desc, if any
+    fn decode_element<R: Read>(&mut self, descriptor: &str, reader: &mut BitReader<R>) -> Result<(Option<f64>, Option<usize>), BufrError> {
+        let before = self.datas_subsets.last().map(Vec::len).unwrap_or(0);
+        let value = if self.compressed {
+            self.compressed_desc(descriptor, reader)?
+        } else {
+            self.simple_desc(descriptor, reader)?
+        };
+        let after = self.datas_subsets.last().map(Vec::len).unwrap_or(0);
+        Ok((value, if after > before { Some(before) } else { None }))
+    }
+
+    // self.path holds the descriptors being decoded, it is left as is when an error is returned
+    fn decode_nodes<R: Read>(&mut self, nodes: &[Node], reader: &mut BitReader<R>) -> Result<Vec<DecodedNode>, BufrError> {
+        let mut decoded = Vec::with_capacity(nodes.len());
+        for node in nodes {
+            let (Node::Element(descriptor) | Node::Operator(descriptor) | Node::Sequence { descriptor, .. } | Node::Replication { descriptor, .. }) = node;
+            self.path.push(descriptor.clone());
+            if !matches!(node, Node::Element(_)) {
+                self.count_values(1)?; // elements are counted with their values
+            }
+            match node {
+                Node::Element(descriptor) => {
+                    // F = 0 : single element descriptor (ref in Table B)
+                    if self.affiche_descriptors {
+                        ecrit!("{}", descriptor);
+                    }
+                    let value = if self.bit_ref_bits > 0 {
+                        self.new_reference_value(descriptor, reader)?;
+                        None
+                    } else {
+                        self.decode_element(descriptor, reader)?.1
+                    };
+                    if value.is_none() && !self.is_selected(descriptor) {
+                        self.path.pop();
+                        continue; // not in the tree either
+                    }
+                    decoded.push(DecodedNode::Element { descriptor: descriptor.clone(), value });
+                }
+                Node::Sequence { descriptor, children } => {
+                    // F = 3 : list of descriptors (ref in table D)
+                    if self.affiche_descriptors {
+                        ecrit!("{}", descriptor);
+                    }
+                    let children = self.decode_nodes(children, reader)?;
+                    decoded.push(DecodedNode::Sequence { descriptor: descriptor.clone(), children });
+                }
+                Node::Replication { descriptor, count, factor, children } => {
+                    // F = 1 : replication, delayed when the count comes from the data
+                    if self.affiche_descriptors {
+                        ecrit!("{}", descriptor);
+                    }
+                    let (count, factor) = match factor {
+                        Some(factor) => {
+                            self.path.push(factor.clone());
+                            let (value, index) = self.decode_element(factor, reader)?;
+                            let value = value.ok_or_else(|| BufrError::InvalidReplication {
+                                descriptor: descriptor.clone(),
+                                reason: format!("missing delayed replication factor {}", factor),
+                                at: Location::default(),
+                            })?;
+                            self.path.pop();
+                            (value as u32, index)
+                        }
+                        None => (*count, None),
+                    };
+                    self.check_limit(Limit::Replication, count as u64)?;
+                    // every repetition reads data, so the count can't exceed the bits left in Section 4
+                    if factor.is_some() && count as u64 > self.end_4.saturating_sub(reader.position()) {
+                        return Err(BufrError::InvalidReplication {
+                            descriptor: descriptor.clone(),
+                            reason: format!("{} repetitions for {} bits left in Section 4", count, self.end_4.saturating_sub(reader.position())),
+                            at: Location::default(),
+                        });
+                    }
+                    let mut repetitions = Vec::new();
+                    for _ in 0..count {
+                        let before = reader.position();
+                        repetitions.push(self.decode_nodes(children, reader)?);
+                        if reader.position() == before && count > 1 {
+                            return Err(BufrError::InvalidReplication {
+                                descriptor: descriptor.clone(),
+                                reason: "the replicated descriptors don't read any data".to_string(),
+                                at: Location::default(),
+                            });
+                        }
+                    }
+                    decoded.push(DecodedNode::Replication { descriptor: descriptor.clone(), factor, repetitions });
+                }
+                Node::Operator(descriptor) => {
+                    // F = 2 : Operator descriptor (ref in table C)
+                    if self.affiche_descriptors {
+                        ecrit!("{}", descriptor);
+                    }
+                    self.descri_table_c(descriptor)?;
+                    decoded.push(DecodedNode::Operator(descriptor.clone()));
+                }
+            }
+            self.path.pop();
+        }
+        Ok(decoded)
+    }
+
+
+    // Sections 0 to 3 of the next message, None at the end of the input.
+    // For an unsupported edition only Section 0 is read.
+    pub fn read_header<R: Read>(&mut self, reader: &mut BitReader<R>, bytes_size: u32) -> Result<Option<Header>, BufrError> {
+        self.read_sections(reader, bytes_size).map_err(|e| self.locate(e, reader))
+    }
+
+    // How non-conformant messages are handled, and the limits of what is decoded
+    pub fn set_options(&mut self, options: DecodeOptions) {
+        self.expander = Expander::new(options.limits.max_depth, options.limits.max_descriptors);
+        self.options = options;
+    }
+
+    fn check_limit(&self, limit: Limit, value: u64) -> Result<(), BufrError> {
+        let limits = &self.options.limits;
+        let max = match limit {
+            Limit::MessageLength => limits.max_message_length as u64,
+            Limit::Descriptors => limits.max_descriptors as u64,
+            Limit::Replication => limits.max_replication as u64,
+            Limit::Depth => limits.max_depth as u64,
+            Limit::StringLength => limits.max_string_length as u64,
+            Limit::Values => limits.max_values,
+        };
+        if value > max {
+            return Err(BufrError::LimitExceeded { limit, value, max, at: Location::default() });
+        }
+        Ok(())
+    }
+
+    // Names of the table files, for tables::TableSet::load and tables::check_tables
+    pub fn table_files(&self) -> &TableFiles {
+        &self.table_files
+    }
+
+    // Warnings of the last message, and of the tables loaded for it
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    fn warn(&mut self, warning: Warning) {
+        log::warn!("{}", warning);
+        self.warnings.push(warning);
+    }
+
+    // An error unless lenient (kept as a warning) or ignored
+    fn nonconformity<R: Read>(&mut self, error: BufrError, reader: &BitReader<R>) -> Result<(), BufrError> {
+        match self.options.strictness {
+            Strictness::Strict => return Err(error),
+            Strictness::Lenient => {
+                let error = self.locate(error, reader);
+                self.warn(Warning::Nonconformity(error));
+            }
+            Strictness::Ignore => {}
+        }
+        Ok(())
+    }
+
+    // Message, byte, Section 4 bit and descriptors `reader` stopped at
+    fn location<R: Read>(&self, reader: &BitReader<R>) -> Location {
+        let position = reader.position();
+        Location {
+            message: Some(reader.messages),
+            byte_offset: Some(position / 8),
+            bit_offset: if self.start_4 > 0 && position >= self.start_4 { Some(position - self.start_4) } else { None },
+            path: self.path.clone(),
+        }
+    }
+
+    fn locate<R: Read>(&self, error: BufrError, reader: &BitReader<R>) -> BufrError {
+        error.at(self.location(reader))
+    }
+
+    fn read_sections<R: Read>(&mut self, reader: &mut BitReader<R>, bytes_size: u32) -> Result<Option<Header>, BufrError> {
+        self.start_4 = 0;
+        self.path.clear();
+        // end of input between two messages
+        let offset = reader.position() / 8;
+        let Some(first) = reader.next_byte()? else {
+            return Ok(None);
+        };
+        reader.messages += 1;
+        let x = (first as u32) << (3 * bytes_size) | reader.read_bits(3 * bytes_size)?;
+         if x != 0x42554652 { // BUFR magic number
+            return Err(BufrError::BadMagic { found: x.to_be_bytes(), at: Location::default() });
+        }
+        affiche!(self, " ----------- BEGIN OF BUFR MESSAGE -----------");
+        affiche!(self, "Entete: BUFR");
+
+        let mut header = Header {
+            offset,
+            total_length: reader.read_bits(3 * bytes_size)?,
+            ..Header::default()
+        };
+        affiche!(self, "Total length of Bufr message in bytes : {}", header.total_length);
+        self.check_limit(Limit::MessageLength, header.total_length as u64)?;
+        header.edition = reader.read_bits(bytes_size)?;
+        affiche!(self, "Bufr Edition number : {}", header.edition);
+
+
+        // SECTION 1
+        let version = header.edition;
+        let length_1 = if version == 2 || version == 3 {
+            self.section1_v2(reader, bytes_size, &mut header)? // same layout in edition 3
+        } else if version == 4 {
+            self.section1_v4(reader, bytes_size, &mut header)?
+        } else {
+            affiche!(self, "Version Inconnue");
+            return Ok(Some(header));
+        };
+
+        header.master_table_version = reader.read_bits(bytes_size)?;
+        affiche!(self, "Version number of master table used : {}", header.master_table_version);
+        header.local_table_version = reader.read_bits(bytes_size)?;
+        affiche!(self, "Version number of local tables used : {}", header.local_table_version);
+
+        header.year = if version < 4 {
+            reader.read_bits(bytes_size)? // year of century
+        } else {
+            reader.read_bits(2 * bytes_size)?
+        };
+        affiche!(self, "Year : {}", header.year);
+        header.month = reader.read_bits(bytes_size)?;
+        affiche!(self, "Month : {}", header.month);
+        header.day = reader.read_bits(bytes_size)?;
+        affiche!(self, "Day : {}", header.day);
+        header.hour = reader.read_bits(bytes_size)?;
+        affiche!(self, "Hour : {}", header.hour);
+        header.minute = reader.read_bits(bytes_size)?;
+        affiche!(self, "Minute : {}", header.minute);
+        if version == 4 {
+            header.second = reader.read_bits(bytes_size)?;
+            affiche!(self, "Second : {}", header.second);
+        }
+
+        self.section1end(version, length_1, reader, bytes_size)?;
+
+        if header.sect2 {
+            self.section2(reader, bytes_size)?;
+        }
+
+         // SECTION 3 ( Data Description )
+        let length_3 = reader.read_bits(3 * bytes_size)?;
+        affiche!(self, "Length of section 3 (Data Description) : {}", length_3);
+        reader.read_bits(bytes_size)?; // Reserved, set to 0
+        header.number_of_subsets = reader.read_bits(2 * bytes_size)?;
+        affiche!(self, "Number of data subsets : {}", header.number_of_subsets);
+        let flags = reader.read_bits(bytes_size)?;
+        header.observed = flags & 0x80 != 0;
+        header.compressed = flags & 0x40 != 0;
+        affiche!(self, "Observed/Compressed Data : {}/{}", header.observed as u8, header.compressed as u8);
+
+
+        if length_3 < 7 {
+            self.nonconformity(BufrError::InvalidLength { section: 3, length: length_3, at: Location::default() }, reader)?;
+        }
+        let mut desc_bytes: Vec<u8> = Vec::new();
+
+        for _ in 0..length_3.saturating_sub(7) {
+            desc_bytes.push(reader.read_bits(bytes_size)? as u8);
+        }
+        for pair in desc_bytes.chunks_exact(2) { // an odd trailing octet is padding
+            header.descriptors.push(bytes_desc(pair[0], pair[1]));
+        }
+
+        if self.affiche_descriptors {
+            ecrit!("Descriptors : {:?}", header.descriptors);
+        }
+
+
+        Ok(Some(header))
+    }
+
+    fn clear_message(&mut self) {
+        self.datas_total.clear(); // Clear data for each message
+        self.datas_unites.clear();
+        self.datas_subsets.clear();
+        self.datas_trees.clear();
+        self.warnings.clear();
+        self.header = None;
+        self.subset_bits.clear();
+        if let Some(trace) = self.trace.as_mut() {
+            trace.clear();
+        }
+    }
+
+    pub fn decode_bufr_message<R: Read>(&mut self, reader: &mut BitReader<R>, bytes_size: u32) -> Result<Option<Datas>, BufrError> {
+        self.clear_message();
+
+        let Some(header) = self.read_header(reader, bytes_size)? else {
+            return Ok(None);
+        };
+        if !header.is_supported() {
+            return Err(self.locate(BufrError::UnsupportedEdition { edition: header.edition, at: Location::default() }, reader));
+        }
+        self.decode_data(reader, bytes_size, header).map(Some).map_err(|e| self.locate(e, reader))
+    }
+
+    // Next message whose header satisfies `predicate`, the others (and those of an unsupported
+    // edition) are skipped by their total length without reading Section 4
+    pub fn decode_matching<R: Read, P: FnMut(&Header) -> bool>(&mut self, reader: &mut BitReader<R>, bytes_size: u32, mut predicate: P) -> Result<Option<Datas>, BufrError> {
+        loop {
+            self.clear_message();
+            let Some(header) = self.read_header(reader, bytes_size)? else {
+                return Ok(None);
+            };
+            if header.is_supported() && predicate(&header) {
+                return self.decode_data(reader, bytes_size, header).map(Some).map_err(|e| self.locate(e, reader));
+            }
+            skip_rest(reader, &header).map_err(|e| self.locate(e, reader))?;
+            affiche!(self, " ----------- BUFR MESSAGE SKIPPED -----------");
+        }
+    }
+
+    // Section 4 and 5 of the message `header` was read from
+    fn decode_data<R: Read>(&mut self, reader: &mut BitReader<R>, bytes_size: u32, header: Header) -> Result<Datas, BufrError> {
+         // LOAD TABLES - only reloaded when the versions differ from the previous message
+        if let Err(e) = self.load_tables(header.master_table_version, header.centre, header.local_table_version) {
+            self.nonconformity(e, reader)?;
+        }
+
+
+        // SECTION 4 ( Datas )
+        self.start_4 = reader.position();
+        let length_4 = reader.read_bits(3 * bytes_size)?;
+        affiche!(self, "Length of section 4 (Datas) : {}", length_4);
+        reader.read_bits(bytes_size)?; // Reserved, SET TO 0
+
+        let start_4 = reader.position();
+        self.end_4 = start_4 + (length_4 as u64).saturating_sub(4) * 8;
+        self.values = 0;
+
+        self.path.clear();
+        let tables_d = [&self.dico_l_d, &self.dico_m_d];
+        let tree = self.expander.expand_cached(&header.descriptors, &tables_d)?;
+        self.compressed = header.compressed;
+        if header.compressed {
+            // the subsets are decoded together and share one tree
+            self.reset_operators();
+            self.datas_subsets = vec![Vec::new(); header.number_of_subsets as usize];
+            let decoded = Rc::new(self.decode_nodes(&tree, reader)?);
+            self.datas_trees = vec![decoded; header.number_of_subsets as usize];
+            for value in self.datas_subsets.iter().flatten() {
+                self.datas_total.entry(value.description.clone()).or_default().push(value.value.unwrap_or(f64::NAN));
+                self.datas_unites.entry(value.description.clone()).or_insert(value.unit.clone());
+            }
+        } else {
+            for _ in 0..header.number_of_subsets {
+                self.reset_operators(); // Table C operators don't carry over from one subset to the next
+                self.datas_subsets.push(Vec::new());
+                let first_bit = reader.position() - self.start_4;
+                let decoded = self.decode_nodes(&tree, reader)?;
+                self.datas_trees.push(Rc::new(decoded));
+                self.subset_bits.push(first_bit..reader.position() - self.start_4);
+            }
+        }
+
+        // Section 4 is padded to an octet boundary, and by editions 2 and 3 (often 4 too) to an even length
+        let consumed = reader.position() - start_4;
+        let section_4_bits = (length_4 as u64).saturating_sub(4) * 8;
+        if consumed > section_4_bits {
+            self.nonconformity(BufrError::InvalidLength { section: 4, length: length_4, at: Location::default() }, reader)?;
+        } else if section_4_bits - consumed.next_multiple_of(8) > 8 {
+            let bits = section_4_bits - consumed;
+            self.nonconformity(BufrError::LeftoverBits { bits, at: Location::default() }, reader)?;
+        }
+        reader.skip_bits(section_4_bits.saturating_sub(consumed))?;
+
+        affiche!(self, " ** END OF DATAS **");
+
+        affiche!(self, "DATAS DESCRIPTORS NUMBER: {}", self.datas_total.len());
+        affiche!(self, "DATAS :");
+        for (key, value) in &self.datas_total {
+            if value.len() < 10 {
+                affiche!(self, "  {} : {:?} ({})", key, value, self.datas_unites.get(key).map(|u| u.to_string()).unwrap_or_default());
+            } else {
+                affiche!(self, "  {} ( {} data(s))", key, value.len());
+            }
+        }
+
+
+        let end = reader.read_bits(4 * bytes_size)?; // (7777 =)  End of BUFR message
+        if end != 0x37373737 {
+            self.nonconformity(BufrError::BadEndMarker { found: end.to_be_bytes(), at: Location::default() }, reader)?;
+        }
+        if reader.position() != (header.offset + header.total_length as u64) * 8 {
+            self.nonconformity(BufrError::InvalidLength { section: 0, length: header.total_length, at: Location::default() }, reader)?;
+        }
+        self.header = Some(header);
+
+        affiche!(self, " ----------- END OF BUFR MESSAGE -----------");
+        Ok(self.datas_total.clone())
+    }
+
+
+    fn section1_v2<R: Read>(&mut self, reader: &mut BitReader<R>, bytes_size: u32, header: &mut Header) -> Result<u32, BufrError> {
+        let length_1 = reader.read_bits(3 * bytes_size)?;
+        affiche!(self, "Length of section 1 : {}", length_1);
+        header.master_table = reader.read_bits(bytes_size)?;
+        affiche!(self, "Bufr master table : {}", header.master_table);
+        header.sub_centre = reader.read_bits(bytes_size)?;
+        affiche!(self, "Identification of originating/generating sub-centre : {}", header.sub_centre);
+        header.centre = reader.read_bits(bytes_size)?;
+        affiche!(self, "Identification of originating/generating centre : {}", header.centre);
+        header.update_sequence = reader.read_bits(bytes_size)?;
+        affiche!(self, "Update sequence number : {}", header.update_sequence);
+        let sect2_indicator = reader.read_bits(bytes_size)?;
+        header.sect2 = sect2_indicator & 0x80 != 0; // flag in the leftmost bit
+        affiche!(self, "Optional (1) / No Optional (0) section follows : {} ({})", sect2_indicator, if header.sect2 { "yes" } else { "no" });
+        header.category = reader.read_bits(bytes_size)?;
+        affiche!(self, "Data Category (Table A) : {}", header.category);
+        header.subcategory = reader.read_bits(bytes_size)?;
+        affiche!(self, "Data category sub-category : {}", header.subcategory);
+        Ok(length_1)
+    }
+
+    fn section1_v4<R: Read>(&mut self, reader: &mut BitReader<R>, bytes_size: u32, header: &mut Header) -> Result<u32, BufrError> {
+        let length_1 = reader.read_bits(3 * bytes_size)?;
+        affiche!(self, "Length of section 1 : {}", length_1);
+        header.master_table = reader.read_bits(bytes_size)?;
+        affiche!(self, "Bufr master table : {}", header.master_table);
+        header.centre = reader.read_bits(2 * bytes_size)?;
+        affiche!(self, "Identification of originating/generating centre : {}", header.centre);
+        header.sub_centre = reader.read_bits(2 * bytes_size)?;
+        affiche!(self, "Identification of originating/generating sub-centre : {}", header.sub_centre);
+        header.update_sequence = reader.read_bits(bytes_size)?;
+        affiche!(self, "Update sequence number : {}", header.update_sequence);
+        let sect2_indicator = reader.read_bits(bytes_size)?;
+        header.sect2 = sect2_indicator & 0x80 != 0; // flag in the leftmost bit
+        affiche!(self, "Optional (1) / No Optional (0) section follows : {}", sect2_indicator);
+        header.category = reader.read_bits(bytes_size)?;
+        affiche!(self, "Data Category (Table A) : {}", header.category);
+        header.subcategory = reader.read_bits(bytes_size)?;
+        affiche!(self, "International data sub-category : {}", header.subcategory);
+        header.local_subcategory = reader.read_bits(bytes_size)?;
+        affiche!(self, "Local sub-category : {}", header.local_subcategory);
+        Ok(length_1)
+    }
+
+
+    fn section1end<R: Read>(&mut self, version: u32, length_1: u32, reader: &mut BitReader<R>, bytes_size: u32) -> Result<(), BufrError> {
+        let lim = if version < 4 { 17 } else { 22 };
+        if length_1 < lim {
+            self.nonconformity(BufrError::InvalidLength { section: 1, length: length_1, at: Location::default() }, reader)?;
+        }
+        if length_1 > lim {
+            affiche!(self, "SECTION 1 ending : ");
+            for _ in 0..(length_1 - lim) {
+                let x = reader.read_bits(bytes_size)?;
+                affiche!(self, "{}  {}", x, x as u8 as char);
+            }
+            affiche!(self, "END OF SECTION 1");
+        }
+        Ok(())
+    }
+
+
+    fn section2<R: Read>(&mut self, reader: &mut BitReader<R>, bytes_size: u32) -> Result<(), BufrError> {
+        let length_2 = reader.read_bits(3 * bytes_size)?;
+        affiche!(self, "Length of section 2 : {}", length_2);
+        if length_2 < 4 {
+            self.nonconformity(BufrError::InvalidLength { section: 2, length: length_2, at: Location::default() }, reader)?;
+        }
+        reader.read_bits(bytes_size)?; // Reserved, set to 0
+        for _ in 0..length_2.saturating_sub(4) {
+            let x = reader.read_bits(bytes_size)?;
+            affiche!(self, "{}  {}", x, x as u8 as char);
+        }
+        affiche!(self, " END OF SECTION 2");
+        Ok(())
+    }
+
+    // Tables of a (master version, centre, local version) set, kept until another set is asked for.
+    // The local tables are optional, a missing master table is loaded empty and reported.
+    pub fn load_tables(&mut self, master_table_version: u32, center_id: u32, local_table_version: u32) -> Result<(), BufrError> {
+        if self.tables_loaded == Some((master_table_version, center_id, local_table_version)) {
+            return Ok(());
+        }
+        self.tables_loaded = Some((master_table_version, center_id, local_table_version));
+        self.expander.clear();
+        self.selected.clear();
+
+        let mut diagnostics: Vec<TableDiagnostic> = Vec::new();
+        let mut missing = None; // first master table that can't be read
+
+        let table_b_path = self.table_files.master_b_path(Path::new(&self.dir_path_table), master_table_version);
+        match tables::load_table_b(&table_b_path, &mut diagnostics) {
+            Ok(dico) => {
+                self.dico_m_b = dico;
+            }
+            Err(e) => {
+                self.dico_m_b = HashMap::new();
+                missing = Some(BufrError::TableNotFound { path: table_b_path, reason: e.to_string(), at: Location::default() });
+            }
+        }
+
+        let table_d_path = self.table_files.master_d_path(Path::new(&self.dir_path_table), master_table_version);
+        match tables::load_table_d(&table_d_path, &mut diagnostics) {
+            Ok(dico) => {
+                self.dico_m_d = dico;
+            }
+            Err(e) => {
+                self.dico_m_d = HashMap::new();
+                missing = missing.or(Some(BufrError::TableNotFound { path: table_d_path, reason: e.to_string(), at: Location::default() }));
+            }
+        }
+
+        let local_table_b_path = self.table_files.local_b_path(Path::new(&self.dir_path_table), center_id, local_table_version);
+        match tables::load_table_b(&local_table_b_path, &mut diagnostics) {
+            Ok(dico) => {
+                self.dico_l_b = dico;
+            }
+            Err(e) => {
+                self.warn(Warning::LocalTableNotFound { path: local_table_b_path, reason: e.to_string() });
+                self.dico_l_b = HashMap::new();
+            }
+        }
+
+        let local_table_d_path = self.table_files.local_d_path(Path::new(&self.dir_path_table), center_id, local_table_version);
+        match tables::load_table_d(&local_table_d_path, &mut diagnostics) {
+            Ok(dico) => {
+                self.dico_l_d = dico;
+            }
+            Err(e) => {
+                self.warn(Warning::LocalTableNotFound { path: local_table_d_path, reason: e.to_string() });
+                self.dico_l_d = HashMap::new();
+            }
+        }
+
+        if self.affiche_descriptors {
+            for diagnostic in &diagnostics {
+                ecrit!(" ** {}", diagnostic);
+            }
+        }
+        if let Some(e) = missing {
+            // the next message tries again
+            self.tables_loaded = None;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    // Table B entry of an element descriptor, or expansion tree of a sequence with the width of
+    // each element and the total bit length when it doesn't depend on the data
+    // `descriptor` as typed by a user, 3-1-192 for 3-01-192
+    pub fn describe(&self, descriptor: &str) -> Result<String, BufrError> {
+        let invalid = |reason: &str| BufrError::InvalidDescriptor { descriptor: descriptor.to_string(), reason: reason.to_string(), at: Location::default() };
+        let key = tables::canonical_fxy(descriptor).ok_or_else(|| invalid("not an F-XX-YYY descriptor"))?;
+        let (f, _, _) = expand::fxy(&key).ok_or_else(|| invalid("not an F-XX-YYY descriptor"))?;
+        let unknown = || BufrError::UnknownDescriptor { descriptor: key.clone(), at: Location::default() };
+        let mut out = String::new();
+        // writing to a String doesn't fail
+        let written: fmt::Result = match f {
+            0 => {
+                let entry = self.descri(&key).ok_or_else(unknown)?;
+                let table = if self.dico_l_b.contains_key(&key) { "local" } else { "master" };
+                (|| {
+                    writeln!(out, "{} {} (Table B {})", key, entry.get("Description").map(String::as_str).unwrap_or(""), table)?;
+                    for field in ["Unit", "Scale", "Ref_Val", "Data_width_bits"] {
+                        writeln!(out, "  {:<16}{}", field, entry.get(field).map(String::as_str).unwrap_or(""))?;
+                    }
+                    Ok(())
+                })()
+            }
+            3 => {
+                if !self.dico_l_d.contains_key(&key) && !self.dico_m_d.contains_key(&key) {
+                    return Err(unknown());
+                }
+                let table = if self.dico_l_d.contains_key(&key) { "local" } else { "master" };
+                let tree = expand::expand(std::slice::from_ref(&key), &[&self.dico_l_d, &self.dico_m_d], self.expander.max_depth, self.expander.max_nodes)?;
+                let children = match tree.first() {
+                    Some(Node::Sequence { children, .. }) => children.as_slice(),
+                    _ => &[],
+                };
+                (|| {
+                    writeln!(out, "{} (Table D {})", key, table)?;
+                    let mut widths = (0, 0); // (2-01 increment, 2-08 width)
+                    match self.describe_nodes(children, 1, &mut widths, &mut out)? {
+                        Some(bits) => writeln!(out, "Total : {} bits", bits),
+                        None => writeln!(out, "Total : depends on the data"),
+                    }
+                })()
+            }
+            _ => return Err(invalid("neither an element nor a sequence descriptor")),
+        };
+        written.expect("formatting into a String");
+        Ok(out)
+    }
+
+    // Prints `nodes` indented by `depth`, returns their bit length if known
+    fn describe_nodes(&self, nodes: &[Node], depth: usize, widths: &mut (i32, u32), out: &mut String) -> Result<Option<u64>, fmt::Error> {
+        let indent = "  ".repeat(depth);
+        let mut total = Some(0u64);
+        for node in nodes {
+            let bits = match node {
+                Node::Element(descriptor) => match self.descri(descriptor) {
+                    Some(entry) => {
+                        let mut width = entry.get("Data_width_bits").and_then(|w| w.parse::<u32>().ok()).unwrap_or(0);
+                        if widths.1 != 0 {
+                            width = widths.1;
+                        }
+                        width = width.saturating_add_signed(widths.0);
+                        let description = entry.get("Description").map(String::as_str).unwrap_or("");
+                        let unit = entry.get("Unit").map(String::as_str).unwrap_or("");
+                        writeln!(out, "{}{} {} ({}) {} bits", indent, descriptor, description, unit, width)?;
+                        Some(width as u64)
+                    }
+                    None => {
+                        writeln!(out, "{}{} UNKNOWN", indent, descriptor)?;
+                        None
+                    }
+                },
+                Node::Operator(descriptor) => {
+                    let (_, x, y) = expand::fxy(descriptor).unwrap_or((2, 0, 0));
+                    writeln!(out, "{}{} operator", indent, descriptor)?;
+                    match x {
+                        1 => {
+                            widths.0 = if y == 0 { 0 } else { y as i32 - 128 };
+                            Some(0)
+                        }
+                        8 => {
+                            widths.1 = 8 * y as u32;
+                            Some(0)
+                        }
+                        2 => Some(0),
+                        _ => None, // bits read by the operator itself aren't known here
+                    }
+                }
+                Node::Sequence { descriptor, children } => {
+                    writeln!(out, "{}{}", indent, descriptor)?;
+                    self.describe_nodes(children, depth + 1, widths, out)?
+                }
+                Node::Replication { descriptor, count, factor, children } => {
+                    match factor {
+                        Some(factor) => writeln!(out, "{}{} delayed replication, factor {}", indent, descriptor, factor)?,
+                        None => writeln!(out, "{}{} replication x {}", indent, descriptor, count)?,
+                    }
+                    let bits = self.describe_nodes(children, depth + 1, widths, out)?;
+                    match factor {
+                        Some(_) => None,
+                        None => bits.map(|b| b * *count as u64),
+                    }
+                }
+            };
+            total = match (total, bits) {
+                (Some(total), Some(bits)) => Some(total + bits),
+                _ => None,
+            };
+        }
+        Ok(total)
+    }
+}
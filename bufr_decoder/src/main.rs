@@ -1,641 +1,689 @@
-use std::fs::File;
-use std::io::{self, Read, BufReader};
+use std::collections::BTreeSet;
 use std::error::Error;
-use std::collections::HashMap;
-use csv::ReaderBuilder;
-use std::path::Path;
-
-struct BitReader<R: Read> {
-    input: R,
-    accumulator: u8,
-    bcount: u8,
-    read: usize,
-    total_read: usize,
-}
-
-impl<R: Read> BitReader<R> {
-    pub fn new(input: R) -> Self {
-        BitReader {
-            input,
-            accumulator: 0,
-            bcount: 0,
-            read: 0,
-            total_read: 0,
-        }
-    }
-
-    fn _readbit(&mut self) -> Result<u8, Box<dyn Error>> {
-        if self.bcount == 0 {
-            let mut buffer = [0];
-            let bytes_read = self.input.read(&mut buffer)?;
-            if bytes_read == 0 {
-                return Err(From::from("End of file reached"));
-            }
-            self.accumulator = buffer[0];
-            self.bcount = 8;
-            self.read = bytes_read;
-        }
-        let rv = (self.accumulator & (1 << (self.bcount - 1))) >> (self.bcount - 1);
-        self.bcount -= 1;
-        Ok(rv)
-    }
-
-    pub fn read_bits(&mut self, n: u32) -> Result<u32, Box<dyn Error>> {
-        self.total_read += 1;
-        let mut v: u32 = 0;
-        for _ in 0..n {
-            v = (v << 1) | (self._readbit()? as u32);
-        }
-        Ok(v)
-    }
-}
-
-fn bits_to_bytes(chaine: u32) -> Result<String, Box<dyn Error>> {
-    let byte_number = chaine.leading_zeros() as u32 / 8;
-    let bin_array = chaine.to_be_bytes();
-    let bin_array_trimmed = &bin_array[byte_number as usize..];
-    let result = String::from_utf8(bin_array_trimmed.to_vec())?;
-    Ok(result)
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, StdoutLock, Write};
+use std::ops::{ControlFlow, RangeInclusive};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use memmap2::Mmap;
+
+#[cfg(feature = "arrow")]
+use bufr_decoder::arrow_export::{ParquetExporter, DEFAULT_BATCH_SIZE};
+use bufr_decoder::error::BufrError;
+use bufr_decoder::export::{CsvExporter, CsvOptions};
+use bufr_decoder::filter::{ElementSelection, HeaderFilter, ReferenceTime};
+use bufr_decoder::options::{DecodeOptions, Limits, Strictness};
+use bufr_decoder::projection::GridGeometry;
+use bufr_decoder::tables::TableFiles;
+use bufr_decoder::{encode, geotiff, json, netcdf, radar, messages, read_message, tables, BitReader, BufrDecoder, DataValue, Message};
+
+#[derive(Parser)]
+#[command(name = "bufr_decoder", version, about = "BUFR decoder for Météo-France data")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
 }
 
-fn bytes_desc(byt: u8) -> String {
-    if byt < 64 {
-        format!("0-{}-", byt)
-    } else if byt < 128 {
-        format!("1-{}-", byt - 64)
-    } else if byt < 192 {
-        format!("2-{}-", byt - 128)
-    } else {
-        format!("3-{}-", byt - 192)
-    }
+#[derive(Subcommand)]
+enum Command {
+    /// Decode BUFR files
+    Decode(DecodeArgs),
+    /// List the messages of BUFR files from their Sections 0 to 3
+    Ls {
+        /// BUFR files, - for standard input
+        #[arg(required = true)]
+        files: Vec<String>,
+        #[command(flatten)]
+        filter: FilterArgs,
+    },
+    /// Trace every descriptor read from Section 4 with its bit offset
+    Dump {
+        /// BUFR files, - for standard input
+        #[arg(required = true)]
+        files: Vec<String>,
+        /// Directory of the bufrtab*/localtab* CSV tables
+        #[arg(long, default_value = "tables")]
+        tables: String,
+        /// Only dump the Nth message (from 1) of each file
+        #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+        message: Option<u64>,
+    },
+    /// Table B entry or expanded Table D sequence of a descriptor
+    Describe {
+        #[arg(value_name = "F-XX-YYY")]
+        descriptor: String,
+        /// Master table version
+        #[arg(long, default_value_t = 16)]
+        master: u32,
+        /// Originating centre of the local tables
+        #[arg(long, default_value_t = 85)]
+        centre: u32,
+        /// Local table version
+        #[arg(long, default_value_t = 14)]
+        local: u32,
+        /// Directory of the bufrtab*/localtab* CSV tables
+        #[arg(long, default_value = "tables")]
+        tables: String,
+    },
+    /// Copy the matching messages, or some of their subsets, to a new BUFR file
+    Filter {
+        /// BUFR files, - for standard input
+        #[arg(required = true)]
+        files: Vec<String>,
+        /// Output BUFR file, - for standard output
+        #[arg(short, long)]
+        output: PathBuf,
+        /// Only keep these subsets (from 1) of uncompressed messages, e.g. 1,3,5-7. Each one is kept
+        /// once in the order of the message; a message with fewer subsets is an error
+        #[arg(long, value_delimiter = ',', value_parser = subset_range, value_name = "LIST")]
+        subsets: Option<Vec<RangeInclusive<usize>>>,
+        /// Directory of the bufrtab*/localtab* CSV tables, to find the subsets
+        #[arg(long, default_value = "tables")]
+        tables: String,
+        #[command(flatten)]
+        filter: FilterArgs,
+    },
+    /// Report every malformed row of a tables directory
+    CheckTables {
+        #[arg(default_value = "tables")]
+        dir: String,
+    },
 }
 
-// Define structs to hold table data, replacing pandas DataFrames
-#[derive(Debug)]
-struct TableBRecord {
-    f: String,
-    x: String,
-    y: String,
-    description: String,
-    unit: String,
-    scale: String,
-    reference_value: String,
-    data_width_bits: String,
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum Format {
+    /// Section by section report
+    Text,
+    /// One JSON document per message
+    Json,
+    /// One row per subset, one column per element
+    Csv,
+    /// As csv, tab separated
+    Tsv,
 }
 
-#[derive(Debug)]
-struct TableDRecord {
-    f: String,
-    x: String,
-    y: String,
-    df: String,
-    dx: String,
-    dy: String,
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum Mode {
+    /// Reject non-conformant messages
+    Strict,
+    /// Decode what can be, reporting each non-conformity as a warning
+    Lenient,
+    /// Decode what can be, silently
+    Ignore,
 }
 
-
-fn tables_b(file_path: &str) -> Result<Vec<TableBRecord>, Box<dyn Error>> {
-    let file = File::open(file_path)?;
-    let reader = BufReader::new(file);
-    let mut rdr = ReaderBuilder::new().delimiter(b';').has_headers(false).from_reader(reader);
-    let mut records = Vec::new();
-    for result in rdr.records() {
-        let record = result?;
-        if record.len() == 8 {
-            records.push(TableBRecord {
-                f: record[0].to_string(),
-                x: record[1].to_string(),
-                y: record[2].to_string(),
-                description: record[3].to_string(),
-                unit: record[4].to_string(),
-                scale: record[5].to_string(),
-                reference_value: record[6].to_string(),
-                data_width_bits: record[7].to_string(),
-            });
+impl From<Mode> for Strictness {
+    fn from(mode: Mode) -> Self {
+        match mode {
+            Mode::Strict => Strictness::Strict,
+            Mode::Lenient => Strictness::Lenient,
+            Mode::Ignore => Strictness::Ignore,
         }
     }
-    Ok(records)
 }
 
-fn tables_d(file_path: &str) -> Result<Vec<TableDRecord>, Box<dyn Error>> {
-    let file = File::open(file_path)?;
-    let reader = BufReader::new(file);
-    let mut rdr = ReaderBuilder::new().delimiter(b';').has_headers(false).from_reader(reader);
-    let mut records = Vec::new();
-    for result in rdr.records() {
-        let record = result?;
-         if record.len() == 6 {
-            records.push(TableDRecord {
-                f: record[0].to_string(),
-                x: record[1].to_string(),
-                y: record[2].to_string(),
-                df: record[3].to_string(),
-                dx: record[4].to_string(),
-                dy: record[5].to_string(),
-            });
-        }
-    }
-    Ok(records)
+// Section 1 criteria, the other messages are skipped without reading Section 4
+#[derive(Args)]
+#[command(next_help_heading = "Message filters")]
+struct FilterArgs {
+    /// Data category
+    #[arg(long)]
+    category: Option<u32>,
+    /// Originating centre
+    #[arg(long)]
+    centre: Option<u32>,
+    #[arg(long)]
+    sub_centre: Option<u32>,
+    /// Master table version
+    #[arg(long)]
+    master: Option<u32>,
+    /// Local table version
+    #[arg(long)]
+    local: Option<u32>,
+    /// Reference time from, inclusive : YYYY[-MM[-DD[THH[:MM[:SS]]]]] starting with its first second
+    #[arg(long, value_name = "TIME", value_parser = ReferenceTime::since)]
+    since: Option<ReferenceTime>,
+    /// Reference time until, inclusive : YYYY[-MM[-DD[THH[:MM[:SS]]]]] up to its last second
+    #[arg(long, value_name = "TIME", value_parser = ReferenceTime::until)]
+    until: Option<ReferenceTime>,
+    #[arg(long, value_name = "N")]
+    min_subsets: Option<u32>,
+    #[arg(long, value_name = "N")]
+    max_subsets: Option<u32>,
 }
 
-
-fn dico_descriptor_b(table_b_records: Vec<TableBRecord>) -> Result<HashMap<String, HashMap<String, String>>, Box<dyn Error>> {
-    let mut dico_desc: HashMap<String, HashMap<String, String>> = HashMap::new();
-    for record in table_b_records {
-        let key = format!("{}-{}-{}", record.f, record.x, record.y);
-        let mut value_map: HashMap<String, String> = HashMap::new();
-        value_map.insert("Description".to_string(), record.description.clone());
-        value_map.insert("Unit".to_string(), record.unit.clone());
-        value_map.insert("Scale".to_string(), record.scale.clone());
-        value_map.insert("Ref_Val".to_string(), record.reference_value.clone());
-        value_map.insert("Data_width_bits".to_string(), record.data_width_bits.clone());
-        dico_desc.insert(key, value_map);
+impl FilterArgs {
+    fn header_filter(&self) -> HeaderFilter {
+        HeaderFilter {
+            category: self.category,
+            centre: self.centre,
+            sub_centre: self.sub_centre,
+            master_table_version: self.master,
+            local_table_version: self.local,
+            since: self.since,
+            until: self.until,
+            min_subsets: self.min_subsets,
+            max_subsets: self.max_subsets,
+        }
     }
-    Ok(dico_desc)
 }
 
+// Bounds for untrusted files, the defaults when not given
+#[derive(Args)]
+#[command(next_help_heading = "Limits")]
+struct LimitArgs {
+    /// Octets of a message
+    #[arg(long, value_name = "OCTETS")]
+    max_message_length: Option<u32>,
+    /// Expanded descriptors of a message
+    #[arg(long, value_name = "N")]
+    max_descriptors: Option<usize>,
+    /// Repetitions of a replication
+    #[arg(long, value_name = "N")]
+    max_replication: Option<u32>,
+    /// Nesting of sequences and replications
+    #[arg(long, value_name = "N")]
+    max_depth: Option<usize>,
+    /// Octets of a CCITT IA5 element
+    #[arg(long, value_name = "OCTETS")]
+    max_string_length: Option<u32>,
+    /// Values decoded from a message, all subsets together
+    #[arg(long, value_name = "N")]
+    max_values: Option<u64>,
+}
 
-fn dico_descriptor_d(table_d_records: Vec<TableDRecord>) -> Result<HashMap<String, Vec<String>>, Box<dyn Error>> {
-    let mut dico_desc: HashMap<String, Vec<String>> = HashMap::new();
-    let mut listed: Vec<String> = Vec::new();
-    let mut key1 = String::new();
-
-    for record in table_d_records {
-        if !record.f.is_empty() && record.f.ends_with("3") {
-             dico_desc.insert(key1.clone(), listed.clone());
-             listed = Vec::new();
-             key1 = format!("{}-{}-{}", record.f, record.x, record.y);
-             listed.push(format!("{}-{}-{}", record.df, record.dx, record.dy));
-        } else if !record.df.is_empty() {
-             listed.push(format!("{}-{}-{}", record.df, record.dx, record.dy));
+impl LimitArgs {
+    fn limits(&self) -> Limits {
+        let default = Limits::default();
+        Limits {
+            max_message_length: self.max_message_length.unwrap_or(default.max_message_length),
+            max_descriptors: self.max_descriptors.unwrap_or(default.max_descriptors),
+            max_replication: self.max_replication.unwrap_or(default.max_replication),
+            max_depth: self.max_depth.unwrap_or(default.max_depth),
+            max_string_length: self.max_string_length.unwrap_or(default.max_string_length),
+            max_values: self.max_values.unwrap_or(default.max_values),
         }
     }
-    dico_desc.insert(key1.clone(), listed.clone());
-    Ok(dico_desc)
 }
 
-struct BufrDecoder {
-    dir_path_table: String,
-    fic_tab_b: String,
-    fic_tab_d: String,
-    fic_local_tab_b: String,
-    fic_local_tab_d: String,
-    affiche_descriptors: bool,
-    dico_m_b: HashMap<String, HashMap<String, String>>,
-    dico_m_d: HashMap<String, Vec<String>>,
-    dico_l_b: HashMap<String, HashMap<String, String>>,
-    dico_l_d: HashMap<String, Vec<String>>,
-    datas_total: HashMap<String, Vec<f64>>, // Store decoded data
-    datas_unites: HashMap<String, String>,
-    bit_width_plus: u32,
-    bit_scale_plus: i32,
-    bit_ref_changed: bool,
-    bit_new_ref: HashMap<String, f64>,
-    bit_new_width: u32,
+#[derive(Args)]
+struct DecodeArgs {
+    /// BUFR files, - for standard input
+    #[arg(required = true)]
+    files: Vec<String>,
+    /// Directory of the bufrtab*/localtab* CSV tables
+    #[arg(long, default_value = "tables")]
+    tables: String,
+    /// Printed on stdout. NetCDF goes to a file with --netcdf, in the classic 64-bit offset
+    /// format (CDF-2) and not NetCDF-4 / HDF5 : every NetCDF-4 reader opens it
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+    /// Trace every descriptor (text format)
+    #[arg(short, long)]
+    verbose: bool,
+    /// Decode at most N messages of each file
+    #[arg(long, value_name = "N")]
+    max_messages: Option<usize>,
+    /// Only decode the Nth message (from 1) of each file
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    message: Option<u64>,
+    /// Only decode these elements : FXY descriptors or Table B description patterns ('*' wildcard)
+    #[arg(long, value_delimiter = ',', value_name = "ELEMENTS")]
+    select: Option<Vec<String>>,
+    /// Missing master tables, unknown descriptors, wrong section lengths, bad "7777" or bits
+    /// left over in Section 4
+    #[arg(long, value_enum, default_value_t = Mode::Strict)]
+    strictness: Mode,
+    /// CSV/TSV : FXY descriptors of the columns to keep
+    #[arg(long, value_delimiter = ',', value_parser = descriptor_arg, value_name = "F-XX-YYY")]
+    columns: Option<Vec<String>>,
+    /// CSV/TSV : units in the header
+    #[arg(long)]
+    units: bool,
+    /// Also write the radar images to DIR/<file>_<message>_<image>.tif
+    #[arg(long, value_name = "DIR")]
+    geotiff: Option<PathBuf>,
+    /// Also write the subsets of all the messages to a CF-1.8 NetCDF classic file (CDF-2, 64-bit
+    /// offsets), not NetCDF-4
+    #[arg(long, value_name = "FILE")]
+    netcdf: Option<PathBuf>,
+    /// Also write the subsets to a Parquet file
+    #[cfg(feature = "arrow")]
+    #[arg(long, value_name = "FILE")]
+    parquet: Option<PathBuf>,
+    #[command(flatten)]
+    filter: FilterArgs,
+    #[command(flatten)]
+    limits: LimitArgs,
 }
 
-impl BufrDecoder {
-    pub fn new(dir_path_table: String, fic_tab_b: String, fic_tab_d: String, fic_local_tab_b: String, fic_local_tab_d: String, affiche_descriptors: bool) -> Self {
-        BufrDecoder {
-            dir_path_table,
-            fic_tab_b,
-            fic_tab_d,
-            fic_local_tab_b,
-            fic_local_tab_d,
-            affiche_descriptors,
-            dico_m_b: HashMap::new(),
-            dico_m_d: HashMap::new(),
-            dico_l_b: HashMap::new(),
-            dico_l_d: HashMap::new(),
-            datas_total: HashMap::new(), // Initialize data storage
-            datas_unites: HashMap::new(),
-            bit_width_plus: 0,
-            bit_scale_plus: 0,
-            bit_ref_changed: false,
-            bit_new_ref: HashMap::new(),
-            bit_new_width: 0,
-        }
-    }
+// Where the decoded messages go, shared by all the files of a run
+struct Outputs {
+    format: Format,
+    csv: Option<CsvExporter<StdoutLock<'static>>>,
+    geotiff: Option<PathBuf>,
+    netcdf: Option<(PathBuf, Vec<Vec<DataValue>>)>, // written by finish
+    #[cfg(feature = "arrow")]
+    parquet: Option<ParquetExporter<File>>,
+}
 
-    fn descri(&self, desc: &str) -> Option<&HashMap<String, String>> {
-        if let Some(r) = self.dico_l_b.get(desc) {
-            if self.affiche_descriptors {
-                println!("{} : {:?}", desc, r);
-            }
-            return Some(r);
-        } else if let Some(r) = self.dico_l_d.get(desc) {
-            if self.affiche_descriptors {
-                println!("{} : {:?}", desc, r);
-            }
-            return None; // D table returns Vec<String>, not HashMap, so return None here and handle D table lookups differently if needed
-        } else if let Some(r) = self.dico_m_b.get(desc) {
-            if self.affiche_descriptors {
-                println!("{} : {:?}", desc, r);
-            }
-            return Some(r);
-        } else if let Some(r) = self.dico_m_d.get(desc) {
-            if self.affiche_descriptors {
-                println!("{} : {:?}", desc, r);
+impl Outputs {
+    // `name` : file stem and message number, for the outputs written per message
+    fn write(&mut self, decoder: &BufrDecoder, name: &str) -> Result<(), Box<dyn Error>> {
+        if self.format == Format::Json {
+            if let Some(message) = json::last_message(decoder) {
+                writeln!(io::stdout().lock(), "{}", serde_json::to_string_pretty(&message)?)?;
             }
-            return None; // Same as above for master D table
-        } else {
-            if self.affiche_descriptors {
-                println!("{} UNKNOWN", desc);
-            }
-            return None;
         }
-    }
-
-    fn simple_desc<R: Read>(&mut self, desc_elt: &str, reader: &mut BitReader<R>) -> Result<(), Box<dyn Error>> {
-        if let Some(descript_elt) = self.descri(desc_elt) {
-            let mut longueur: u32 = descript_elt.get("Data_width_bits").unwrap().parse::<u32>().unwrap_or(0); // Get data width from descriptor
-            if self.bit_new_width != 0 {
-                longueur = self.bit_new_width;
-            }
-            longueur += self.bit_width_plus;
-
-
-            let description = descript_elt.get("Description").unwrap_or(&String::from("No Description")).clone();
-            if self.affiche_descriptors {
-                println!("longueur : {}, Description : {}", longueur, description);
-            }
-
-            let tot_bits = reader.read_bits(longueur)?;
-            let scale: f64 = descript_elt.get("Scale").unwrap_or(&String::from("0")).parse::<f64>().unwrap_or(0.0) + self.bit_scale_plus as f64;
-            let mut ref_val: f64 = descript_elt.get("Ref_Val").unwrap_or(&String::from("0")).parse::<f64>().unwrap_or(0.0);
-
-            if self.bit_ref_changed && self.bit_new_ref.contains_key(desc_elt) {
-                ref_val += self.bit_new_ref.get(desc_elt).unwrap_or(&0.0);
-            }
-
-
-            let val_data = (tot_bits as f64 + ref_val) / 10f64.powf(scale);
-
-            let unit = descript_elt.get("Unit").unwrap_or(&String::from("")).clone();
-            if unit == "CCITT IA5" {
-                match bits_to_bytes(tot_bits) {
-                    Ok(byte_str) => println!("  \"{}\"", byte_str),
-                    Err(_) => println!("  (Non-printable CCITT IA5 data)"),
+        if let Some(csv) = self.csv.as_mut() {
+            csv.write_subsets(decoder.subsets())?;
+        }
+        if let Some((_, subsets)) = self.netcdf.as_mut() {
+            subsets.extend_from_slice(decoder.subsets());
+        }
+        if let Some(dir) = &self.geotiff {
+            let mut n = 0;
+            for subset in decoder.subsets() {
+                for image in radar::images(subset)? {
+                    n += 1;
+                    let geometry = GridGeometry::from_values(subset, &image)?;
+                    geotiff::save_geotiff(&dir.join(format!("{}_{}.tif", name, n)), &image, &geometry)?;
                 }
-            } else if self.affiche_descriptors {
-                println!("  = {} {}", val_data, unit);
             }
-
-            self.datas_total.entry(description.clone()).or_insert_with(Vec::new).push(val_data);
-            self.datas_unites.entry(description).or_insert(unit);
         }
-        Ok(())
-    }
-
-    fn descri_table_c<R: Read>(&mut self, reader: &mut BitReader<R>, descriptor: &str, descriptors: &mut Vec<String>, index_descript: &mut usize) -> Result<(), Box<dyn Error>> {
-        let new_ref = descriptor.split('-').nth(2).unwrap_or("0").parse::<i32>().unwrap_or(0);
-        match descriptor.split('-').nth(1).unwrap_or("0").parse::<i32>().unwrap_or(0) {
-            1 => { // change data width
-                self.bit_width_plus = if new_ref == 0 { 0 } else { (new_ref - 128) as u32 };
-            },
-            2 => { // change scale
-                self.bit_scale_plus = if new_ref == 0 { 0 } else { new_ref - 128 };
-            },
-            3 => { // change reference value
-                if new_ref > 0 {
-                    self.bit_ref_changed = true;
-                    let ybits = descriptor.split('-').nth(2).unwrap_or("0").parse::<u32>().unwrap_or(0);
-                    *index_descript += 1;
-                    while *index_descript < descriptors.len() && descriptors[*index_descript] != "2-3-255" {
-                        let desc_new = &descriptors[*index_descript];
-                        let result = reader.read_bits(ybits)?;
-                        let ref_val = if result >= 2u32.pow(ybits - 1) {
-                            -1.0 * (result - 2u32.pow(ybits - 1)) as f64
-                        } else {
-                            result as f64
-                        };
-                        self.bit_new_ref.insert(desc_new.clone(), ref_val);
-                        *index_descript += 1;
-                    }
-                    if *index_descript < descriptors.len() && descriptors[*index_descript] == "2-3-255" {
-                        // consume "2-3-255"
-                    }
-                } else {
-                    self.bit_ref_changed = false;
-                    self.bit_new_ref.clear();
-                }
-                 *index_descript -= 1; // to compensate for the increment in main loop
-            },
-            8 => { // change bit width
-                self.bit_new_width = if new_ref == 0 { 0 } else { 8 * new_ref as u32 };
-            },
-            _ => {}
+        #[cfg(feature = "arrow")]
+        if let Some(parquet) = self.parquet.as_mut() {
+            parquet.write_subsets(decoder.subsets())?;
         }
         Ok(())
     }
 
-
-    fn decode_bufr_message<R: Read>(&mut self, reader: &mut BitReader<R>, bytes_size: u32) -> Result<Option<HashMap<String, Vec<f64>>>, Box<dyn Error>> {
-        println!(" ----------- BEGIN OF BUFR MESSAGE -----------");
-        self.datas_total.clear(); // Clear data for each message
-        self.datas_unites.clear();
-        self.bit_width_plus = 0;
-        self.bit_scale_plus = 0;
-        self.bit_ref_changed = false;
-        self.bit_new_ref.clear();
-        self.bit_new_width = 0;
-
-
-        let x = reader.read_bits(4 * bytes_size)?;
-         if x != 0x42554652 { // BUFR magic number
-            return Ok(None);
+    fn finish(self) -> Result<(), Box<dyn Error>> {
+        if let Some(mut csv) = self.csv {
+            csv.flush()?;
         }
-        println!("Entete: BUFR");
-
-        let total_length = reader.read_bits(3 * bytes_size)?;
-        println!("Total length of Bufr message in bytes : {}", total_length);
-        let edition_number = reader.read_bits(1 * bytes_size)?;
-        println!("Bufr Edition number : {}", edition_number);
-
-
-        // SECTION 1
-        let version = edition_number; // Assuming edition number is version for now
-        let (length_1, master_table_number, sub_center_id, center_id, sect2) =
-            if version == 2 {
-                self.section1_v2(reader, bytes_size)?
-            } else if version == 4 {
-                self.section1_v4(reader, bytes_size)?
-            } else {
-                println!("Version Inconnue");
-                return Ok(None);
-            };
-
-        let master_table_version = reader.read_bits(1 * bytes_size)?;
-        println!("Version number of master table used : {}", master_table_version);
-        let local_table_version = reader.read_bits(1 * bytes_size)?;
-        println!("Version number of local tables used : {}", local_table_version);
-
-         // LOAD TABLES - This should be done only once in the decoder init for efficiency, unless tables can change mid-file.
-        self.load_tables(master_table_version, center_id, local_table_version)?;
-
-
-        let year = if version == 2 {
-            reader.read_bits(1 * bytes_size)?
-        } else { // version == 4
-            reader.read_bits(2 * bytes_size)?
-        };
-        println!("Year : {}", year);
-        let month = reader.read_bits(1 * bytes_size)?;
-        println!("Month : {}", month);
-        let day = reader.read_bits(1 * bytes_size)?;
-        println!("Day : {}", day);
-        let hour = reader.read_bits(1 * bytes_size)?;
-        println!("Hour : {}", hour);
-        let minute = reader.read_bits(1 * bytes_size)?;
-        println!("Minute : {}", minute);
-        if version == 4 {
-            let second = reader.read_bits(1 * bytes_size)?;
-            println!("Second : {}", second);
-        }
-
-        self.section1end(version, length_1, reader, bytes_size)?;
-
-        if sect2 {
-            self.section2(reader, bytes_size)?;
+        if let Some((path, subsets)) = self.netcdf {
+            netcdf::save_netcdf(&path, &subsets)?;
         }
-
-         // SECTION 3 ( Data Description )
-        let length_3 = reader.read_bits(3 * bytes_size)?;
-        println!("Length of section 3 (Data Description) : {}", length_3);
-        reader.read_bits(1 * bytes_size)?; // Reserved, set to 0
-        let number_of_data_subsets = reader.read_bits(2 * bytes_size)?;
-        println!("Number of data subsets : {}", number_of_data_subsets);
-        let _observed_compressed_data = reader.read_bits(1 * bytes_size)?;
-        // println!("Observed/Compressed Data : ", x//128 , '/', (x//64)%2);
-
-
-        let mut descriptors: Vec<String> = Vec::new();
-        let mut desc_bytes = String::new();
-
-        for i in 0..(length_3 - 7) {
-            let x = reader.read_bits(1 * bytes_size)?;
-            if i % 2 == 1 {
-                desc_bytes.push_str(&format!("{}", x));
-                descriptors.push(format!("{}{}", bytes_desc(desc_bytes.as_bytes()[0]), desc_bytes.as_bytes()[1]));
-                desc_bytes.clear();
-            } else {
-                desc_bytes = bytes_desc(x as u8);
-            }
+        #[cfg(feature = "arrow")]
+        if let Some(parquet) = self.parquet {
+            parquet.close()?;
         }
-
-        if self.affiche_descriptors {
-            println!("Descriptors : {:?}", descriptors);
-        }
-
-
-        // SECTION 4 ( Datas )
-        let length_4 = reader.read_bits(3 * bytes_size)?;
-        println!("Length of section 4 (Datas) : {}", length_4);
-        reader.read_bits(1 * bytes_size)?; // Reserved, SET TO 0
-
-        let mut index_descript = 0;
-        while index_descript < descriptors.len() {
-            let descriptor = &descriptors[index_descript];
-            if self.affiche_descriptors {
-                println!("{}", descriptor);
-            }
-
-            if descriptor.starts_with("0-") {
-                // F = 0 : single element descriptor (ref in Table B)
-                self.simple_desc(descriptor, reader)?;
-            } else if descriptor.starts_with("3-") {
-                // F = 3 : list of descriptors (ref in table D)
-                if let Some(descript_elt) = self.dico_m_d.get(descriptor) { // Master table D lookup
-                    for eltk in descript_elt {
-                        if self.affiche_descriptors {
-                            println!("{}", eltk);
-                        }
-                        descriptors.insert(index_descript + 1, eltk.clone()); // Insert list of descriptors
-                    }
-                } else if let Some(descript_elt) = self.dico_l_d.get(descriptor) { // Local table D lookup
-                     for eltk in descript_elt {
-                        if self.affiche_descriptors {
-                            println!("{}", eltk);
-                        }
-                        descriptors.insert(index_descript + 1, eltk.clone()); // Insert list of descriptors
-                    }
-                }
-
-            } else if descriptor.starts_with("2-") {
-                // F = 2 : Operator descriptor (ref in table C)
-                self.descri_table_c(reader, descriptor, &mut descriptors, &mut index_descript)?;
-            }
-             // ... (F=1 handling to be implemented) ...
-
-
-            index_descript += 1;
-        }
-
-
-        println!(" ** END OF DATAS **");
-
-        println!("DATAS DESCRIPTORS NUMBER: {}", self.datas_total.len());
-        println!("DATAS :");
-        for (key, value) in &self.datas_total {
-            if value.len() < 10 {
-                println!("  {} : {:?} ({})", key, value, self.datas_unites.get(key).unwrap_or(&String::new()));
-            } else {
-                println!("  {} ( {} data(s))", key, value.len());
-            }
-        }
-
-
-        reader.read_bits(4 * bytes_size)?; // (7777 =)  End of BUFR message
-
-        println!(" ----------- END OF BUFR MESSAGE -----------");
-        Ok(Some(self.datas_total.clone()))
+        Ok(())
     }
+}
 
+// Memory-mapped file, or a stream for standard input and what can't be mapped (pipes)
+enum Input {
+    Mapped(Mmap),
+    Stream(Box<dyn Read>),
+}
 
-    fn section1_v2<R: Read>(&mut self, reader: &mut BitReader<R>, bytes_size: u32) -> Result<(u32, u32, u32, u32, bool), Box<dyn Error>> {
-        let length_1 = reader.read_bits(3 * bytes_size)?;
-        println!("Length of section 1 : {}", length_1);
-        let bufr_master_table = reader.read_bits(1 * bytes_size)?;
-        println!("Bufr master table : {}", bufr_master_table);
-        let sub_center_id = reader.read_bits(1 * bytes_size)?;
-        println!("Identification of originating/generating sub-centre : {}", sub_center_id);
-        let center_id = reader.read_bits(1 * bytes_size)?;
-        println!("Identification of originating/generating centre : {}", center_id);
-        let update_sequence_number = reader.read_bits(1 * bytes_size)?;
-        println!("Update sequence number : {}", update_sequence_number);
-        let sect2_indicator = reader.read_bits(1 * bytes_size)?;
-        let sect2 = sect2_indicator == 1;
-        println!("Optional (1) / No Optional (0) section follows : {} ({})", sect2_indicator, if sect2 { "yes" } else { "no" });
-        let data_category = reader.read_bits(1 * bytes_size)?;
-        println!("Data Category (Table A) : {}", data_category);
-        let data_subcategory = reader.read_bits(1 * bytes_size)?;
-        println!("Data category sub-category : {}", data_subcategory);
-        Ok((length_1, bufr_master_table, sub_center_id, center_id, sect2))
+fn open(path: &str) -> Result<Input, Box<dyn Error>> {
+    if path == "-" {
+        return Ok(Input::Stream(Box::new(BufReader::new(io::stdin().lock()))));
     }
-
-    fn section1_v4<R: Read>(&mut self, reader: &mut BitReader<R>, bytes_size: u32) -> Result<(u32, u32, u32, u32, bool), Box<dyn Error>> {
-        let length_1 = reader.read_bits(3 * bytes_size)?;
-        println!("Length of section 1 : {}", length_1);
-        let bufr_master_table = reader.read_bits(1 * bytes_size)?;
-        println!("Bufr master table : {}", bufr_master_table);
-        let center_id = reader.read_bits(2 * bytes_size)?;
-        println!("Identification of originating/generating centre : {}", center_id);
-        let sub_center_id = reader.read_bits(2 * bytes_size)?;
-        println!("Identification of originating/generating sub-centre : {}", sub_center_id);
-        let update_sequence_number = reader.read_bits(1 * bytes_size)?;
-        println!("Update sequence number : {}", update_sequence_number);
-        let sect2_indicator = reader.read_bits(1 * bytes_size)?;
-        println!("Optional (1) / No Optional (0) section follows : {}", sect2_indicator);
-        let data_category = reader.read_bits(1 * bytes_size)?;
-        println!("Data Category (Table A) : {}", data_category);
-        let international_data_subcategory = reader.read_bits(1 * bytes_size)?;
-        println!("International data sub-category : {}", international_data_subcategory);
-        let local_subcategory = reader.read_bits(1 * bytes_size)?;
-        println!("Local sub-category : {}", local_subcategory);
-        Ok((length_1, bufr_master_table, sub_center_id, center_id, false)) // sect2 is always false for v4?
+    let file = File::open(path)?;
+    // SAFETY: the file is only read, truncating it while it is decoded is not supported
+    match unsafe { Mmap::map(&file) } {
+        Ok(map) => Ok(Input::Mapped(map)),
+        Err(_) => Ok(Input::Stream(Box::new(BufReader::new(file)))),
     }
+}
 
-
-    fn section1end<R: Read>(&mut self, version: u32, length_1: u32, reader: &mut BitReader<R>, bytes_size: u32) -> Result<(), Box<dyn Error>> {
-        let lim = if version == 2 { 17 } else { 22 };
-        if length_1 > lim {
-            println!("SECTION 1 ending : ");
-            for _ in 0..(length_1 - lim) {
-                let x = reader.read_bits(1 * bytes_size)?;
-                println!("{}  {}", x, x as u8 as char);
+impl Input {
+    // Each message until `f` breaks, borrowed from the mapping or read from the stream
+    fn for_each_message(&mut self, mut f: impl FnMut(Message) -> Result<ControlFlow<()>, Box<dyn Error>>) -> Result<(), Box<dyn Error>> {
+        match self {
+            Input::Mapped(map) => {
+                for message in messages(map) {
+                    if f(message?)?.is_break() {
+                        break;
+                    }
+                }
+            }
+            Input::Stream(stream) => {
+                let mut reader = BitReader::new(stream);
+                loop {
+                    let offset = reader.position() / 8;
+                    let Some(bytes) = read_message(&mut reader)? else {
+                        break;
+                    };
+                    if f(Message { index: reader.messages(), offset, bytes: &bytes })?.is_break() {
+                        break;
+                    }
+                }
             }
-            println!("END OF SECTION 1");
         }
         Ok(())
     }
+}
 
-
-    fn section2<R: Read>(&mut self, reader: &mut BitReader<R>, bytes_size: u32) -> Result<(), Box<dyn Error>> {
-        let length_2 = reader.read_bits(3 * bytes_size)?;
-        println!("Length of section 2 : {}", length_2);
-        reader.read_bits(1 * bytes_size)?; // Reserved, set to 0
-        for _ in 0..(length_2 - 4) {
-            let x = reader.read_bits(1 * bytes_size)?;
-            println!("{}  {}", x, x as u8 as char);
+// Decodes the selected messages of one file, returns how many were decoded
+fn decode_file(path: &str, decoder: &mut BufrDecoder, args: &DecodeArgs, outputs: &mut Outputs) -> Result<usize, Box<dyn Error>> {
+    let filter = args.filter.header_filter();
+    let stem = match Path::new(path).file_stem() {
+        Some(stem) if path != "-" => stem.to_string_lossy().into_owned(),
+        _ => "stdin".to_string(),
+    };
+    let max_messages = if args.message.is_some() { Some(1) } else { args.max_messages };
+    let (mut read, mut decoded) = (0, 0);
+    open(path)?.for_each_message(|message| {
+        read = message.index;
+        // --message N : only message N is a candidate
+        if args.message.is_some_and(|n| (message.index as u64) < n) {
+            return Ok(ControlFlow::Continue(()));
         }
-        println!(" END OF SECTION 2");
-        Ok(())
-    }
-
-    fn load_tables(&mut self, master_table_version: u32, center_id: u32, local_table_version: u32) -> Result<(), Box<dyn Error>> {
-        let table_b_path = Path::new(&self.dir_path_table).join(format!("{}{}.csv", self.fic_tab_b, master_table_version));
-        match tables_b(table_b_path.to_str().unwrap_or_default()) {
-            Ok(table_b_records) => {
-                self.dico_m_b = dico_descriptor_b(table_b_records)?;
-            }
-            Err(e) => {
-                println!(" ** UNABLE TO READ MASTER TABLE B {} : {}", master_table_version, e);
-                self.dico_m_b = HashMap::new();
+        if decoder.decode_matching(&mut message.reader(), 8, |header| filter.matches(header))?.is_some() {
+            for warning in decoder.warnings() {
+                eprintln!("{}: warning: {}", path, warning);
             }
+            decoded += 1;
+            outputs.write(decoder, &format!("{}_{}", stem, message.index))?;
         }
+        let done = args.message.is_some() || max_messages.is_some_and(|max| decoded >= max);
+        Ok(if done { ControlFlow::Break(()) } else { ControlFlow::Continue(()) })
+    })?;
+    if let Some(n) = args.message.filter(|n| read < *n as usize) {
+        return Err(From::from(format!("no message {} in file, only {}", n, read)));
+    }
+    Ok(decoded)
+}
 
-        let table_d_path = Path::new(&self.dir_path_table).join(format!("{}{}.csv", self.fic_tab_d, master_table_version));
-        match tables_d(table_d_path.to_str().unwrap_or_default()) {
-            Ok(table_d_records) => {
-                self.dico_m_d = dico_descriptor_d(table_d_records)?;
+fn decode(args: DecodeArgs) -> Result<ExitCode, Box<dyn Error>> {
+    let text = args.format == Format::Text;
+    let mut decoder = BufrDecoder::with_tables(args.tables.clone());
+    decoder.affiche_descriptors(text && args.verbose);
+    decoder.affiche_sections(text);
+    decoder.select(args.select.as_deref().map(ElementSelection::new));
+    decoder.set_options(DecodeOptions { strictness: args.strictness.into(), limits: args.limits.limits() });
+
+    let csv = match args.format {
+        Format::Csv | Format::Tsv => {
+            let options = CsvOptions {
+                delimiter: if args.format == Format::Tsv { b'\t' } else { b',' },
+                columns: args.columns.clone(),
+                units_in_header: args.units,
+            };
+            Some(CsvExporter::new(io::stdout().lock(), options))
+        }
+        _ => None,
+    };
+    let mut outputs = Outputs {
+        format: args.format,
+        csv,
+        geotiff: args.geotiff.clone(),
+        netcdf: args.netcdf.clone().map(|path| (path, Vec::new())),
+        #[cfg(feature = "arrow")]
+        parquet: match &args.parquet {
+            Some(path) => Some(ParquetExporter::new(File::create(path)?, DEFAULT_BATCH_SIZE, args.columns.clone())),
+            None => None,
+        },
+    };
+
+    // a file that fails doesn't stop the others, but makes the exit code non zero
+    let mut failures = 0;
+    for path in &args.files {
+        match decode_file(path, &mut decoder, &args, &mut outputs) {
+            Ok(decoded) => {
+                if text {
+                    writeln!(io::stdout().lock(), " END OF FILE {} ({} message(s))", path, decoded)?;
+                }
             }
+            Err(e) if broken_pipe(e.as_ref()) => return Err(e),
             Err(e) => {
-                println!(" ** UNABLE TO READ MASTER TABLE D {} : {}", master_table_version, e);
-                self.dico_m_d = HashMap::new();
+                eprintln!("{}: {}", path, e);
+                failures += 1;
             }
         }
+    }
+    outputs.finish()?;
+    Ok(if failures == 0 { ExitCode::SUCCESS } else { ExitCode::FAILURE })
+}
 
-        let local_table_b_path = Path::new(&self.dir_path_table).join(format!("{}{}_{}.csv", self.fic_local_tab_b, center_id, local_table_version));
-        match tables_b(local_table_b_path.to_str().unwrap_or_default()) {
-            Ok(local_table_b_records) => {
-                self.dico_l_b = dico_descriptor_b(local_table_b_records)?;
-            }
-            Err(e) => {
-                println!(" ** UNABLE TO READ LOCAL TABLE B {} _ {} : {}", center_id, local_table_version, e);
-                self.dico_l_b = HashMap::new();
+// bufr_decoder ls <files> : one line per message, Section 4 is skipped by its length
+fn ls(files: &[String], filter: &HeaderFilter) -> Result<ExitCode, Box<dyn Error>> {
+    // only Sections 0 to 3 are read, never the tables
+    let mut decoder = BufrDecoder::with_tables(String::new());
+
+    let mut failures = 0;
+    for path in files {
+        let mut listing = || -> Result<(), Box<dyn Error>> {
+            let mut out = io::stdout().lock();
+            writeln!(out, "{}", path)?;
+            writeln!(
+                out,
+                "{:>5} {:>10} {:>8} {:>3} {:>9} {:>7} {:>7} {:<19} {:>7} {:>4}",
+                "#", "offset", "length", "ed", "centre", "categ", "tables", "reference time", "subsets", "comp"
+            )?;
+            open(path)?.for_each_message(|message| {
+                let Some(header) = decoder.read_header(&mut message.reader(), 8)? else {
+                    return Ok(ControlFlow::Continue(()));
+                };
+                let index = message.index;
+                let listed = filter.is_empty() || (header.is_supported() && filter.matches(&header));
+                if !listed {
+                    return Ok(ControlFlow::Continue(()));
+                }
+                if header.is_supported() {
+                    writeln!(
+                        out,
+                        "{:>5} {:>10} {:>8} {:>3} {:>9} {:>7} {:>7} {:<19} {:>7} {:>4}",
+                        index,
+                        header.offset,
+                        header.total_length,
+                        header.edition,
+                        format!("{}/{}", header.centre, header.sub_centre),
+                        format!("{}/{}", header.category, header.subcategory),
+                        format!("{}/{}", header.master_table_version, header.local_table_version),
+                        header.reference_time(),
+                        header.number_of_subsets,
+                        if header.compressed { "yes" } else { "no" }
+                    )?;
+                } else {
+                    writeln!(out, "{:>5} {:>10} {:>8} {:>3} (unsupported edition)", index, header.offset, header.total_length, header.edition)?;
+                }
+                Ok(ControlFlow::Continue(()))
+            })
+        };
+        if let Err(e) = listing() {
+            if broken_pipe(e.as_ref()) {
+                return Err(e);
             }
+            eprintln!("{}: {}", path, e);
+            failures += 1;
         }
+    }
+    Ok(if failures == 0 { ExitCode::SUCCESS } else { ExitCode::FAILURE })
+}
 
-        let local_table_d_path = Path::new(&self.dir_path_table).join(format!("{}{}_{}.csv", self.fic_local_tab_d, center_id, local_table_version));
-        match tables_d(local_table_d_path.to_str().unwrap_or_default()) {
-            Ok(local_table_d_records) => {
-                self.dico_l_d = dico_descriptor_d(local_table_d_records)?;
+// bufr_decoder dump <files> : bit offset, width, raw integer, scale, reference and value of
+// every descriptor, as far as the decoding goes when a message fails
+fn dump(files: &[String], dir_path_table: &str, message: Option<u64>) -> Result<ExitCode, Box<dyn Error>> {
+    let mut decoder = BufrDecoder::with_tables(dir_path_table);
+    decoder.enable_trace(true);
+
+    let mut failures = 0;
+    for path in files {
+        let mut dumping = || -> Result<(), Box<dyn Error>> {
+            let mut read = 0;
+            open(path)?.for_each_message(|bytes| {
+                read = bytes.index;
+                if message.is_some_and(|n| (bytes.index as u64) < n) {
+                    return Ok(ControlFlow::Continue(()));
+                }
+                let result = decoder.decode_bufr_message(&mut bytes.reader(), 8);
+                let mut out = io::stdout().lock();
+                writeln!(out, "{} message {}", path, bytes.index)?;
+                writeln!(out, "{:>5} {:>8} {:>3} {:<8} {:>10} {:>4} {:>11} {:<24} description", "sub", "bit", "wid", "fxy", "raw", "scal", "reference", "value")?;
+                for entry in decoder.trace() {
+                    writeln!(out, "{}", entry)?;
+                }
+                for warning in decoder.warnings() {
+                    eprintln!("{}: warning: {}", path, warning);
+                }
+                result?;
+                Ok(if message.is_some() { ControlFlow::Break(()) } else { ControlFlow::Continue(()) })
+            })?;
+            if let Some(n) = message.filter(|n| read < *n as usize) {
+                return Err(From::from(format!("no message {} in file, only {}", n, read)));
             }
-            Err(e) => {
-                println!(" ** UNABLE TO READ LOCAL TABLE D {} _ {} : {}", center_id, local_table_version, e);
-                self.dico_l_d = HashMap::new();
+            Ok(())
+        };
+        if let Err(e) = dumping() {
+            if broken_pipe(e.as_ref()) {
+                return Err(e);
             }
+            eprintln!("{}: {}", path, e);
+            failures += 1;
         }
-        Ok(())
     }
-
-
+    Ok(if failures == 0 { ExitCode::SUCCESS } else { ExitCode::FAILURE })
 }
 
+// bufr_decoder describe <descriptor> : looked up local tables first, as when decoding
+#[allow(clippy::result_large_err)] // the library's own error, as returned by BufrDecoder::describe
+fn describe(descriptor: &str, master: u32, centre: u32, local: u32, dir_path_table: &str) -> Result<ExitCode, BufrError> {
+    let mut decoder = BufrDecoder::with_tables(dir_path_table);
+    decoder.load_tables(master, centre, local)?;
+    for warning in decoder.warnings() {
+        eprintln!("warning: {}", warning);
+    }
+    write!(io::stdout().lock(), "{}", decoder.describe(descriptor)?)?;
+    Ok(ExitCode::SUCCESS)
+}
 
-fn main() -> Result<(), Box<dyn Error>> {
-    println!("Hello, world!");
-
-    let dir_path_table = "/Users/adrienbufort/Documents/workspace/meteolibre_dataset/tables".to_string(); // Replace with your actual path
-    let fic_tab_b = "bufrtabb_{}".to_string();
-    let fic_tab_d = "bufrtabd_{}".to_string();
-    let fic_local_tab_b = "localtabb_{}_{}".to_string();
-    let fic_local_tab_d = "localtabd_{}_{}".to_string();
-    let affiche_descriptors = true;
-
-    let mut decoder = BufrDecoder::new(dir_path_table, fic_tab_b, fic_tab_d, fic_local_tab_b, fic_local_tab_d, affiche_descriptors);
-    let file_path = "/Users/adrienbufort/Documents/workspace/meteolibre_dataset/T_IMFR27_C_LFPW_20241228120000.bufr"; // Replace with your BUFR file path
-
-    let input_file = File::open(file_path)?;
-    let mut reader = BitReader::new(BufReader::new(input_file));
+// "3" or "5-7"
+fn descriptor_arg(arg: &str) -> Result<String, String> {
+    tables::canonical_fxy(arg).ok_or_else(|| format!("{:?} is not an F-XX-YYY descriptor", arg))
+}
 
-    while let Some(_datas_total) = decoder.decode_bufr_message(&mut reader, 8)? {
-         // Process datas_total if needed
+// Subset numbers go from 1 to 65535 (2 octets in Section 3)
+fn subset_range(arg: &str) -> Result<RangeInclusive<usize>, String> {
+    let number = |n: &str| match n.trim().parse::<usize>() {
+        Ok(n) if (1..=u16::MAX as usize).contains(&n) => Ok(n),
+        _ => Err(format!("{:?} is not a subset number", n)),
+    };
+    match arg.split_once('-') {
+        Some((first, last)) => {
+            let (first, last) = (number(first)?, number(last)?);
+            if first > last {
+                return Err(format!("{:?} is an empty range", arg));
+            }
+            Ok(first..=last)
+        }
+        None => number(arg).map(|n| n..=n),
     }
+}
 
+// bufr_decoder filter <files> -o <output> : matching messages copied byte for byte,
+// or rewritten with only the selected subsets
+fn filter(files: &[String], output: &Path, subsets: Option<&[RangeInclusive<usize>]>, dir_path_table: &str, filter: &HeaderFilter) -> Result<ExitCode, Box<dyn Error>> {
+    let mut decoder = BufrDecoder::with_tables(dir_path_table);
+    let mut writer: Box<dyn Write> = if output.as_os_str() == "-" {
+        Box::new(BufWriter::new(io::stdout().lock()))
+    } else {
+        Box::new(BufWriter::new(File::create(output)?))
+    };
+
+    // each subset once, in the order of the message whatever the order given
+    let subsets: Option<BTreeSet<usize>> = subsets.map(|ranges| ranges.iter().flat_map(|range| range.clone()).collect());
+
+    let (mut read, mut written, mut failures) = (0, 0, 0);
+    for path in files {
+        let mut copying = || -> Result<(), Box<dyn Error>> {
+            open(path)?.for_each_message(|message| {
+                read += 1;
+                let Some(header) = decoder.read_header(&mut message.reader(), 8)? else {
+                    return Ok(ControlFlow::Continue(()));
+                };
+                if !(header.is_supported() && filter.matches(&header)) {
+                    return Ok(ControlFlow::Continue(()));
+                }
+                let Some(subsets) = &subsets else {
+                    writer.write_all(message.bytes)?;
+                    written += 1;
+                    return Ok(ControlFlow::Continue(()));
+                };
+                let extracted = decoder.decode_bufr_message(&mut message.reader(), 8).map_err(Box::from).and_then(|_| {
+                    if let Some(last) = subsets.last().filter(|last| **last > header.number_of_subsets as usize) {
+                        return Err(From::from(format!("no subset {}, the message has {}", last, header.number_of_subsets)));
+                    }
+                    let keep: Vec<usize> = subsets.iter().map(|n| n - 1).collect();
+                    encode::extract_subsets(message.bytes, &header, decoder.subset_bits(), &keep)
+                });
+                match extracted {
+                    Ok(extracted) => {
+                        writer.write_all(&extracted)?;
+                        written += 1;
+                    }
+                    Err(e) => {
+                        eprintln!("{}: message {}: {}", path, message.index, e);
+                        failures += 1;
+                    }
+                }
+                Ok(ControlFlow::Continue(()))
+            })
+        };
+        if let Err(e) = copying() {
+            if broken_pipe(e.as_ref()) {
+                return Err(e);
+            }
+            eprintln!("{}: {}", path, e);
+            failures += 1;
+        }
+    }
+    writer.flush()?;
+    eprintln!("{} of {} message(s) written to {}", written, read, output.display());
+    Ok(if failures == 0 { ExitCode::SUCCESS } else { ExitCode::FAILURE })
+}
 
-    println!(" END OF FILE ");
+// bufr_decoder check-tables <dir> : report every malformed row of a tables directory
+fn check_tables(dir_path_table: &str) -> Result<ExitCode, Box<dyn Error>> {
+    let diagnostics = tables::check_tables(Path::new(dir_path_table), &TableFiles::default())?;
+    let mut out = io::stdout().lock();
+    for diagnostic in &diagnostics {
+        writeln!(out, "{}", diagnostic)?;
+    }
+    writeln!(out, "{} problem(s) found in {}", diagnostics.len(), dir_path_table)?;
+    Ok(if diagnostics.is_empty() { ExitCode::SUCCESS } else { ExitCode::FAILURE })
+}
 
+// The reader of stdout went away (| head) : nothing left to do, not a failure
+fn broken_pipe(e: &(dyn Error + 'static)) -> bool {
+    let io = match e.downcast_ref::<csv::Error>() {
+        Some(e) => match e.kind() {
+            csv::ErrorKind::Io(io) => Some(io),
+            _ => None,
+        },
+        None => match e.downcast_ref::<BufrError>() {
+            Some(BufrError::Io { source, .. }) => Some(source),
+            _ => e.downcast_ref::<io::Error>(),
+        },
+    };
+    io.is_some_and(|io| io.kind() == io::ErrorKind::BrokenPipe)
+}
 
-    Ok(())
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let result = match cli.command {
+        Command::Decode(args) => decode(args),
+        Command::Ls { files, filter } => ls(&files, &filter.header_filter()),
+        Command::Dump { files, tables, message } => dump(&files, &tables, message),
+        Command::Describe { descriptor, master, centre, local, tables } => describe(&descriptor, master, centre, local, &tables).map_err(Box::from),
+        Command::Filter { files, output, subsets, tables, filter: header_filter } => {
+            filter(&files, &output, subsets.as_deref(), &tables, &header_filter.header_filter())
+        }
+        Command::CheckTables { dir } => check_tables(&dir),
+    };
+    match result {
+        Ok(code) => code,
+        Err(e) if broken_pipe(e.as_ref()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
 }
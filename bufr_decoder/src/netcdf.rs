@@ -0,0 +1,508 @@
+// CF-1.8 NetCDF output. Written as NetCDF classic with 64-bit offsets (CDF-2), not NetCDF-4 :
+// the netcdf crate needs libnetcdf and HDF5, which the build machines don't have, and every
+// NetCDF-4 reader opens classic files. Data larger than 4 GiB per variable isn't supported.
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::projection::{GridGeometry, ProjectionKind};
+use crate::radar;
+use crate::DataValue;
+
+// NetCDF classic format tags and types
+const NC_DIMENSION: u32 = 0x0A;
+const NC_VARIABLE: u32 = 0x0B;
+const NC_ATTRIBUTE: u32 = 0x0C;
+const NC_CHAR: u32 = 2;
+const NC_INT: u32 = 4;
+const NC_FLOAT: u32 = 5;
+const NC_DOUBLE: u32 = 6;
+
+const FILL_DOUBLE: f64 = 9.969209968386869e36;
+const FILL_INT: i32 = -2147483647;
+const TIME_UNITS: &str = "seconds since 1970-01-01 00:00:00";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Attr {
+    Text(String),
+    Int(Vec<i32>),
+    Float(Vec<f32>),
+    Double(Vec<f64>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Values {
+    Char(Vec<u8>),
+    Int(Vec<i32>),
+    Float(Vec<f32>),
+    Double(Vec<f64>),
+}
+
+impl Values {
+    fn len(&self) -> usize {
+        match self {
+            Values::Char(v) => v.len(),
+            Values::Int(v) => v.len(),
+            Values::Float(v) => v.len(),
+            Values::Double(v) => v.len(),
+        }
+    }
+
+    fn nc_type(&self) -> u32 {
+        match self {
+            Values::Char(_) => NC_CHAR,
+            Values::Int(_) => NC_INT,
+            Values::Float(_) => NC_FLOAT,
+            Values::Double(_) => NC_DOUBLE,
+        }
+    }
+
+    fn bytes(&self) -> Vec<u8> {
+        let mut bytes: Vec<u8> = match self {
+            Values::Char(v) => v.clone(),
+            Values::Int(v) => v.iter().flat_map(|x| x.to_be_bytes()).collect(),
+            Values::Float(v) => v.iter().flat_map(|x| x.to_be_bytes()).collect(),
+            Values::Double(v) => v.iter().flat_map(|x| x.to_be_bytes()).collect(),
+        };
+        bytes.resize(bytes.len().next_multiple_of(4), 0);
+        bytes
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variable {
+    pub name: String,
+    pub dims: Vec<usize>, // indexes in Dataset::dims
+    pub attrs: Vec<(String, Attr)>,
+    pub values: Values,
+}
+
+// In-memory NetCDF classic dataset, without record (unlimited) dimension
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Dataset {
+    pub dims: Vec<(String, usize)>,
+    pub attrs: Vec<(String, Attr)>,
+    pub vars: Vec<Variable>,
+}
+
+fn put_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend(value.to_be_bytes());
+}
+
+fn put_name(out: &mut Vec<u8>, name: &str) {
+    put_u32(out, name.len() as u32);
+    out.extend(name.as_bytes());
+    out.resize(out.len().next_multiple_of(4), 0);
+}
+
+fn put_attrs(out: &mut Vec<u8>, attrs: &[(String, Attr)]) {
+    if attrs.is_empty() {
+        put_u32(out, 0);
+        put_u32(out, 0);
+        return;
+    }
+    put_u32(out, NC_ATTRIBUTE);
+    put_u32(out, attrs.len() as u32);
+    for (name, attr) in attrs {
+        put_name(out, name);
+        let values = match attr {
+            Attr::Text(s) => Values::Char(s.as_bytes().to_vec()),
+            Attr::Int(v) => Values::Int(v.clone()),
+            Attr::Float(v) => Values::Float(v.clone()),
+            Attr::Double(v) => Values::Double(v.clone()),
+        };
+        put_u32(out, values.nc_type());
+        put_u32(out, values.len() as u32);
+        out.extend(values.bytes());
+    }
+}
+
+impl Dataset {
+    // Index of the dimension `name`, added if new. An existing one must have the same length.
+    pub fn add_dim(&mut self, name: &str, len: usize) -> Result<usize, Box<dyn Error>> {
+        if let Some(index) = self.dims.iter().position(|(n, _)| n == name) {
+            if self.dims[index].1 != len {
+                return Err(From::from(format!("NetCDF dimension {} of length {} already defined with length {}", name, len, self.dims[index].1)));
+            }
+            return Ok(index);
+        }
+        self.dims.push((name.to_string(), len));
+        Ok(self.dims.len() - 1)
+    }
+
+    pub fn add_var(&mut self, name: &str, dims: &[usize], attrs: Vec<(String, Attr)>, values: Values) {
+        self.vars.push(Variable { name: name.to_string(), dims: dims.to_vec(), attrs, values });
+    }
+
+    fn header(&self, begins: &[u64]) -> Vec<u8> {
+        let mut out = b"CDF\x02".to_vec(); // 64-bit offset format
+        put_u32(&mut out, 0); // numrecs
+        if self.dims.is_empty() {
+            put_u32(&mut out, 0);
+            put_u32(&mut out, 0);
+        } else {
+            put_u32(&mut out, NC_DIMENSION);
+            put_u32(&mut out, self.dims.len() as u32);
+            for (name, len) in &self.dims {
+                put_name(&mut out, name);
+                put_u32(&mut out, *len as u32);
+            }
+        }
+        put_attrs(&mut out, &self.attrs);
+        if self.vars.is_empty() {
+            put_u32(&mut out, 0);
+            put_u32(&mut out, 0);
+        } else {
+            put_u32(&mut out, NC_VARIABLE);
+            put_u32(&mut out, self.vars.len() as u32);
+            for (var, begin) in self.vars.iter().zip(begins) {
+                put_name(&mut out, &var.name);
+                put_u32(&mut out, var.dims.len() as u32);
+                var.dims.iter().for_each(|d| put_u32(&mut out, *d as u32));
+                put_attrs(&mut out, &var.attrs);
+                put_u32(&mut out, var.values.nc_type());
+                let vsize = var.values.bytes().len();
+                put_u32(&mut out, u32::try_from(vsize).unwrap_or(u32::MAX));
+                out.extend(begin.to_be_bytes());
+            }
+        }
+        out
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<(), Box<dyn Error>> {
+        for var in &self.vars {
+            let expected: usize = var.dims.iter().map(|d| self.dims[*d].1).product();
+            if var.values.len() != expected {
+                return Err(From::from(format!("Variable {} has {} values for {} cells", var.name, var.values.len(), expected)));
+            }
+        }
+        if let Some((name, _)) = self.dims.iter().find(|(_, len)| *len == 0) {
+            return Err(From::from(format!("Dimension {} is empty", name)));
+        }
+        // header size doesn't depend on the offsets, compute it once with dummy ones
+        let mut begin = self.header(&vec![0; self.vars.len()]).len() as u64;
+        let mut begins = Vec::with_capacity(self.vars.len());
+        let datas: Vec<Vec<u8>> = self.vars.iter().map(|v| v.values.bytes()).collect();
+        for data in &datas {
+            begins.push(begin);
+            begin += data.len() as u64;
+        }
+        writer.write_all(&self.header(&begins))?;
+        for data in &datas {
+            writer.write_all(data)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+fn text(value: &str) -> Attr {
+    Attr::Text(value.to_string())
+}
+
+// Variable name from a Table B description : "Temperature/air temperature" -> "temperature_air_temperature"
+fn var_name(description: &str) -> String {
+    let mut name = String::new();
+    for c in description.to_lowercase().chars() {
+        if c.is_ascii_alphanumeric() {
+            name.push(c);
+        } else if !name.ends_with('_') {
+            name.push('_');
+        }
+    }
+    let name = name.trim_matches('_').to_string();
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("v_{}", name)
+    } else {
+        name
+    }
+}
+
+fn find(values: &[DataValue], descriptors: &[&str]) -> Option<f64> {
+    descriptors.iter().find_map(|d| values.iter().find(|v| v.descriptor == *d).and_then(|v| v.value))
+}
+
+fn find_text<'a>(values: &'a [DataValue], descriptors: &[&str]) -> Option<&'a str> {
+    descriptors.iter().find_map(|d| values.iter().find(|v| v.descriptor == *d).and_then(|v| v.text.as_deref()))
+}
+
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+// Observation time (0-04-001 to 0-04-006) in seconds since 1970
+fn epoch_seconds(values: &[DataValue]) -> Option<f64> {
+    let year = find(values, &["0-04-001"])? as i64;
+    let month = find(values, &["0-04-002"])? as i64;
+    let day = find(values, &["0-04-003"])? as i64;
+    let hour = find(values, &["0-04-004"]).unwrap_or(0.0);
+    let minute = find(values, &["0-04-005"]).unwrap_or(0.0);
+    let second = find(values, &["0-04-006"]).unwrap_or(0.0);
+    Some(days_from_civil(year, month, day) as f64 * 86400.0 + hour * 3600.0 + minute * 60.0 + second)
+}
+
+fn latlon(values: &[DataValue]) -> (Option<f64>, Option<f64>) {
+    (find(values, &["0-05-001", "0-05-002"]), find(values, &["0-06-001", "0-06-002"]))
+}
+
+// Station identifier : WMO block and station number, else station name, else position
+fn station_id(values: &[DataValue]) -> String {
+    if let (Some(block), Some(station)) = (find(values, &["0-01-001"]), find(values, &["0-01-002"])) {
+        return format!("{:02}{:03}", block as i64, station as i64);
+    }
+    if let Some(name) = find_text(values, &["0-01-015", "0-01-018", "0-01-019"]) {
+        return name.to_string();
+    }
+    match latlon(values) {
+        (Some(lat), Some(lon)) => format!("{:.5},{:.5}", lat, lon),
+        _ => String::new(),
+    }
+}
+
+fn char_values(texts: &[String], width: usize) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(texts.len() * width);
+    for text in texts {
+        let mut chars = text.as_bytes().to_vec();
+        chars.resize(width, 0);
+        bytes.extend(chars);
+    }
+    bytes
+}
+
+// Element columns of the observations : one per (descriptor, occurrence in the subset)
+struct Column {
+    name: String,
+    sample: DataValue,
+    numbers: Vec<f64>,
+    texts: Vec<String>,
+}
+
+fn add_observations(dataset: &mut Dataset, subsets: &[&Vec<DataValue>]) -> Result<(), Box<dyn Error>> {
+    // contiguous ragged array : observations grouped by station, in order of appearance
+    let mut stations: Vec<String> = Vec::new();
+    let mut by_station: HashMap<String, Vec<&Vec<DataValue>>> = HashMap::new();
+    for subset in subsets {
+        let id = station_id(subset);
+        if !by_station.contains_key(&id) {
+            stations.push(id.clone());
+        }
+        by_station.entry(id).or_default().push(subset);
+    }
+    let obs: Vec<&Vec<DataValue>> = stations.iter().flat_map(|s| by_station[s].iter().copied()).collect();
+
+    let mut columns: Vec<Column> = Vec::new();
+    let mut index: HashMap<(String, usize), usize> = HashMap::new();
+    for (row, subset) in obs.iter().enumerate() {
+        let mut occurrences: HashMap<&str, usize> = HashMap::new();
+        for value in subset.iter().filter(|v| !v.descriptor.starts_with("0-31-")) {
+            let occurrence = occurrences.entry(&value.descriptor).or_insert(0);
+            *occurrence += 1;
+            let key = (value.descriptor.clone(), *occurrence);
+            let column = *index.entry(key).or_insert_with(|| {
+                columns.push(Column { name: String::new(), sample: value.clone(), numbers: vec![f64::NAN; obs.len()], texts: vec![String::new(); obs.len()] });
+                columns.len() - 1
+            });
+            let column = &mut columns[column];
+            match (&value.text, value.value) {
+                (Some(text), _) => column.texts[row] = text.clone(),
+                (None, Some(number)) => column.numbers[row] = number,
+                _ => {}
+            }
+        }
+    }
+
+    // names from the descriptions, occurrence then descriptor suffixes on clashes
+    let mut used: Vec<String> = ["station", "obs", "station_id", "lat", "lon", "row_size", "time", "x", "y", "latitude", "longitude", "projection", "radar_image"].iter().map(|s| s.to_string()).collect();
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    for column in columns.iter_mut() {
+        let base = var_name(&column.sample.description);
+        let count = seen.entry(column.sample.descriptor.clone()).or_insert(0);
+        *count += 1;
+        let mut name = if *count > 1 { format!("{}_{}", base, count) } else { base };
+        if used.contains(&name) {
+            name = format!("{}_{}", name, column.sample.descriptor.replace('-', "_"));
+        }
+        used.push(name.clone());
+        column.name = name;
+    }
+
+    let station_dim = dataset.add_dim("station", stations.len())?;
+    let obs_dim = dataset.add_dim("obs", obs.len())?;
+    let id_width = stations.iter().map(|s| s.len()).max().unwrap_or(0).max(1);
+    let id_dim = dataset.add_dim("station_id_strlen", id_width)?;
+    dataset.add_var(
+        "station_id",
+        &[station_dim, id_dim],
+        vec![("cf_role".into(), text("timeseries_id")), ("long_name".into(), text("station identifier"))],
+        Values::Char(char_values(&stations, id_width)),
+    );
+    let firsts: Vec<&Vec<DataValue>> = stations.iter().map(|s| by_station[s][0]).collect();
+    let lats = firsts.iter().map(|s| latlon(s).0.unwrap_or(FILL_DOUBLE)).collect();
+    let lons = firsts.iter().map(|s| latlon(s).1.unwrap_or(FILL_DOUBLE)).collect();
+    dataset.add_var(
+        "lat",
+        &[station_dim],
+        vec![("standard_name".into(), text("latitude")), ("units".into(), text("degrees_north")), ("_FillValue".into(), Attr::Double(vec![FILL_DOUBLE]))],
+        Values::Double(lats),
+    );
+    dataset.add_var(
+        "lon",
+        &[station_dim],
+        vec![("standard_name".into(), text("longitude")), ("units".into(), text("degrees_east")), ("_FillValue".into(), Attr::Double(vec![FILL_DOUBLE]))],
+        Values::Double(lons),
+    );
+    dataset.add_var(
+        "row_size",
+        &[station_dim],
+        vec![("long_name".into(), text("number of observations for this station")), ("sample_dimension".into(), text("obs"))],
+        Values::Int(stations.iter().map(|s| by_station[s].len() as i32).collect()),
+    );
+    dataset.add_var(
+        "time",
+        &[obs_dim],
+        vec![("standard_name".into(), text("time")), ("units".into(), text(TIME_UNITS)), ("calendar".into(), text("standard")), ("_FillValue".into(), Attr::Double(vec![FILL_DOUBLE]))],
+        Values::Double(obs.iter().map(|s| epoch_seconds(s).unwrap_or(FILL_DOUBLE)).collect()),
+    );
+
+    for column in columns {
+        let mut attrs = vec![("long_name".into(), text(&column.sample.description)), ("bufr_descriptor".into(), text(&column.sample.descriptor))];
+        if column.sample.unit.is_character() {
+            let width = column.texts.iter().map(|t| t.len()).max().unwrap_or(0).max(1);
+            let dim = dataset.add_dim(&format!("{}_strlen", column.name), width)?;
+            dataset.add_var(&column.name, &[obs_dim, dim], attrs, Values::Char(char_values(&column.texts, width)));
+        } else {
+            attrs.push(("units".into(), text(column.sample.unit.cf_units())));
+            attrs.push(("_FillValue".into(), Attr::Double(vec![FILL_DOUBLE])));
+            attrs.push(("coordinates".into(), text("time lat lon")));
+            let values = column.numbers.iter().map(|v| if v.is_nan() { FILL_DOUBLE } else { *v }).collect();
+            dataset.add_var(&column.name, &[obs_dim], attrs, Values::Double(values));
+        }
+    }
+    Ok(())
+}
+
+fn grid_mapping(geometry: &GridGeometry) -> Vec<(String, Attr)> {
+    let p = &geometry.projection;
+    let mut attrs = vec![("semi_major_axis".into(), Attr::Double(vec![p.ellipsoid.a])), ("semi_minor_axis".into(), Attr::Double(vec![p.ellipsoid.b]))];
+    match p.kind {
+        ProjectionKind::Geographic => attrs.push(("grid_mapping_name".into(), text("latitude_longitude"))),
+        ProjectionKind::PolarStereographic => {
+            attrs.push(("grid_mapping_name".into(), text("polar_stereographic")));
+            attrs.push(("straight_vertical_longitude_from_pole".into(), Attr::Double(vec![p.lon_0])));
+            attrs.push(("latitude_of_projection_origin".into(), Attr::Double(vec![p.lat_0])));
+            attrs.push(("standard_parallel".into(), Attr::Double(vec![p.lat_1])));
+        }
+        ProjectionKind::LambertConformal => {
+            attrs.push(("grid_mapping_name".into(), text("lambert_conformal_conic")));
+            attrs.push(("standard_parallel".into(), Attr::Double(vec![p.lat_1, p.lat_2])));
+            attrs.push(("longitude_of_central_meridian".into(), Attr::Double(vec![p.lon_0])));
+            attrs.push(("latitude_of_projection_origin".into(), Attr::Double(vec![p.lat_0])));
+        }
+    }
+    if p.kind != ProjectionKind::Geographic {
+        attrs.push(("false_easting".into(), Attr::Double(vec![p.x_0])));
+        attrs.push(("false_northing".into(), Attr::Double(vec![p.y_0])));
+    }
+    attrs
+}
+
+// Every image of a radar subset as a (y, x) variable with its coordinates and grid mapping
+fn add_images(dataset: &mut Dataset, subset: &[DataValue], count: &mut usize) -> Result<(), Box<dyn Error>> {
+    for image in radar::images(subset)? {
+        *count += 1;
+        let suffix = if *count > 1 { format!("_{}", count) } else { String::new() };
+        let geometry = GridGeometry::from_values(subset, &image)?;
+        let geographic = geometry.projection.kind == ProjectionKind::Geographic;
+        let y_dim = dataset.add_dim(&format!("y{}", suffix), image.ny)?;
+        let x_dim = dataset.add_dim(&format!("x{}", suffix), image.nx)?;
+        let (x_name, y_name) = if geographic { ("longitude", "latitude") } else { ("projection_x_coordinate", "projection_y_coordinate") };
+        let units = if geographic { ("degrees_east", "degrees_north") } else { ("m", "m") };
+        dataset.add_var(
+            &format!("x{}", suffix),
+            &[x_dim],
+            vec![("standard_name".into(), text(x_name)), ("units".into(), text(units.0))],
+            Values::Double((0..image.nx).map(|col| geometry.xy(0, col).0).collect()),
+        );
+        dataset.add_var(
+            &format!("y{}", suffix),
+            &[y_dim],
+            vec![("standard_name".into(), text(y_name)), ("units".into(), text(units.1))],
+            Values::Double((0..image.ny).map(|row| geometry.xy(row, 0).1).collect()),
+        );
+        let (lons, lats) = geometry.lonlats();
+        dataset.add_var(
+            &format!("latitude{}", suffix),
+            &[y_dim, x_dim],
+            vec![("standard_name".into(), text("latitude")), ("units".into(), text("degrees_north"))],
+            Values::Double(lats),
+        );
+        dataset.add_var(
+            &format!("longitude{}", suffix),
+            &[y_dim, x_dim],
+            vec![("standard_name".into(), text("longitude")), ("units".into(), text("degrees_east"))],
+            Values::Double(lons),
+        );
+        dataset.add_var(&format!("projection{}", suffix), &[], grid_mapping(&geometry), Values::Int(vec![FILL_INT]));
+        let mut coordinates = format!("latitude{0} longitude{0}", suffix);
+        if let Some(time) = epoch_seconds(subset) {
+            // scalar coordinate, "time" is taken by the observations when there are some
+            let name = if dataset.vars.iter().any(|v| v.name == "time") { format!("image_time{}", suffix) } else { format!("time{}", suffix) };
+            dataset.add_var(
+                &name,
+                &[],
+                vec![("standard_name".into(), text("time")), ("units".into(), text(TIME_UNITS)), ("calendar".into(), text("standard"))],
+                Values::Double(vec![time]),
+            );
+            coordinates = format!("{} {}", name, coordinates);
+        }
+        let fill = if image.nodata.is_nan() { f32::NAN } else { image.nodata as f32 };
+        let attrs = vec![
+            ("long_name".into(), text("radar image")),
+            ("units".into(), text(if image.scaled { "dBZ" } else { "1" })),
+            ("_FillValue".into(), Attr::Float(vec![fill])),
+            ("grid_mapping".into(), text(&format!("projection{}", suffix))),
+            ("coordinates".into(), text(&coordinates)),
+            ("bufr_descriptor".into(), text("0-30-198")),
+        ];
+        let values = image.data.iter().map(|v| if v.is_nan() { fill } else { *v as f32 }).collect();
+        dataset.add_var(&format!("radar_image{}", suffix), &[y_dim, x_dim], attrs, Values::Float(values));
+    }
+    Ok(())
+}
+
+// CF dataset of decoded subsets : radar subsets become gridded variables,
+// the other ones observations of a station / obs contiguous ragged array
+pub fn dataset(subsets: &[Vec<DataValue>]) -> Result<Dataset, Box<dyn Error>> {
+    let mut dataset = Dataset::default();
+    dataset.attrs.push(("Conventions".into(), text("CF-1.8")));
+    dataset.attrs.push(("source".into(), text("BUFR")));
+
+    let (radar, observations): (Vec<&Vec<DataValue>>, Vec<&Vec<DataValue>>) =
+        subsets.iter().filter(|s| !s.is_empty()).partition(|s| s.iter().any(|v| v.descriptor == "0-30-198"));
+    if !observations.is_empty() {
+        dataset.attrs.push(("featureType".into(), text("timeSeries")));
+        add_observations(&mut dataset, &observations)?;
+    }
+    let mut images = 0;
+    for subset in radar {
+        add_images(&mut dataset, subset, &mut images)?;
+    }
+    Ok(dataset)
+}
+
+pub fn write_netcdf<W: Write>(writer: &mut W, subsets: &[Vec<DataValue>]) -> Result<(), Box<dyn Error>> {
+    dataset(subsets)?.write(writer)
+}
+
+pub fn save_netcdf(path: &Path, subsets: &[Vec<DataValue>]) -> Result<(), Box<dyn Error>> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_netcdf(&mut writer, subsets)
+}
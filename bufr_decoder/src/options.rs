@@ -0,0 +1,69 @@
+use std::fmt;
+
+use crate::expand::{DEFAULT_MAX_DEPTH, DEFAULT_MAX_NODES};
+
+// What happens when a message doesn't conform : missing master tables, unknown descriptors,
+// wrong section lengths, bad "7777", bits left over at the end of Section 4
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Strictness {
+    // the message is rejected with the BufrError
+    #[default]
+    Strict,
+    // the BufrError is collected in BufrDecoder::warnings and decoding goes on
+    Lenient,
+    // decoding goes on without a word
+    Ignore,
+}
+
+// Bounds for untrusted files, a message exceeding one is rejected with BufrError::LimitExceeded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    pub max_message_length: u32, // octets, total length of Section 0
+    pub max_descriptors: usize,  // nodes of the expanded descriptors, replications counted once
+    pub max_replication: u32,    // repetitions of one replication
+    pub max_depth: usize,        // nesting of sequences and replications
+    pub max_string_length: u32,  // octets of one CCITT IA5 element
+    pub max_values: u64,         // values of all the subsets, plus the sequences, replications and operators of their trees
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_message_length: 0xff_ffff, // what Section 0 can express
+            max_descriptors: DEFAULT_MAX_NODES,
+            max_replication: u16::MAX as u32, // widest standard factor, 0-31-002
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_string_length: 255, // 2-08-255
+            max_values: 1 << 24,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    MessageLength,
+    Descriptors,
+    Replication,
+    Depth,
+    StringLength,
+    Values,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Limit::MessageLength => write!(f, "message length"),
+            Limit::Descriptors => write!(f, "expanded descriptor count"),
+            Limit::Replication => write!(f, "replication count"),
+            Limit::Depth => write!(f, "nesting depth"),
+            Limit::StringLength => write!(f, "string length"),
+            Limit::Values => write!(f, "decoded value count"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DecodeOptions {
+    pub strictness: Strictness,
+    pub limits: Limits,
+}
@@ -0,0 +1,409 @@
+use std::error::Error;
+use std::f64::consts::{FRAC_PI_2, FRAC_PI_4};
+
+use crate::radar::RadarImage;
+use crate::DataValue;
+
+// Elements of the centre 247 (OPERA) local tables describing the projection
+const PROJECTION_TYPE: &str = "0-29-201";
+const SEMI_MAJOR_AXIS: &str = "0-29-199";
+const SEMI_MINOR_AXIS: &str = "0-29-200";
+const LONG_ORIGIN: &str = "0-29-193";
+const LAT_ORIGIN: &str = "0-29-194";
+const LONG_ORIGIN_HIGH: &str = "0-29-203"; // same with 1e-5 degree resolution
+const LAT_ORIGIN_HIGH: &str = "0-29-204";
+const X_OFFSET: &str = "0-29-195";
+const Y_OFFSET: &str = "0-29-196";
+const STANDARD_PARALLEL_1: &str = "0-29-197";
+const STANDARD_PARALLEL_2: &str = "0-29-198";
+const PROJ_STRING: &str = "0-29-205";
+const PIXEL_SIZE_X: &str = "0-05-033";
+const PIXEL_SIZE_Y: &str = "0-06-033";
+
+const WGS84: Ellipsoid = Ellipsoid { a: 6378137.0, b: 6356752.314245 };
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ellipsoid {
+    pub a: f64, // semi-major axis (m)
+    pub b: f64, // semi-minor axis (m)
+}
+
+impl Ellipsoid {
+    pub fn eccentricity(&self) -> f64 {
+        (1.0 - (self.b * self.b) / (self.a * self.a)).max(0.0).sqrt()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProjectionKind {
+    Geographic,         // plain longitude / latitude grid
+    PolarStereographic, // code 1 of table 0-29-201 with a polar origin
+    LambertConformal,   // code 2 of table 0-29-201
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Projection {
+    pub kind: ProjectionKind,
+    pub ellipsoid: Ellipsoid,
+    pub lon_0: f64, // degrees
+    pub lat_0: f64,
+    pub lat_1: f64, // first standard parallel (latitude of true scale for stereographic)
+    pub lat_2: f64,
+    pub k_0: f64,   // scale factor at the pole when no latitude of true scale is given
+    pub x_0: f64,   // false easting (m)
+    pub y_0: f64,   // false northing (m)
+}
+
+fn find(values: &[DataValue], descriptor: &str) -> Option<f64> {
+    values.iter().find(|v| v.descriptor == descriptor).and_then(|v| v.value)
+}
+
+// Conformal latitude helper t(phi) of Snyder's formulas (15-9)
+fn tsfn(phi: f64, e: f64) -> f64 {
+    let es = e * phi.sin();
+    (FRAC_PI_4 - phi / 2.0).tan() / ((1.0 - es) / (1.0 + es)).powf(e / 2.0)
+}
+
+fn msfn(phi: f64, e: f64) -> f64 {
+    phi.cos() / (1.0 - (e * phi.sin()).powi(2)).sqrt()
+}
+
+// Inverse of tsfn by fixed point iteration (Snyder 7-9)
+fn phi_from_ts(ts: f64, e: f64) -> f64 {
+    let mut phi = FRAC_PI_2 - 2.0 * ts.atan();
+    for _ in 0..15 {
+        let es = e * phi.sin();
+        let next = FRAC_PI_2 - 2.0 * (ts * ((1.0 - es) / (1.0 + es)).powf(e / 2.0)).atan();
+        if (next - phi).abs() < 1e-12 {
+            return next;
+        }
+        phi = next;
+    }
+    phi
+}
+
+fn normalize_lon(lon: f64) -> f64 {
+    let lon = (lon + 180.0).rem_euclid(360.0) - 180.0;
+    if lon == -180.0 { 180.0 } else { lon }
+}
+
+impl Projection {
+    // Parameters from a PROJ initialisation string such as
+    // "+proj=stere +lat_0=90 +lon_0=0 +lat_ts=45 +a=6378137 +b=6356752 +x_0=0 +y_0=0"
+    pub fn from_proj_string(proj: &str) -> Result<Projection, Box<dyn Error>> {
+        let mut params = std::collections::HashMap::new();
+        for token in proj.split_whitespace() {
+            let token = token.trim_start_matches('+');
+            match token.split_once('=') {
+                Some((k, v)) => params.insert(k.to_string(), v.to_string()),
+                None => params.insert(token.to_string(), String::new()),
+            };
+        }
+        let num = |key: &str| -> Result<Option<f64>, Box<dyn Error>> {
+            match params.get(key) {
+                Some(v) => Ok(Some(v.parse::<f64>().map_err(|_| format!("Bad PROJ parameter +{}={}", key, v))?)),
+                None => Ok(None),
+            }
+        };
+        let kind = match params.get("proj").map(String::as_str) {
+            Some("stere") | Some("ups") => ProjectionKind::PolarStereographic,
+            Some("lcc") => ProjectionKind::LambertConformal,
+            Some("longlat") | Some("latlong") | Some("lonlat") | Some("latlon") => ProjectionKind::Geographic,
+            other => return Err(From::from(format!("Unsupported PROJ projection {:?}", other.unwrap_or("")))),
+        };
+        let mut ellipsoid = match params.get("ellps").map(String::as_str) {
+            Some("sphere") => Ellipsoid { a: 6370997.0, b: 6370997.0 },
+            Some("GRS80") => Ellipsoid { a: 6378137.0, b: 6356752.314140 },
+            _ => WGS84,
+        };
+        if let Some(r) = num("R")? {
+            ellipsoid = Ellipsoid { a: r, b: r };
+        }
+        if let Some(a) = num("a")? {
+            ellipsoid = Ellipsoid { a, b: num("b")?.unwrap_or(a) };
+        }
+        let lat_0 = num("lat_0")?.unwrap_or(0.0);
+        let lat_1 = num("lat_ts")?.or(num("lat_1")?).unwrap_or(lat_0);
+        let projection = Projection {
+            kind,
+            ellipsoid,
+            lon_0: num("lon_0")?.unwrap_or(0.0),
+            lat_0,
+            lat_1,
+            lat_2: num("lat_2")?.unwrap_or(lat_1),
+            k_0: num("k_0")?.or(num("k")?).unwrap_or(1.0),
+            x_0: num("x_0")?.unwrap_or(0.0),
+            y_0: num("y_0")?.unwrap_or(0.0),
+        };
+        projection.check()?;
+        Ok(projection)
+    }
+
+    // Projection of a decoded radar subset : the PROJ string (0-29-205) when present,
+    // otherwise the projection type, ellipsoid, origin, offsets and standard parallels.
+    // An error without either, rather than guessing a geographic grid.
+    pub fn from_values(values: &[DataValue]) -> Result<Projection, Box<dyn Error>> {
+        if let Some(proj) = values.iter().find(|v| v.descriptor == PROJ_STRING).and_then(|v| v.text.as_deref()) {
+            if !proj.trim().is_empty() {
+                return Projection::from_proj_string(proj);
+            }
+        }
+        let kind = match find(values, PROJECTION_TYPE) {
+            Some(1.0) => ProjectionKind::PolarStereographic,
+            Some(2.0) => ProjectionKind::LambertConformal,
+            None => return Err(From::from("Projection type (0-29-201) or PROJ string (0-29-205) missing")),
+            Some(code) => return Err(From::from(format!("Unsupported projection type {} (0-29-201)", code))),
+        };
+        let ellipsoid = match (find(values, SEMI_MAJOR_AXIS), find(values, SEMI_MINOR_AXIS)) {
+            (Some(a), Some(b)) if a > 0.0 && b > 0.0 => Ellipsoid { a, b },
+            (Some(a), None) if a > 0.0 => Ellipsoid { a, b: a },
+            _ => WGS84,
+        };
+        let lat_0 = find(values, LAT_ORIGIN_HIGH).or(find(values, LAT_ORIGIN)).unwrap_or(0.0);
+        let lat_1 = find(values, STANDARD_PARALLEL_1).unwrap_or(lat_0);
+        let projection = Projection {
+            kind,
+            ellipsoid,
+            lon_0: find(values, LONG_ORIGIN_HIGH).or(find(values, LONG_ORIGIN)).unwrap_or(0.0),
+            lat_0,
+            lat_1,
+            lat_2: find(values, STANDARD_PARALLEL_2).unwrap_or(lat_1),
+            k_0: 1.0,
+            x_0: find(values, X_OFFSET).unwrap_or(0.0),
+            y_0: find(values, Y_OFFSET).unwrap_or(0.0),
+        };
+        projection.check()?;
+        Ok(projection)
+    }
+
+    fn check(&self) -> Result<(), Box<dyn Error>> {
+        if self.kind == ProjectionKind::PolarStereographic && (self.lat_0.abs() - 90.0).abs() > 1e-6 {
+            return Err(From::from(format!("Only polar stereographic projections are supported (lat_0 = {})", self.lat_0)));
+        }
+        if self.kind == ProjectionKind::LambertConformal && (self.lat_1 + self.lat_2).abs() < 1e-10 {
+            return Err(From::from("Lambert conformal standard parallels can't be symmetric about the equator"));
+        }
+        Ok(())
+    }
+
+    fn south(&self) -> bool {
+        self.lat_0 < 0.0
+    }
+
+    // Lambert conformal constants (n, F, rho0) of Snyder (15-8) to (15-1a)
+    fn lcc_constants(&self) -> (f64, f64, f64) {
+        let e = self.ellipsoid.eccentricity();
+        let (phi1, phi2) = (self.lat_1.to_radians(), self.lat_2.to_radians());
+        let (m1, t1) = (msfn(phi1, e), tsfn(phi1, e));
+        let n = if (phi1 - phi2).abs() < 1e-10 {
+            phi1.sin()
+        } else {
+            (m1.ln() - msfn(phi2, e).ln()) / (t1.ln() - tsfn(phi2, e).ln())
+        };
+        let f = m1 / (n * t1.powf(n));
+        let rho0 = self.ellipsoid.a * f * tsfn(self.lat_0.to_radians(), e).powf(n);
+        (n, f, rho0)
+    }
+
+    // Polar stereographic radius factor : rho = a * factor * t
+    fn stere_factor(&self) -> f64 {
+        let e = self.ellipsoid.eccentricity();
+        let phi_c = if self.south() { -self.lat_1 } else { self.lat_1 }.to_radians();
+        if (phi_c - FRAC_PI_2).abs() > 1e-10 {
+            msfn(phi_c, e) / tsfn(phi_c, e)
+        } else {
+            2.0 * self.k_0 / ((1.0 + e).powf(1.0 + e) * (1.0 - e).powf(1.0 - e)).sqrt()
+        }
+    }
+
+    // Scale factor at the pole giving the same projection with lat_1 at +-90 (EPSG variant A)
+    pub fn pole_scale(&self) -> f64 {
+        let e = self.ellipsoid.eccentricity();
+        self.stere_factor() * ((1.0 + e).powf(1.0 + e) * (1.0 - e).powf(1.0 - e)).sqrt() / 2.0
+    }
+
+    // (lon, lat) in degrees to projected (x, y) in metres (degrees for geographic grids)
+    pub fn forward(&self, lon: f64, lat: f64) -> (f64, f64) {
+        let e = self.ellipsoid.eccentricity();
+        let a = self.ellipsoid.a;
+        match self.kind {
+            ProjectionKind::Geographic => (lon, lat),
+            ProjectionKind::PolarStereographic => {
+                let sign = if self.south() { -1.0 } else { 1.0 };
+                let phi = (sign * lat).to_radians();
+                let dlam = (sign * (lon - self.lon_0)).to_radians();
+                let rho = a * self.stere_factor() * tsfn(phi, e);
+                (sign * rho * dlam.sin() + self.x_0, sign * -rho * dlam.cos() + self.y_0)
+            }
+            ProjectionKind::LambertConformal => {
+                let (n, f, rho0) = self.lcc_constants();
+                let rho = a * f * tsfn(lat.to_radians(), e).powf(n);
+                let theta = n * (lon - self.lon_0).to_radians();
+                (rho * theta.sin() + self.x_0, rho0 - rho * theta.cos() + self.y_0)
+            }
+        }
+    }
+
+    // Projected (x, y) back to (lon, lat) in degrees
+    pub fn inverse(&self, x: f64, y: f64) -> (f64, f64) {
+        let e = self.ellipsoid.eccentricity();
+        let a = self.ellipsoid.a;
+        let (x, y) = (x - self.x_0, y - self.y_0);
+        match self.kind {
+            ProjectionKind::Geographic => (x + self.x_0, y + self.y_0),
+            ProjectionKind::PolarStereographic => {
+                let sign = if self.south() { -1.0 } else { 1.0 };
+                let (x, y) = (sign * x, sign * y);
+                let rho = x.hypot(y);
+                let phi = phi_from_ts(rho / (a * self.stere_factor()), e);
+                let lam = x.atan2(-y);
+                (normalize_lon(sign * lam.to_degrees() + self.lon_0), sign * phi.to_degrees())
+            }
+            ProjectionKind::LambertConformal => {
+                let (n, f, rho0) = self.lcc_constants();
+                let sign = n.signum();
+                let rho = sign * x.hypot(rho0 - y);
+                let theta = (sign * x).atan2(sign * (rho0 - y));
+                let phi = if rho == 0.0 { sign * FRAC_PI_2 } else { phi_from_ts((rho / (a * f)).powf(1.0 / n), e) };
+                (normalize_lon((theta / n).to_degrees() + self.lon_0), phi.to_degrees())
+            }
+        }
+    }
+}
+
+// Position of a radar grid in its projection : upper left corner of the image and pixel size
+#[derive(Debug, Clone, PartialEq)]
+pub struct GridGeometry {
+    pub projection: Projection,
+    pub nx: usize,
+    pub ny: usize,
+    pub x_min: f64, // western edge of the first column
+    pub y_max: f64, // northern edge of the first row
+    pub dx: f64,
+    pub dy: f64,
+}
+
+// Corners transmitted by 3-01-192 / 3-01-194 : NW, NE, SE, SW as (lat, lon)
+fn corners(values: &[DataValue]) -> Vec<(f64, f64)> {
+    let lats = values.iter().filter(|v| v.descriptor == "0-05-001" || v.descriptor == "0-05-002");
+    let lons = values.iter().filter(|v| v.descriptor == "0-06-001" || v.descriptor == "0-06-002");
+    lats.zip(lons).take(4).filter_map(|(lat, lon)| Some((lat.value?, lon.value?))).collect()
+}
+
+impl GridGeometry {
+    // Geometry of `image` from the projection elements and the corners / pixel sizes of 3-01-192
+    pub fn from_values(values: &[DataValue], image: &RadarImage) -> Result<GridGeometry, Box<dyn Error>> {
+        let projection = Projection::from_values(values)?;
+        let corners = corners(values);
+        if corners.len() < 4 || image.nx == 0 || image.ny == 0 {
+            return Err(From::from("Radar grid corners (3-01-023 x 4) or dimensions missing"));
+        }
+        let (x_min, y_max) = projection.forward(corners[0].1, corners[0].0);
+        let (x_se, y_se) = projection.forward(corners[2].1, corners[2].0);
+        let metric = projection.kind != ProjectionKind::Geographic;
+        let dx = match find(values, PIXEL_SIZE_X) {
+            Some(size) if metric && size > 0.0 => size,
+            _ => (x_se - x_min) / image.nx as f64,
+        };
+        let dy = match find(values, PIXEL_SIZE_Y) {
+            Some(size) if metric && size > 0.0 => size,
+            _ => (y_max - y_se) / image.ny as f64,
+        };
+        Ok(GridGeometry { projection, nx: image.nx, ny: image.ny, x_min, y_max, dx, dy })
+    }
+
+    // Projected coordinates of the centre of a pixel
+    pub fn xy(&self, row: usize, col: usize) -> (f64, f64) {
+        (self.x_min + (col as f64 + 0.5) * self.dx, self.y_max - (row as f64 + 0.5) * self.dy)
+    }
+
+    // (lon, lat) of the centre of a pixel
+    pub fn lonlat(&self, row: usize, col: usize) -> (f64, f64) {
+        let (x, y) = self.xy(row, col);
+        self.projection.inverse(x, y)
+    }
+
+    // Row-major longitudes and latitudes of every pixel centre
+    pub fn lonlats(&self) -> (Vec<f64>, Vec<f64>) {
+        let mut lons = Vec::with_capacity(self.nx * self.ny);
+        let mut lats = Vec::with_capacity(self.nx * self.ny);
+        for row in 0..self.ny {
+            for col in 0..self.nx {
+                let (lon, lat) = self.lonlat(row, col);
+                lons.push(lon);
+                lats.push(lat);
+            }
+        }
+        (lons, lats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn projection(kind: ProjectionKind, ellipsoid: Ellipsoid, lon_0: f64, lat_0: f64, lat_1: f64, lat_2: f64) -> Projection {
+        Projection { kind, ellipsoid, lon_0, lat_0, lat_1, lat_2, k_0: 1.0, x_0: 0.0, y_0: 0.0 }
+    }
+
+    fn assert_close(expected: (f64, f64), found: (f64, f64), tolerance: f64) {
+        assert!((expected.0 - found.0).abs() < tolerance && (expected.1 - found.1).abs() < tolerance, "expected {:?}, found {:?}", expected, found);
+    }
+
+    fn round_trips(projection: &Projection, points: &[(f64, f64)]) {
+        for &(lon, lat) in points {
+            let (x, y) = projection.forward(lon, lat);
+            assert_close((lon, lat), projection.inverse(x, y), 1e-9);
+        }
+    }
+
+    #[test]
+    fn north_polar_stereographic() {
+        // on a sphere true at the pole : rho = 2 R tan(45 - lat / 2)
+        let sphere = Ellipsoid { a: 6371000.0, b: 6371000.0 };
+        let p = projection(ProjectionKind::PolarStereographic, sphere, 10.0, 90.0, 90.0, 90.0);
+        let rho = 2.0 * 6371000.0 * 15f64.to_radians().tan();
+        assert_close((rho * 20f64.to_radians().sin(), -rho * 20f64.to_radians().cos()), p.forward(30.0, 60.0), 1e-6);
+        let p = Projection { x_0: -500000.0, y_0: 250000.0, ..projection(ProjectionKind::PolarStereographic, WGS84, 10.0, 90.0, 45.0, 45.0) };
+        round_trips(&p, &[(2.0, 51.0), (-170.0, 10.0), (10.0, 89.9), (100.0, 45.0)]);
+        // the same with the scale factor at the pole
+        let variant_a = Projection { lat_1: 90.0, k_0: p.pole_scale(), ..p.clone() };
+        assert_close(p.forward(2.0, 51.0), variant_a.forward(2.0, 51.0), 1e-6);
+        assert!((p.pole_scale() - 0.8536).abs() < 1e-3); // (1 + sin 45) / 2 on a sphere
+    }
+
+    #[test]
+    fn south_polar_stereographic() {
+        // Snyder, Map Projections - A Working Manual, p. 317 : International ellipsoid, true at 71S
+        let international = Ellipsoid { a: 6378388.0, b: 6378388.0 * (1.0f64 - 0.00672267).sqrt() };
+        let p = projection(ProjectionKind::PolarStereographic, international, -100.0, -90.0, -71.0, -71.0);
+        assert_close((-1540033.6, -560526.4), p.forward(150.0, -75.0), 0.1);
+        round_trips(&p, &[(150.0, -75.0), (0.0, -60.0), (-100.0, -89.9), (45.0, -10.0)]);
+    }
+
+    #[test]
+    fn lambert_conformal() {
+        // Snyder p. 296 : Clarke 1866, standard parallels 33N and 45N, origin 23N 96W
+        let clarke = Ellipsoid { a: 6378206.4, b: 6378206.4 * (1.0f64 - 0.00676866).sqrt() };
+        let p = projection(ProjectionKind::LambertConformal, clarke, -96.0, 23.0, 33.0, 45.0);
+        assert_close((1894410.9, 1564649.5), p.forward(-75.0, 35.0), 0.1);
+        round_trips(&p, &[(-75.0, 35.0), (-120.0, 50.0), (-96.0, 23.0), (-60.0, 10.0)]);
+        // one standard parallel, southern hemisphere
+        let p = projection(ProjectionKind::LambertConformal, WGS84, 140.0, -30.0, -30.0, -30.0);
+        round_trips(&p, &[(150.0, -35.0), (120.0, -20.0)]);
+    }
+
+    #[test]
+    fn proj_strings() {
+        let p = Projection::from_proj_string("+proj=stere +lat_0=90 +lon_0=10 +lat_ts=45 +a=6378137 +b=6356752 +x_0=1 +y_0=2").unwrap();
+        assert_eq!((p.kind, p.lon_0, p.lat_1, p.x_0, p.y_0), (ProjectionKind::PolarStereographic, 10.0, 45.0, 1.0, 2.0));
+        assert_eq!(p.ellipsoid, Ellipsoid { a: 6378137.0, b: 6356752.0 });
+        assert!(Projection::from_proj_string("+proj=merc").is_err());
+        assert!(Projection::from_proj_string("+proj=stere +lat_0=45").is_err());
+    }
+
+    #[test]
+    fn projection_type_is_required() {
+        assert!(Projection::from_values(&[]).is_err());
+    }
+}
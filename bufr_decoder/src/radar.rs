@@ -0,0 +1,170 @@
+use std::error::Error;
+use std::io::Read;
+use std::ops::Index;
+
+use flate2::read::ZlibDecoder;
+
+use crate::DataValue;
+
+// Elements of the centre 247 (OPERA) local tables used to rebuild an image
+const PIXELS_PER_ROW: &str = "0-30-021";
+const PIXELS_PER_COLUMN: &str = "0-30-022";
+const BINS_ALONG_RADIAL: &str = "0-30-194";
+const NUMBER_OF_AZIMUTHS: &str = "0-30-195";
+const DBZ_OFFSET: &str = "0-21-198";
+const DBZ_INCREMENT: &str = "0-21-199";
+const COMPRESSION_METHOD: &str = "0-30-197";
+const COMPRESSED_BYTE: &str = "0-30-198";
+
+// Code table 0-30-197
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+    Zlib,
+    None,
+}
+
+impl Compression {
+    fn from_code(code: Option<f64>) -> Result<Compression, Box<dyn Error>> {
+        match code {
+            Some(0.0) => Ok(Compression::Zlib),
+            None => Ok(Compression::None), // missing : bytes are transmitted as is
+            Some(c) => Err(From::from(format!("Unsupported radar compression method {}", c))),
+        }
+    }
+}
+
+// Radar image as a row-major ny x nx grid of physical values, NaN where there is no data
+#[derive(Debug, Clone, PartialEq)]
+pub struct RadarImage {
+    pub nx: usize,
+    pub ny: usize,
+    pub compression: Compression,
+    pub bytes_per_pixel: usize,
+    pub scaled: bool, // dBZ = alpha + beta * pixel applied (0-21-198 / 0-21-199 present)
+    pub nodata: f64,  // what the BUFR missing pixel (all bits set) decodes to, stored as NaN in data
+    pub data: Vec<f64>,
+}
+
+impl RadarImage {
+    pub fn shape(&self) -> (usize, usize) {
+        (self.ny, self.nx)
+    }
+
+    pub fn get(&self, row: usize, col: usize) -> Option<f64> {
+        if row < self.ny && col < self.nx {
+            Some(self.data[row * self.nx + col])
+        } else {
+            None
+        }
+    }
+
+    pub fn row(&self, row: usize) -> Option<&[f64]> {
+        if row < self.ny {
+            Some(&self.data[row * self.nx..(row + 1) * self.nx])
+        } else {
+            None
+        }
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = &[f64]> {
+        self.data.chunks(self.nx.max(1))
+    }
+}
+
+impl Index<(usize, usize)> for RadarImage {
+    type Output = f64;
+
+    fn index(&self, (row, col): (usize, usize)) -> &f64 {
+        &self.data[row * self.nx + col]
+    }
+}
+
+fn as_usize(value: &DataValue) -> Option<usize> {
+    value.value.filter(|v| *v >= 0.0).map(|v| v as usize)
+}
+
+// Pixels are 1 byte, 2 bytes or IEEE doubles, big-endian as everything else in BUFR
+fn pixels(bytes: &[u8], nx: usize, ny: usize) -> Result<(usize, Vec<f64>), Box<dyn Error>> {
+    let count = nx.checked_mul(ny).ok_or_else(|| format!("Radar image of {} x {} pixels is too large", ny, nx))?;
+    if count == 0 {
+        return Err(From::from("Radar image without dimensions (0-30-021 / 0-30-022)"));
+    }
+    if !bytes.len().is_multiple_of(count) {
+        return Err(From::from(format!("{} bytes can't hold a {} x {} image", bytes.len(), ny, nx)));
+    }
+    let size = bytes.len() / count;
+    let values = match size {
+        1 => bytes.iter().map(|b| if *b == u8::MAX { f64::NAN } else { *b as f64 }).collect(),
+        2 => bytes
+            .chunks_exact(2)
+            .map(|c| u16::from_be_bytes([c[0], c[1]]))
+            .map(|v| if v == u16::MAX { f64::NAN } else { v as f64 })
+            .collect(),
+        8 => bytes.chunks_exact(8).map(|c| f64::from_be_bytes(c.try_into().unwrap())).collect(),
+        _ => return Err(From::from(format!("Unsupported radar pixel size of {} bytes", size))),
+    };
+    Ok((size, values))
+}
+
+// Rebuild every compressed array (3-21-206 style : 0-30-197 then rows of 0-30-198 bytes)
+// of a decoded subset, using the dimensions and dBZ scaling transmitted before it
+pub fn images(values: &[DataValue]) -> Result<Vec<RadarImage>, Box<dyn Error>> {
+    let mut images = Vec::new();
+    let (mut nx, mut ny) = (0, 0);
+    let (mut alpha, mut beta): (Option<f64>, Option<f64>) = (None, None);
+
+    let mut index = 0;
+    while index < values.len() {
+        let value = &values[index];
+        index += 1;
+        match value.descriptor.as_str() {
+            PIXELS_PER_ROW | BINS_ALONG_RADIAL => nx = as_usize(value).unwrap_or(0),
+            PIXELS_PER_COLUMN | NUMBER_OF_AZIMUTHS => ny = as_usize(value).unwrap_or(0),
+            DBZ_OFFSET => alpha = value.value,
+            DBZ_INCREMENT => beta = value.value,
+            COMPRESSION_METHOD => {
+                let compression = Compression::from_code(value.value)?;
+                let mut bytes = Vec::new();
+                while index < values.len() && (values[index].descriptor == COMPRESSED_BYTE || values[index].descriptor.starts_with("0-31-")) {
+                    if values[index].descriptor == COMPRESSED_BYTE {
+                        // 255 is a legitimate byte here even though it reads as missing
+                        bytes.push(values[index].value.map(|v| v as u8).unwrap_or(u8::MAX));
+                    }
+                    index += 1;
+                }
+                let bytes = match compression {
+                    Compression::Zlib => {
+                        // 8 bytes per pixel at most, one more tells a payload inflating past the grid
+                        let max = nx.checked_mul(ny).and_then(|count| count.checked_mul(8)).ok_or_else(|| format!("Radar image of {} x {} pixels is too large", ny, nx))?;
+                        let mut inflated = Vec::new();
+                        ZlibDecoder::new(bytes.as_slice()).take(max as u64 + 1).read_to_end(&mut inflated)?;
+                        if inflated.len() > max {
+                            return Err(From::from(format!("Compressed radar data larger than a {} x {} image", ny, nx)));
+                        }
+                        inflated
+                    }
+                    Compression::None => bytes,
+                };
+                let (bytes_per_pixel, mut data) = pixels(&bytes, nx, ny)?;
+                let scaled = alpha.is_some() && beta.is_some();
+                let mut nodata = match bytes_per_pixel {
+                    1 => u8::MAX as f64,
+                    2 => u16::MAX as f64,
+                    _ => f64::NAN,
+                };
+                if let (Some(a), Some(b)) = (alpha, beta) {
+                    data.iter_mut().for_each(|v| *v = a + b * *v);
+                    nodata = a + b * nodata;
+                }
+                images.push(RadarImage { nx, ny, compression, bytes_per_pixel, scaled, nodata, data });
+            }
+            _ => {}
+        }
+    }
+    Ok(images)
+}
+
+// First image of a decoded subset
+pub fn image(values: &[DataValue]) -> Result<RadarImage, Box<dyn Error>> {
+    images(values)?.into_iter().next().ok_or_else(|| From::from("No compressed radar array (0-30-197 / 0-30-198) in message"))
+}
@@ -0,0 +1,535 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+use csv::{ReaderBuilder, Terminator, Trim};
+
+// Table B entries keyed by "F-XX-YYY", each holding Description/Unit/Scale/Ref_Val/Data_width_bits
+pub type DicoB = HashMap<String, HashMap<String, String>>;
+// Table D sequences keyed by "F-XX-YYY"
+pub type DicoD = HashMap<String, Vec<String>>;
+// Non-blank CSV rows with their line number
+type Rows = Vec<(u64, Vec<String>)>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TableIssue {
+    FieldCount { expected: usize, found: usize },
+    NotNumeric { column: &'static str, value: String },
+    DuplicateDescriptor(String),
+    NotASequence(String),
+    OrphanElement(String),
+    UndefinedDescriptor { sequence: String, descriptor: String },
+    // numeric F/X/Y outside 0-255 (F, X) or 0-65535 (Y)
+    InvalidDescriptor { f: String, x: String, y: String },
+}
+
+// One problem found while loading a table, with the 1-based line it comes from
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableDiagnostic {
+    pub file: String,
+    pub line: u64,
+    pub issue: TableIssue,
+}
+
+impl fmt::Display for TableIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TableIssue::FieldCount { expected, found } => write!(f, "expected {} fields, found {}", expected, found),
+            TableIssue::NotNumeric { column, value } => write!(f, "{} is not numeric : {:?}", column, value),
+            TableIssue::DuplicateDescriptor(desc) => write!(f, "duplicate descriptor {} (last definition kept)", desc),
+            TableIssue::NotASequence(desc) => write!(f, "{} starts a sequence but F is not 3", desc),
+            TableIssue::OrphanElement(desc) => write!(f, "element {} does not belong to any sequence", desc),
+            TableIssue::UndefinedDescriptor { sequence, descriptor } => write!(f, "sequence {} references undefined descriptor {}", sequence, descriptor),
+            TableIssue::InvalidDescriptor { f: df, x, y } => write!(f, "invalid descriptor {}-{}-{}", df, x, y),
+        }
+    }
+}
+
+impl fmt::Display for TableDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.file, self.line, self.issue)
+    }
+}
+
+#[derive(Debug)]
+pub struct TableBRecord {
+    pub line: u64,
+    pub f: String,
+    pub x: String,
+    pub y: String,
+    pub description: String,
+    pub unit: String,
+    pub scale: String,
+    pub reference_value: String,
+    pub data_width_bits: String,
+}
+
+#[derive(Debug)]
+pub struct TableDRecord {
+    pub line: u64,
+    pub f: String,
+    pub x: String,
+    pub y: String,
+    pub df: String,
+    pub dx: String,
+    pub dy: String,
+}
+
+// Canonical "F-XX-YYY" key, the same form the decoder builds from Section 3
+pub fn fxy_key(f: &str, x: &str, y: &str) -> Option<String> {
+    let f = f.trim().parse::<u8>().ok()?;
+    let x = x.trim().parse::<u8>().ok()?;
+    let y = y.trim().parse::<u16>().ok()?;
+    Some(format!("{}-{:02}-{:03}", f, x, y))
+}
+
+// Canonical key of a descriptor typed by a user : "0-1-1" and "0-01-001" are both 0-01-001
+pub fn canonical_fxy(descriptor: &str) -> Option<String> {
+    match descriptor.split('-').collect::<Vec<_>>()[..] {
+        [f, x, y] => fxy_key(f, x, y),
+        _ => None,
+    }
+}
+
+fn file_name(file_path: &Path) -> String {
+    file_path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_else(|| file_path.display().to_string())
+}
+
+fn csv_rows<R: Read>(reader: R) -> Result<Rows, Box<dyn Error>> {
+    let mut rdr = ReaderBuilder::new()
+        .delimiter(b';')
+        .has_headers(false)
+        .flexible(true)
+        .quoting(false)
+        .comment(Some(b'#'))
+        .terminator(Terminator::Any(b'\n')) // localtabb_85_12 has stray CRs inside descriptions
+        .trim(Trim::All)
+        .from_reader(reader);
+    let mut rows = Vec::new();
+    for result in rdr.byte_records() {
+        let record = result?;
+        let line = record.position().map(|p| p.line()).unwrap_or(0);
+        // some local tables are Latin-1 encoded (French descriptions)
+        let fields: Vec<String> = record
+            .iter()
+            .map(|f| std::str::from_utf8(f).map(str::to_string).unwrap_or_else(|_| f.iter().map(|&b| b as char).collect()))
+            .collect();
+        if fields.iter().all(|f| f.is_empty()) {
+            continue; // blank line
+        }
+        rows.push((line, fields));
+    }
+    Ok(rows)
+}
+
+fn check_numeric(file: &str, line: u64, column: &'static str, value: &str, diagnostics: &mut Vec<TableDiagnostic>) -> bool {
+    if value.parse::<i64>().is_ok() {
+        return true;
+    }
+    diagnostics.push(TableDiagnostic {
+        file: file.to_string(),
+        line,
+        issue: TableIssue::NotNumeric { column, value: value.to_string() },
+    });
+    false
+}
+
+// Rows with a wrong field count or non-numeric F/X/Y/scale/reference/width are reported and skipped
+pub fn tables_b(file_path: &Path, diagnostics: &mut Vec<TableDiagnostic>) -> Result<Vec<TableBRecord>, Box<dyn Error>> {
+    records_b(BufReader::new(File::open(file_path)?), &file_name(file_path), diagnostics)
+}
+
+fn records_b<R: Read>(input: R, file: &str, diagnostics: &mut Vec<TableDiagnostic>) -> Result<Vec<TableBRecord>, Box<dyn Error>> {
+    let mut records = Vec::new();
+    for (line, record) in csv_rows(input)? {
+        if record.len() != 8 {
+            diagnostics.push(TableDiagnostic { file: file.to_string(), line, issue: TableIssue::FieldCount { expected: 8, found: record.len() } });
+            continue;
+        }
+        let columns = [("F", 0), ("X", 1), ("Y", 2), ("scale", 5), ("reference value", 6), ("data width", 7)];
+        let mut valid = true;
+        for (column, idx) in columns {
+            valid &= check_numeric(file, line, column, &record[idx], diagnostics);
+        }
+        if !valid {
+            continue;
+        }
+        records.push(TableBRecord {
+            line,
+            f: record[0].clone(),
+            x: record[1].clone(),
+            y: record[2].clone(),
+            description: record[3].clone(),
+            unit: record[4].clone(),
+            scale: record[5].clone(),
+            reference_value: record[6].clone(),
+            data_width_bits: record[7].clone(),
+        });
+    }
+    Ok(records)
+}
+
+pub fn tables_d(file_path: &Path, diagnostics: &mut Vec<TableDiagnostic>) -> Result<Vec<TableDRecord>, Box<dyn Error>> {
+    records_d(BufReader::new(File::open(file_path)?), &file_name(file_path), diagnostics)
+}
+
+fn records_d<R: Read>(input: R, file: &str, diagnostics: &mut Vec<TableDiagnostic>) -> Result<Vec<TableDRecord>, Box<dyn Error>> {
+    let mut records = Vec::new();
+    for (line, record) in csv_rows(input)? {
+        if record.len() != 6 {
+            diagnostics.push(TableDiagnostic { file: file.to_string(), line, issue: TableIssue::FieldCount { expected: 6, found: record.len() } });
+            continue;
+        }
+        let mut valid = true;
+        if !record[0].is_empty() {
+            for (column, idx) in [("F", 0), ("X", 1), ("Y", 2)] {
+                valid &= check_numeric(file, line, column, &record[idx], diagnostics);
+            }
+        }
+        for (column, idx) in [("element F", 3), ("element X", 4), ("element Y", 5)] {
+            valid &= check_numeric(file, line, column, &record[idx], diagnostics);
+        }
+        if !valid {
+            continue;
+        }
+        records.push(TableDRecord {
+            line,
+            f: record[0].clone(),
+            x: record[1].clone(),
+            y: record[2].clone(),
+            df: record[3].clone(),
+            dx: record[4].clone(),
+            dy: record[5].clone(),
+        });
+    }
+    Ok(records)
+}
+
+// Key of a descriptor, None with a diagnostic when it doesn't fit the F-XX-YYY form
+fn descriptor_key(file: &str, line: u64, f: &str, x: &str, y: &str, diagnostics: &mut Vec<TableDiagnostic>) -> Option<String> {
+    let key = fxy_key(f, x, y);
+    if key.is_none() {
+        diagnostics.push(TableDiagnostic {
+            file: file.to_string(),
+            line,
+            issue: TableIssue::InvalidDescriptor { f: f.to_string(), x: x.to_string(), y: y.to_string() },
+        });
+    }
+    key
+}
+
+pub fn dico_descriptor_b(file: &str, table_b_records: Vec<TableBRecord>, diagnostics: &mut Vec<TableDiagnostic>) -> DicoB {
+    let mut dico_desc: DicoB = HashMap::new();
+    for record in table_b_records {
+        let Some(key) = descriptor_key(file, record.line, &record.f, &record.x, &record.y, diagnostics) else { continue };
+        let mut value_map: HashMap<String, String> = HashMap::new();
+        value_map.insert("Description".to_string(), record.description);
+        value_map.insert("Unit".to_string(), record.unit);
+        value_map.insert("Scale".to_string(), record.scale);
+        value_map.insert("Ref_Val".to_string(), record.reference_value);
+        value_map.insert("Data_width_bits".to_string(), record.data_width_bits);
+        if dico_desc.insert(key.clone(), value_map).is_some() {
+            diagnostics.push(TableDiagnostic { file: file.to_string(), line: record.line, issue: TableIssue::DuplicateDescriptor(key) });
+        }
+    }
+    dico_desc
+}
+
+// A row with F filled starts a new sequence, following rows with only the element columns extend it.
+// Rows with an invalid descriptor are skipped, an invalid sequence descriptor skips the whole sequence.
+pub fn dico_descriptor_d(file: &str, table_d_records: &[TableDRecord], diagnostics: &mut Vec<TableDiagnostic>) -> DicoD {
+    let mut dico_desc: DicoD = HashMap::new();
+    let mut key1: Option<String> = None;
+
+    for record in table_d_records {
+        if !record.f.is_empty() {
+            key1 = None;
+            let Some(key) = descriptor_key(file, record.line, &record.f, &record.x, &record.y, diagnostics) else { continue };
+            if record.f != "3" {
+                diagnostics.push(TableDiagnostic { file: file.to_string(), line: record.line, issue: TableIssue::NotASequence(key) });
+                continue;
+            }
+            if dico_desc.insert(key.clone(), Vec::new()).is_some() {
+                diagnostics.push(TableDiagnostic { file: file.to_string(), line: record.line, issue: TableIssue::DuplicateDescriptor(key.clone()) });
+            }
+            key1 = Some(key);
+        }
+        let Some(element) = descriptor_key(file, record.line, &record.df, &record.dx, &record.dy, diagnostics) else { continue };
+        if let Some(key) = &key1 {
+            dico_desc.entry(key.clone()).or_default().push(element);
+        } else {
+            diagnostics.push(TableDiagnostic { file: file.to_string(), line: record.line, issue: TableIssue::OrphanElement(element) });
+        }
+    }
+    dico_desc
+}
+
+// Every element of a sequence must be an operator/replication (F = 1 or 2) or be defined
+// in one of the given B (F = 0) or D (F = 3) tables
+pub fn check_references(file: &str, table_d_records: &[TableDRecord], tables_b: &[&DicoB], tables_d: &[&DicoD], diagnostics: &mut Vec<TableDiagnostic>) {
+    let mut sequence = String::new();
+    for record in table_d_records {
+        if !record.f.is_empty() {
+            sequence = fxy_key(&record.f, &record.x, &record.y).unwrap_or_default();
+        }
+        let Some(element) = fxy_key(&record.df, &record.dx, &record.dy) else { continue };
+        let defined = match record.df.as_str() {
+            "0" => tables_b.iter().any(|b| b.contains_key(&element)),
+            "3" => tables_d.iter().any(|d| d.contains_key(&element)),
+            _ => true,
+        };
+        if !defined {
+            diagnostics.push(TableDiagnostic {
+                file: file.to_string(),
+                line: record.line,
+                issue: TableIssue::UndefinedDescriptor { sequence: sequence.clone(), descriptor: element },
+            });
+        }
+    }
+}
+
+pub fn load_table_b(file_path: &Path, diagnostics: &mut Vec<TableDiagnostic>) -> Result<DicoB, Box<dyn Error>> {
+    read_table_b(BufReader::new(File::open(file_path)?), &file_name(file_path), diagnostics)
+}
+
+pub fn load_table_d(file_path: &Path, diagnostics: &mut Vec<TableDiagnostic>) -> Result<DicoD, Box<dyn Error>> {
+    read_table_d(BufReader::new(File::open(file_path)?), &file_name(file_path), diagnostics)
+}
+
+// Same as load_table_b / load_table_d from any input, `file` names it in the diagnostics
+pub fn read_table_b<R: Read>(input: R, file: &str, diagnostics: &mut Vec<TableDiagnostic>) -> Result<DicoB, Box<dyn Error>> {
+    let records = records_b(input, file, diagnostics)?;
+    Ok(dico_descriptor_b(file, records, diagnostics))
+}
+
+pub fn read_table_d<R: Read>(input: R, file: &str, diagnostics: &mut Vec<TableDiagnostic>) -> Result<DicoD, Box<dyn Error>> {
+    let records = records_d(input, file, diagnostics)?;
+    Ok(dico_descriptor_d(file, &records, diagnostics))
+}
+
+// Names of the tables in a directory : <master_b><master>.csv and <local_b><centre>_<local>.csv,
+// the same for Table D
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableFiles {
+    pub master_b: String,
+    pub master_d: String,
+    pub local_b: String,
+    pub local_d: String,
+}
+
+impl Default for TableFiles {
+    fn default() -> Self {
+        TableFiles {
+            master_b: "bufrtabb_".to_string(),
+            master_d: "bufrtabd_".to_string(),
+            local_b: "localtabb_".to_string(),
+            local_d: "localtabd_".to_string(),
+        }
+    }
+}
+
+impl TableFiles {
+    pub fn master_b_path(&self, dir_path: &Path, master: u32) -> PathBuf {
+        dir_path.join(format!("{}{}.csv", self.master_b, master))
+    }
+
+    pub fn master_d_path(&self, dir_path: &Path, master: u32) -> PathBuf {
+        dir_path.join(format!("{}{}.csv", self.master_d, master))
+    }
+
+    pub fn local_b_path(&self, dir_path: &Path, centre: u32, local: u32) -> PathBuf {
+        dir_path.join(format!("{}{}_{}.csv", self.local_b, centre, local))
+    }
+
+    pub fn local_d_path(&self, dir_path: &Path, centre: u32, local: u32) -> PathBuf {
+        dir_path.join(format!("{}{}_{}.csv", self.local_d, centre, local))
+    }
+}
+
+// Master and local tables of one (master version, centre, local version), looked up local first
+#[derive(Debug, Clone, Default)]
+pub struct TableSet {
+    pub master_b: DicoB,
+    pub master_d: DicoD,
+    pub local_b: DicoB,
+    pub local_d: DicoD,
+}
+
+impl TableSet {
+    // The master tables are required, the local tables optional (see BufrDecoder::table_files)
+    pub fn load(dir_path: &Path, files: &TableFiles, master: u32, centre: u32, local: u32) -> Result<TableSet, Box<dyn Error>> {
+        let mut diagnostics = Vec::new();
+        let mut tables = TableSet {
+            master_b: load_table_b(&files.master_b_path(dir_path, master), &mut diagnostics)?,
+            master_d: load_table_d(&files.master_d_path(dir_path, master), &mut diagnostics)?,
+            ..TableSet::default()
+        };
+        let local_b_path = files.local_b_path(dir_path, centre, local);
+        if local_b_path.exists() {
+            tables.local_b = load_table_b(&local_b_path, &mut diagnostics)?;
+        }
+        let local_d_path = files.local_d_path(dir_path, centre, local);
+        if local_d_path.exists() {
+            tables.local_d = load_table_d(&local_d_path, &mut diagnostics)?;
+        }
+        Ok(tables)
+    }
+
+    pub fn entry_b(&self, descriptor: &str) -> Option<&HashMap<String, String>> {
+        self.local_b.get(descriptor).or_else(|| self.master_b.get(descriptor))
+    }
+
+    pub fn tables_d(&self) -> [&DicoD; 2] {
+        [&self.local_d, &self.master_d]
+    }
+}
+
+// Validate every master and local table of a directory.
+// Master sequences are checked against the master tables of the same version, local sequences
+// against their local tables plus the most recent master tables found.
+pub fn check_tables(dir_path: &Path, files: &TableFiles) -> Result<Vec<TableDiagnostic>, Box<dyn Error>> {
+    let mut diagnostics = Vec::new();
+    let mut masters: Vec<u32> = Vec::new();
+    let mut locals: Vec<(u32, u32)> = Vec::new();
+    for entry in fs::read_dir(dir_path)? {
+        let name = entry?.file_name().to_string_lossy().into_owned();
+        let Some(stem) = name.strip_suffix(".csv") else { continue };
+        if let Some(version) = stem.strip_prefix(files.master_b.as_str()).and_then(|v| v.parse().ok()) {
+            masters.push(version);
+        } else if let Some((centre, version)) = stem.strip_prefix(files.local_b.as_str()).and_then(|v| v.split_once('_')) {
+            if let (Ok(centre), Ok(version)) = (centre.parse(), version.parse()) {
+                locals.push((centre, version));
+            }
+        }
+    }
+    masters.sort_unstable();
+    locals.sort_unstable();
+
+    let mut latest_master: (DicoB, DicoD) = (HashMap::new(), HashMap::new());
+    for version in &masters {
+        let b = load_table_b(&files.master_b_path(dir_path, *version), &mut diagnostics)?;
+        let d_path = files.master_d_path(dir_path, *version);
+        let d = if d_path.exists() {
+            let records = tables_d(&d_path, &mut diagnostics)?;
+            let d = dico_descriptor_d(&file_name(&d_path), &records, &mut diagnostics);
+            check_references(&file_name(&d_path), &records, &[&b], &[&d], &mut diagnostics);
+            d
+        } else {
+            HashMap::new()
+        };
+        latest_master = (b, d);
+    }
+
+    for (centre, version) in &locals {
+        let b = load_table_b(&files.local_b_path(dir_path, *centre, *version), &mut diagnostics)?;
+        let d_path = files.local_d_path(dir_path, *centre, *version);
+        if d_path.exists() {
+            let records = tables_d(&d_path, &mut diagnostics)?;
+            let d = dico_descriptor_d(&file_name(&d_path), &records, &mut diagnostics);
+            check_references(&file_name(&d_path), &records, &[&b, &latest_master.0], &[&d, &latest_master.1], &mut diagnostics);
+        }
+    }
+    Ok(diagnostics)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn issues(diagnostics: &[TableDiagnostic]) -> Vec<(u64, TableIssue)> {
+        diagnostics.iter().map(|d| (d.line, d.issue.clone())).collect()
+    }
+
+    #[test]
+    fn table_b_rows() {
+        let csv = b"0;01;001;WMO block number;Numeric;0;0;7\n\
+                    0;01;002;WMO station number;Numeric;0;0\n\
+                    0;01;003;Region;Code table;x;0;3\n\
+                    0;01;001;Block again;Numeric;0;0;7\n\
+                    0;300;001;Too wide;Numeric;0;0;7\n\
+                    \n\
+                    0;01;015;Nom de la station \xe9tendu;CCITT IA5;0;0;160\n";
+        let mut diagnostics = Vec::new();
+        let dico = read_table_b(&csv[..], "b.csv", &mut diagnostics).unwrap();
+        assert_eq!(
+            issues(&diagnostics),
+            [
+                (2, TableIssue::FieldCount { expected: 8, found: 7 }),
+                (3, TableIssue::NotNumeric { column: "scale", value: "x".to_string() }),
+                (4, TableIssue::DuplicateDescriptor("0-01-001".to_string())),
+                (5, TableIssue::InvalidDescriptor { f: "0".to_string(), x: "300".to_string(), y: "001".to_string() }),
+            ]
+        );
+        assert_eq!(diagnostics[0].to_string(), "b.csv:2: expected 8 fields, found 7");
+        let mut keys: Vec<&String> = dico.keys().collect();
+        keys.sort();
+        assert_eq!(keys, ["0-01-001", "0-01-015"]);
+        assert_eq!(dico["0-01-001"]["Description"], "Block again"); // last definition kept
+        // Latin-1 bytes read one char each
+        assert_eq!(dico["0-01-015"]["Description"], "Nom de la station étendu");
+    }
+
+    #[test]
+    fn table_d_rows() {
+        let csv = b"3;01;001;0;01;001\n\
+                    ;;;0;01;002\n\
+                    ;;;3;01;099\n\
+                    ;;;0;01;999\n\
+                    3;01;001;0;01;003\n\
+                    0;01;004;0;01;001\n\
+                    ;;;0;01;002\n\
+                    3;01;002;0;1;1\n\
+                    ;;;2;300;0\n\
+                    3;256;003;0;01;001\n\
+                    ;;;0;01;002\n";
+        let mut diagnostics = Vec::new();
+        let records = records_d(&csv[..], "d.csv", &mut diagnostics).unwrap();
+        let dico = dico_descriptor_d("d.csv", &records, &mut diagnostics);
+        assert_eq!(
+            issues(&diagnostics),
+            [
+                (5, TableIssue::DuplicateDescriptor("3-01-001".to_string())),
+                (6, TableIssue::NotASequence("0-01-004".to_string())),
+                (7, TableIssue::OrphanElement("0-01-002".to_string())),
+                (9, TableIssue::InvalidDescriptor { f: "2".to_string(), x: "300".to_string(), y: "0".to_string() }),
+                (10, TableIssue::InvalidDescriptor { f: "3".to_string(), x: "256".to_string(), y: "003".to_string() }),
+                (11, TableIssue::OrphanElement("0-01-002".to_string())),
+            ]
+        );
+        assert!(!dico.contains_key(""));
+        assert_eq!(dico.len(), 2);
+        assert_eq!(dico["3-01-001"], ["0-01-003"]);
+        assert_eq!(dico["3-01-002"], ["0-01-001"]);
+
+        // the first definition of 3-01-001 references an undefined element and sequence
+        let b: DicoB = [("0-01-001".to_string(), HashMap::new()), ("0-01-002".to_string(), HashMap::new())].into();
+        let mut diagnostics = Vec::new();
+        check_references("d.csv", &records[..4], &[&b], &[&dico], &mut diagnostics);
+        let undefined = |line, descriptor: &str| (line, TableIssue::UndefinedDescriptor { sequence: "3-01-001".to_string(), descriptor: descriptor.to_string() });
+        assert_eq!(issues(&diagnostics), [undefined(3, "3-01-099"), undefined(4, "0-01-999")]);
+    }
+
+    #[test]
+    fn local_tables_first() {
+        let entry = |description: &str| HashMap::from([("Description".to_string(), description.to_string())]);
+        let tables = TableSet {
+            master_b: [("0-01-001".to_string(), entry("master")), ("0-01-002".to_string(), entry("master"))].into(),
+            local_b: [("0-01-001".to_string(), entry("local"))].into(),
+            ..TableSet::default()
+        };
+        assert_eq!(tables.entry_b("0-01-001").unwrap()["Description"], "local");
+        assert_eq!(tables.entry_b("0-01-002").unwrap()["Description"], "master");
+        assert!(tables.entry_b("0-01-003").is_none());
+        assert!(std::ptr::eq(tables.tables_d()[0], &tables.local_d));
+    }
+
+    #[test]
+    fn table_files() {
+        let files = TableFiles::default();
+        let dir = Path::new("tables");
+        assert_eq!(files.master_b_path(dir, 16), dir.join("bufrtabb_16.csv"));
+        assert_eq!(files.local_d_path(dir, 85, 14), dir.join("localtabd_85_14.csv"));
+    }
+}
@@ -0,0 +1,335 @@
+use std::fmt;
+
+// Canonical units for the Table B "Unit" column. The shipped CSVs spell the same
+// unit in many ways ("Meters", "Meter", "m", "M" ; "CCITT IA5", "CCITTIA5", "CCITT IA" ...),
+// so every string goes through Unit::parse before being compared or converted. Single letter
+// symbols are matched with their case : "A" is the ampere, "a" the year.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Unit {
+    CodeTable,
+    FlagTable,
+    Numeric,
+    Character, // CCITT IA5
+    Meter,
+    Kilometer,
+    Millimeter,
+    Foot,
+    GeopotentialMeter,
+    Kelvin,
+    Celsius,
+    Pascal,
+    Hectopascal,
+    MeterPerSecond,
+    Knot,
+    KilometerPerHour,
+    MillimeterPerHour,
+    Degree,
+    DegreeTrue,
+    Radian,
+    Second,
+    Minute,
+    Hour,
+    Day,
+    Month,
+    Year,
+    Ampere,
+    Percent,
+    PerMille,
+    Decibel,
+    Dbz,
+    Hertz,
+    KilogramPerSquareMeter,
+    KilogramPerSquareMeterPerSecond,
+    JoulePerSquareMeter,
+    WattPerSquareMeter,
+    DateComponent(String), // year, month, day ... of a date (0-04-001 to 0-04-007, 0-04-043), not a duration
+    Other(String), // anything not recognised, whitespace-normalised
+}
+
+// Physical dimension of a unit, only units sharing one can be converted
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Dimension {
+    Length,
+    Temperature,
+    Pressure,
+    Speed,
+    Angle,
+    Duration,
+    Ratio,
+}
+
+impl Unit {
+    pub fn parse(raw: &str) -> Unit {
+        let trimmed = raw.trim();
+        if trimmed.chars().count() == 1 {
+            return match trimmed {
+                "m" | "M" => Unit::Meter,
+                "K" => Unit::Kelvin,
+                "C" => Unit::Celsius,
+                "s" | "S" => Unit::Second,
+                "h" => Unit::Hour,
+                "d" => Unit::Day,
+                "a" => Unit::Year,
+                "A" => Unit::Ampere,
+                "%" => Unit::Percent,
+                _ => Unit::Other(trimmed.to_string()),
+            };
+        }
+        let key = raw.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+        if key.starts_with("code table") || key.starts_with("code-table") || key.starts_with("common code table") {
+            return Unit::CodeTable;
+        }
+        if key.starts_with("flag table") || key.starts_with("flag-table") {
+            return Unit::FlagTable;
+        }
+        if key.starts_with("ccitt") {
+            return Unit::Character;
+        }
+        match key.as_str() {
+            "numeric" | "number" => Unit::Numeric,
+            "meter" | "meters" | "metre" | "metres" => Unit::Meter,
+            "km" => Unit::Kilometer,
+            "mm" => Unit::Millimeter,
+            "ft" => Unit::Foot,
+            "gpm" => Unit::GeopotentialMeter,
+            "kelvin" => Unit::Kelvin,
+            "degc" | "°c" => Unit::Celsius,
+            "pa" | "pascal" => Unit::Pascal,
+            "hpa" => Unit::Hectopascal,
+            "m/s" | "m s-1" | "ms-1" | "m.s-1" => Unit::MeterPerSecond,
+            "kt" | "knot" | "knots" => Unit::Knot,
+            "km/h" | "km h-1" => Unit::KilometerPerHour,
+            "mm*h-1" | "mm/h" | "mm h-1" => Unit::MillimeterPerHour,
+            "degree" | "degrees" | "deg" => Unit::Degree,
+            "degree true" | "degre vrai" => Unit::DegreeTrue,
+            "rad" | "radians" => Unit::Radian,
+            "second" | "seconds" => Unit::Second,
+            "min" | "minute" | "minutes" => Unit::Minute,
+            "hour" | "hours" | "heure" => Unit::Hour,
+            "day" | "days" => Unit::Day,
+            "mon" | "month" => Unit::Month,
+            "year" => Unit::Year,
+            "ampere" => Unit::Ampere,
+            "0/00" | "part per thousand" => Unit::PerMille,
+            "db" | "decibels" => Unit::Decibel,
+            "dbz" => Unit::Dbz,
+            "hz" => Unit::Hertz,
+            "kg m-2" | "kgm-2" | "kg/m**2" => Unit::KilogramPerSquareMeter,
+            "kg m-2 s-1" => Unit::KilogramPerSquareMeterPerSecond,
+            "j m-2" | "j/m**2" => Unit::JoulePerSquareMeter,
+            "w m-2" => Unit::WattPerSquareMeter,
+            _ => Unit::Other(raw.split_whitespace().collect::<Vec<_>>().join(" ")),
+        }
+    }
+
+    // Unit of the Table B element `descriptor` : the date and time of day elements count in the
+    // calendar, a day of the month is not converted to hours
+    pub fn of_element(descriptor: &str, raw: &str) -> Unit {
+        match descriptor {
+            "0-04-001" | "0-04-002" | "0-04-003" | "0-04-004" | "0-04-005" | "0-04-006" | "0-04-007" | "0-04-043" => {
+                Unit::DateComponent(raw.split_whitespace().collect::<Vec<_>>().join(" "))
+            }
+            _ => Unit::parse(raw),
+        }
+    }
+
+    // Unit string as expected by the CF conventions / UDUNITS ("" for character data)
+    pub fn cf_units(&self) -> &str {
+        match self {
+            Unit::CodeTable | Unit::FlagTable | Unit::Numeric | Unit::DateComponent(_) => "1",
+            Unit::Character => "",
+            Unit::Meter => "m",
+            Unit::Kilometer => "km",
+            Unit::Millimeter => "mm",
+            Unit::Foot => "ft",
+            Unit::GeopotentialMeter => "gpm",
+            Unit::Kelvin => "K",
+            Unit::Celsius => "degC",
+            Unit::Pascal => "Pa",
+            Unit::Hectopascal => "hPa",
+            Unit::MeterPerSecond => "m s-1",
+            Unit::Knot => "knot",
+            Unit::KilometerPerHour => "km h-1",
+            Unit::MillimeterPerHour => "mm h-1",
+            Unit::Degree | Unit::DegreeTrue => "degree",
+            Unit::Radian => "rad",
+            Unit::Second => "s",
+            Unit::Minute => "min",
+            Unit::Hour => "h",
+            Unit::Day => "day",
+            Unit::Month => "month",
+            Unit::Year => "year",
+            Unit::Ampere => "A",
+            Unit::Percent => "%",
+            Unit::PerMille => "1e-3",
+            Unit::Decibel => "dB",
+            Unit::Dbz => "dBZ",
+            Unit::Hertz => "Hz",
+            Unit::KilogramPerSquareMeter => "kg m-2",
+            Unit::KilogramPerSquareMeterPerSecond => "kg m-2 s-1",
+            Unit::JoulePerSquareMeter => "J m-2",
+            Unit::WattPerSquareMeter => "W m-2",
+            Unit::Other(s) => s,
+        }
+    }
+
+    pub fn is_character(&self) -> bool {
+        *self == Unit::Character
+    }
+
+    // Dimension, scale factor and offset such that base = value * factor + offset
+    fn base(&self) -> Option<(Dimension, f64, f64)> {
+        let base = match self {
+            Unit::Meter => (Dimension::Length, 1.0, 0.0),
+            Unit::Kilometer => (Dimension::Length, 1000.0, 0.0),
+            Unit::Millimeter => (Dimension::Length, 0.001, 0.0),
+            Unit::Foot => (Dimension::Length, 0.3048, 0.0),
+            Unit::Kelvin => (Dimension::Temperature, 1.0, 0.0),
+            Unit::Celsius => (Dimension::Temperature, 1.0, 273.15),
+            Unit::Pascal => (Dimension::Pressure, 1.0, 0.0),
+            Unit::Hectopascal => (Dimension::Pressure, 100.0, 0.0),
+            Unit::MeterPerSecond => (Dimension::Speed, 1.0, 0.0),
+            Unit::Knot => (Dimension::Speed, 1852.0 / 3600.0, 0.0),
+            Unit::KilometerPerHour => (Dimension::Speed, 1.0 / 3.6, 0.0),
+            Unit::Degree | Unit::DegreeTrue => (Dimension::Angle, 1.0, 0.0),
+            Unit::Radian => (Dimension::Angle, 180.0 / std::f64::consts::PI, 0.0),
+            Unit::Second => (Dimension::Duration, 1.0, 0.0),
+            Unit::Minute => (Dimension::Duration, 60.0, 0.0),
+            Unit::Hour => (Dimension::Duration, 3600.0, 0.0),
+            Unit::Day => (Dimension::Duration, 86400.0, 0.0),
+            Unit::Percent => (Dimension::Ratio, 0.01, 0.0),
+            Unit::PerMille => (Dimension::Ratio, 0.001, 0.0),
+            _ => return None,
+        };
+        Some(base)
+    }
+
+    pub fn is_convertible_to(&self, target: &Unit) -> bool {
+        match (self.base(), target.base()) {
+            (Some((d1, _, _)), Some((d2, _, _))) => d1 == d2,
+            _ => self == target,
+        }
+    }
+
+    // Convert a decoded value expressed in this unit to `target`,
+    // None if the two units do not measure the same quantity
+    pub fn convert(&self, value: f64, target: &Unit) -> Option<f64> {
+        if self == target {
+            return Some(value);
+        }
+        let (d1, f1, o1) = self.base()?;
+        let (d2, f2, o2) = target.base()?;
+        if d1 != d2 {
+            return None;
+        }
+        Some((value * f1 + o1 - o2) / f2)
+    }
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Unit::CodeTable => write!(f, "Code table"),
+            Unit::FlagTable => write!(f, "Flag table"),
+            Unit::Numeric => write!(f, "Numeric"),
+            Unit::Character => write!(f, "CCITT IA5"),
+            Unit::DateComponent(raw) => write!(f, "{}", raw),
+            _ => write!(f, "{}", self.cf_units()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(expected: f64, found: Option<f64>) -> bool {
+        found.is_some_and(|found| (expected - found).abs() < 1e-9 * expected.abs().max(1.0))
+    }
+
+    #[test]
+    fn spellings() {
+        for raw in ["m", "M", "Meters", "Meter", " metre "] {
+            assert_eq!(Unit::parse(raw), Unit::Meter, "{:?}", raw);
+        }
+        for raw in ["CCITT IA5", "CCITTIA5", "CCITT IA", "ccitt  ia5"] {
+            assert_eq!(Unit::parse(raw), Unit::Character, "{:?}", raw);
+        }
+        for raw in ["Code table", "CODE TABLE 0 01 001", "Common Code table C-1", "code-table"] {
+            assert_eq!(Unit::parse(raw), Unit::CodeTable, "{:?}", raw);
+        }
+        assert_eq!(Unit::parse("Flag table"), Unit::FlagTable);
+        assert_eq!(Unit::parse("K"), Unit::Kelvin);
+        assert_eq!(Unit::parse("°C"), Unit::Celsius);
+        assert_eq!(Unit::parse("M S-1"), Unit::MeterPerSecond);
+        assert_eq!(Unit::parse("KT"), Unit::Knot);
+        assert_eq!(Unit::parse("Degree true"), Unit::DegreeTrue);
+        assert_eq!(Unit::parse("KG M-2"), Unit::KilogramPerSquareMeter);
+        assert_eq!(Unit::parse("dBZ"), Unit::Dbz);
+        assert_eq!(Unit::parse("A"), Unit::Ampere);
+        assert_eq!(Unit::parse("a"), Unit::Year);
+        assert_eq!(Unit::parse("d"), Unit::Day);
+        assert_eq!(Unit::parse("D"), Unit::Other("D".to_string()));
+        assert_eq!(Unit::parse("S"), Unit::Second);
+        assert_eq!(Unit::parse("C"), Unit::Celsius);
+    }
+
+    #[test]
+    fn date_components() {
+        for (descriptor, raw) in [("0-04-001", "a"), ("0-04-003", "d"), ("0-04-003", "Day"), ("0-04-004", "h")] {
+            let unit = Unit::of_element(descriptor, raw);
+            assert_eq!(unit, Unit::DateComponent(raw.to_string()));
+            assert_eq!((unit.to_string().as_str(), unit.cf_units()), (raw, "1"));
+            assert!(!unit.is_convertible_to(&Unit::Second));
+            assert_eq!(unit.convert(15.0, &Unit::Hour), None);
+        }
+        // the same unit in a time increment is a duration
+        assert!(close(48.0, Unit::of_element("0-04-013", "d").convert(2.0, &Unit::Hour)));
+    }
+
+    #[test]
+    fn unknown_units() {
+        let unit = Unit::parse("  furlong   per fortnight ");
+        assert_eq!(unit, Unit::Other("furlong per fortnight".to_string()));
+        assert_eq!(unit.cf_units(), "furlong per fortnight");
+        assert_eq!(unit.convert(3.0, &unit), Some(3.0));
+        assert_eq!(unit.convert(3.0, &Unit::Meter), None);
+        assert_eq!(Unit::Meter.convert(3.0, &unit), None);
+        assert!(!unit.is_convertible_to(&Unit::Meter));
+    }
+
+    #[test]
+    fn conversions() {
+        // length
+        assert!(close(1500.0, Unit::Kilometer.convert(1.5, &Unit::Meter)));
+        assert!(close(0.3048, Unit::Foot.convert(1.0, &Unit::Meter)));
+        assert!(close(2.0, Unit::Millimeter.convert(2000.0, &Unit::Meter)));
+        // temperature, an offset and no factor
+        assert!(close(0.0, Unit::Kelvin.convert(273.15, &Unit::Celsius)));
+        assert!(close(298.15, Unit::Celsius.convert(25.0, &Unit::Kelvin)));
+        // pressure
+        assert!(close(1013.25, Unit::Pascal.convert(101325.0, &Unit::Hectopascal)));
+        // speed, 1 knot = 1852 m per hour
+        assert!(close(0.514_444_444_444, Unit::Knot.convert(1.0, &Unit::MeterPerSecond)));
+        assert!(close(36.0, Unit::MeterPerSecond.convert(10.0, &Unit::KilometerPerHour)));
+        assert!(close(1.0, Unit::KilometerPerHour.convert(1.852, &Unit::Knot)));
+        // angle
+        assert!(close(180.0, Unit::Radian.convert(std::f64::consts::PI, &Unit::Degree)));
+        assert!(close(90.0, Unit::DegreeTrue.convert(90.0, &Unit::Degree)));
+        // duration
+        assert!(close(5400.0, Unit::Hour.convert(1.5, &Unit::Second)));
+        assert!(close(1440.0, Unit::Day.convert(1.0, &Unit::Minute)));
+        // ratio
+        assert!(close(125.0, Unit::Percent.convert(12.5, &Unit::PerMille)));
+    }
+
+    #[test]
+    fn other_dimensions_are_not_converted() {
+        assert_eq!(Unit::Meter.convert(1.0, &Unit::Second), None);
+        assert_eq!(Unit::Kelvin.convert(1.0, &Unit::Pascal), None);
+        assert_eq!(Unit::Dbz.convert(1.0, &Unit::Decibel), None);
+        assert!(Unit::Knot.is_convertible_to(&Unit::KilometerPerHour));
+        assert!(!Unit::Knot.is_convertible_to(&Unit::Degree));
+        assert!(Unit::Dbz.is_convertible_to(&Unit::Dbz));
+    }
+}
@@ -0,0 +1,27 @@
+// Paths and decoder shared by the integration tests, not every test file uses all of them
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use bufr_decoder::{BitReader, BufrDecoder, DataValue};
+
+pub fn data_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("data")
+}
+
+pub fn tables_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("..").join("tables")
+}
+
+pub fn decoder() -> BufrDecoder {
+    BufrDecoder::with_tables(tables_dir().to_string_lossy())
+}
+
+// Subsets of the corpus message tests/data/<name>.bufr
+pub fn subsets(name: &str) -> Vec<Vec<DataValue>> {
+    let message = fs::read(data_dir().join(name).with_extension("bufr")).unwrap();
+    let mut decoder = decoder();
+    decoder.decode_bufr_message(&mut BitReader::new(message.as_slice()), 8).unwrap().unwrap();
+    decoder.subsets().to_vec()
+}
@@ -0,0 +1,427 @@
+// Golden-file conformance suite : every tests/data/<name>.bufr is decoded and compared,
+// element by element, with tests/data/<name>.json (see tests/data/make_corpus.py).
+mod common;
+
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use serde_json::Value;
+
+use bufr_decoder::error::{BufrError, Warning};
+use bufr_decoder::encode::{self, encode_message};
+use bufr_decoder::filter::{ElementSelection, HeaderFilter, ReferenceTime};
+use bufr_decoder::options::{DecodeOptions, Strictness};
+use bufr_decoder::tables::TableSet;
+use bufr_decoder::{messages, BitReader, DataValue, DecodedNode, Header};
+
+use common::{data_dir, decoder, tables_dir};
+
+fn close(expected: f64, found: f64) -> bool {
+    (expected - found).abs() <= 1e-9 * expected.abs().max(1.0)
+}
+
+fn header_differences(expected: &Value, header: &Header, differences: &mut Vec<String>) {
+    let found = [
+        ("edition", Value::from(header.edition)),
+        ("centre", Value::from(header.centre)),
+        ("sub_centre", Value::from(header.sub_centre)),
+        ("category", Value::from(header.category)),
+        ("subcategory", Value::from(header.subcategory)),
+        ("master_table_version", Value::from(header.master_table_version)),
+        ("local_table_version", Value::from(header.local_table_version)),
+        ("number_of_subsets", Value::from(header.number_of_subsets)),
+        ("compressed", Value::from(header.compressed)),
+        ("optional_section", Value::from(header.sect2)),
+        ("reference_time", Value::from(header.reference_time())),
+        ("descriptors", Value::from(header.descriptors.clone())),
+    ];
+    for (key, found) in found {
+        if expected[key] != found {
+            differences.push(format!("{} : expected {}, found {}", key, expected[key], found));
+        }
+    }
+}
+
+fn element_difference(expected: &Value, found: &DataValue) -> Option<String> {
+    let fxy = expected["fxy"].as_str().unwrap_or_default();
+    if fxy != found.descriptor {
+        return Some(format!("expected {}, found {}", fxy, found.descriptor));
+    }
+    let matches = if expected["missing"] == Value::Bool(true) {
+        found.value.is_none() && found.text.is_none()
+    } else if let Some(text) = expected["text"].as_str() {
+        found.text.as_deref() == Some(text)
+    } else {
+        matches!((expected["value"].as_f64(), found.value), (Some(e), Some(f)) if close(e, f))
+    };
+    if matches {
+        return None;
+    }
+    Some(format!("{} : expected {}, found {:?} {:?}", fxy, expected, found.value, found.text))
+}
+
+fn subsets_differences(expected: &Value, subsets: &[Vec<DataValue>], differences: &mut Vec<String>) {
+    let expected = expected["subsets"].as_array().cloned().unwrap_or_default();
+    if expected.len() != subsets.len() {
+        differences.push(format!("expected {} subsets, found {}", expected.len(), subsets.len()));
+    }
+    for (index, (expected, found)) in expected.iter().zip(subsets).enumerate() {
+        let expected = expected.as_array().cloned().unwrap_or_default();
+        for (position, (e, f)) in expected.iter().zip(found).enumerate() {
+            if let Some(difference) = element_difference(e, f) {
+                differences.push(format!("subset {} element {} : {}", index + 1, position + 1, difference));
+            }
+        }
+        if expected.len() != found.len() {
+            differences.push(format!("subset {} : expected {} elements, found {}", index + 1, expected.len(), found.len()));
+        }
+    }
+}
+
+// Differences between the decoding of `bufr` and its expected JSON, empty when conform
+fn check(bufr: &Path) -> Vec<String> {
+    let expected: Value = match fs::read_to_string(bufr.with_extension("json")).map(|s| serde_json::from_str(&s)) {
+        Ok(Ok(expected)) => expected,
+        _ => return vec!["unreadable expected decoding".to_string()],
+    };
+    let mut decoder = decoder();
+    let mut reader = BitReader::new(BufReader::new(File::open(bufr).unwrap()));
+    match decoder.decode_bufr_message(&mut reader, 8) {
+        Ok(Some(_)) => {}
+        Ok(None) => return vec!["no message decoded".to_string()],
+        Err(e) => return vec![format!("decoding failed : {}", e)],
+    }
+    let mut differences = Vec::new();
+    header_differences(&expected, decoder.header().unwrap(), &mut differences);
+    subsets_differences(&expected, decoder.subsets(), &mut differences);
+    differences
+}
+
+#[test]
+fn golden_files() {
+    let files = corpus();
+    assert!(!files.is_empty(), "empty corpus");
+
+    let mut report = Vec::new();
+    for file in &files {
+        for difference in check(file) {
+            report.push(format!("{} : {}", file.file_name().unwrap().to_string_lossy(), difference));
+        }
+    }
+    assert!(report.is_empty(), "{} difference(s)\n{}", report.len(), report.join("\n"));
+}
+
+#[test]
+fn corpus_covers_editions_and_compression() {
+    let mut seen = Vec::new();
+    for entry in fs::read_dir(data_dir()).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_some_and(|e| e == "json") {
+            let expected: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+            seen.push((
+                expected["edition"].as_u64().unwrap(),
+                expected["compressed"].as_bool().unwrap(),
+                expected["centre"].as_u64().unwrap(),
+                expected["optional_section"].as_bool().unwrap(),
+            ));
+        }
+    }
+    for edition in [2, 3, 4] {
+        assert!(seen.iter().any(|s| s.0 == edition), "no edition {} message", edition);
+    }
+    assert!(seen.iter().any(|s| s.1) && seen.iter().any(|s| !s.1), "compressed and uncompressed messages expected");
+    assert!(seen.iter().any(|s| s.2 == 85) && seen.iter().any(|s| s.2 == 247), "centre 85 and 247 messages expected");
+    assert!(seen.iter().any(|s| s.3), "no message with a Section 2");
+}
+
+#[test]
+fn compressed_subsets_share_their_tree() {
+    let message = fs::read(data_dir().join("ed4_compressed.bufr")).unwrap();
+    let mut decoder = decoder();
+    decoder.decode_bufr_message(&mut BitReader::new(message.as_slice()), 8).unwrap().unwrap();
+    let trees = decoder.trees();
+    assert_eq!(trees.len(), 4);
+    assert!(trees.iter().all(|tree| Rc::ptr_eq(tree, &trees[0])));
+}
+
+#[test]
+fn truncated_message_error_is_located() {
+    let message = fs::read(data_dir().join("ed3_subsets.bufr")).unwrap();
+    let mut reader = BitReader::new(&message[..60]);
+    let error = decoder().decode_bufr_message(&mut reader, 8).unwrap_err();
+    assert!(matches!(error, BufrError::Truncated { .. }), "{}", error);
+    let at = error.location();
+    assert_eq!((at.message, at.byte_offset), (Some(1), Some(60)));
+    assert!(at.bit_offset.is_some() && !at.path.is_empty(), "{}", error);
+}
+
+#[test]
+fn non_matching_messages_are_not_decoded() {
+    // the edition 2 message names an unknown descriptor : only decoding it would fail
+    let mut skipped = fs::read(data_dir().join("ed2_replication_85.bufr")).unwrap();
+    let section3 = 8 + ((skipped[8] as usize) << 16 | (skipped[9] as usize) << 8 | skipped[10] as usize);
+    skipped[section3 + 7..section3 + 9].copy_from_slice(&[63, 255]);
+    let mut input = skipped.clone();
+    input.extend(fs::read(data_dir().join("ed3_subsets.bufr")).unwrap());
+    input.extend(skipped);
+    input.extend(fs::read(data_dir().join("ed4_compressed.bufr")).unwrap());
+
+    let mut decoder = decoder();
+    assert!(decoder.decode_bufr_message(&mut BitReader::new(input.as_slice()), 8).is_err());
+    let filter = HeaderFilter { master_table_version: Some(16), since: ReferenceTime::since("2024").ok(), ..HeaderFilter::default() };
+    let mut reader = BitReader::new(input.as_slice());
+    let mut found = Vec::new();
+    while decoder.decode_matching(&mut reader, 8, |header| filter.matches(header)).unwrap().is_some() {
+        let header = decoder.header().unwrap();
+        found.push((header.offset, header.edition, decoder.subsets().len()));
+    }
+    assert_eq!(found, [(78, 3, 3), (324, 4, 4)]);
+}
+
+#[test]
+fn bad_end_marker_is_a_warning_when_lenient() {
+    let mut message = fs::read(data_dir().join("ed4_operators.bufr")).unwrap();
+    let end = message.len() - 1;
+    message[end] = b'8';
+    let mut decoder = decoder();
+    assert!(matches!(decoder.decode_bufr_message(&mut BitReader::new(message.as_slice()), 8), Err(BufrError::BadEndMarker { .. })));
+
+    decoder.set_options(DecodeOptions { strictness: Strictness::Lenient, ..DecodeOptions::default() });
+    assert!(decoder.decode_bufr_message(&mut BitReader::new(message.as_slice()), 8).unwrap().is_some());
+    assert_eq!(decoder.subsets().len(), 1);
+    assert!(matches!(decoder.warnings(), [Warning::Nonconformity(BufrError::BadEndMarker { .. })]), "{:?}", decoder.warnings());
+}
+
+#[test]
+fn messages_borrow_their_slices() {
+    let mut input = Vec::new();
+    for name in ["ed2_replication_85", "ed3_compressed", "ed4_opera_247"] {
+        input.extend(fs::read(data_dir().join(name).with_extension("bufr")).unwrap());
+    }
+    let mut decoder = decoder();
+    let mut offsets = Vec::new();
+    let mut whole = BitReader::new(input.as_slice());
+    for message in messages(&input) {
+        let message = message.unwrap();
+        let offset = message.offset as usize;
+        assert!(std::ptr::eq(message.bytes, &input[offset..offset + message.bytes.len()]));
+        decoder.decode_bufr_message(&mut message.reader(), 8).unwrap().unwrap();
+        let header = decoder.header().unwrap().clone();
+        let subsets = decoder.subsets().to_vec();
+        decoder.decode_bufr_message(&mut whole, 8).unwrap().unwrap();
+        assert_eq!((decoder.header().unwrap(), decoder.subsets()), (&header, subsets.as_slice()));
+        offsets.push(header.offset);
+    }
+    assert_eq!(offsets.len(), 3);
+    assert_eq!(offsets[0], 0);
+
+    let error = messages(&input[..offsets[2] as usize + 20]).last().unwrap().unwrap_err();
+    assert!(matches!(error, BufrError::Truncated { .. }), "{}", error);
+    assert_eq!(error.location().message, Some(3));
+}
+
+#[test]
+fn slices_decode_as_the_stream() {
+    // the whole corpus then a truncated message, mapped (slices) or read from a file (stream)
+    let mut input = Vec::new();
+    for file in corpus() {
+        input.extend(fs::read(file).unwrap());
+    }
+    let first = fs::read(data_dir().join("ed3_subsets.bufr")).unwrap();
+    input.extend(&first[..first.len() - 10]);
+    let path = Path::new(env!("CARGO_TARGET_TMPDIR")).join("corpus.bufr");
+    fs::write(&path, &input).unwrap();
+
+    let mut stream = BitReader::new(BufReader::new(File::open(&path).unwrap()));
+    let (mut from_slices, mut from_stream) = (decoder(), decoder());
+    let mut decoded = 0;
+    for message in messages(&input) {
+        let Ok(message) = message else {
+            break;
+        };
+        from_slices.decode_bufr_message(&mut message.reader(), 8).unwrap().unwrap();
+        from_stream.decode_bufr_message(&mut stream, 8).unwrap().unwrap();
+        assert_eq!(from_slices.header(), from_stream.header(), "message {}", message.index);
+        assert_eq!(from_slices.subsets(), from_stream.subsets(), "message {}", message.index);
+        assert_eq!(from_slices.trees(), from_stream.trees(), "message {}", message.index);
+        assert_eq!(from_slices.subset_bits(), from_stream.subset_bits(), "message {}", message.index);
+        decoded += 1;
+    }
+    assert_eq!(decoded, corpus().len());
+
+    // the truncated message fails at the same place
+    let from_slices = messages(&input).last().unwrap().unwrap_err();
+    let from_stream = from_stream.decode_bufr_message(&mut stream, 8).unwrap_err();
+    assert!(matches!((&from_slices, &from_stream), (BufrError::Truncated { .. }, BufrError::Truncated { .. })), "{} / {}", from_slices, from_stream);
+    assert_eq!(from_slices.location().message, from_stream.location().message);
+    assert_eq!(from_slices.location().message, Some(decoded + 1));
+}
+
+#[test]
+fn describe_accepts_short_forms() {
+    let mut decoder = decoder();
+    decoder.load_tables(13, 85, 14).unwrap();
+    let sequence = decoder.describe("3-1-194").unwrap();
+    assert!(sequence.starts_with("3-01-194 (Table D local)\n"), "{}", sequence);
+    assert_eq!(decoder.describe(" 3-01-194").unwrap(), sequence);
+    assert!(decoder.describe("0-1-1").unwrap().starts_with("0-01-001 WMO block number (Table B master)\n"));
+
+    assert!(matches!(decoder.describe("1-01-002"), Err(BufrError::InvalidDescriptor { .. })));
+    assert!(matches!(decoder.describe("0-1"), Err(BufrError::InvalidDescriptor { .. })));
+    assert!(matches!(decoder.describe("0-63-250"), Err(BufrError::UnknownDescriptor { ref descriptor, .. }) if descriptor == "0-63-250"));
+    assert!(matches!(decoder.describe("3-63-250"), Err(BufrError::UnknownDescriptor { .. })));
+}
+
+// (descriptor, repetitions, delayed factor value) of every replication, depth first
+fn replications(nodes: &[DecodedNode], values: &[DataValue], found: &mut Vec<(String, usize, Option<f64>)>) {
+    for node in nodes {
+        match node {
+            DecodedNode::Sequence { children, .. } => replications(children, values, found),
+            DecodedNode::Replication { descriptor, factor, repetitions } => {
+                found.push((descriptor.clone(), repetitions.len(), factor.and_then(|f| values[f].value)));
+                for repetition in repetitions {
+                    replications(repetition, values, found);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[test]
+fn selected_elements_only() {
+    let message = fs::read(data_dir().join("ed2_replication_85.bufr")).unwrap();
+    let decode = |selection: Option<ElementSelection>| {
+        let mut decoder = decoder();
+        decoder.select(selection);
+        decoder.decode_bufr_message(&mut BitReader::new(message.as_slice()), 8).unwrap().unwrap();
+        let mut found = Vec::new();
+        replications(&decoder.trees()[0], &decoder.subsets()[0], &mut found);
+        (decoder.subsets()[0].clone(), found)
+    };
+    let (all, all_replications) = decode(None);
+    assert_eq!(all_replications, [("1-01-000".to_string(), 3, Some(3.0))]);
+
+    // typed as users do : short FXY, padded FXY, description pattern
+    let (selected, replications) = decode(Some(ElementSelection::new(&["0-12-101", "0-1-195", "0-04-01", "*year*"])));
+    let wanted = ["0-12-101", "0-01-195", "0-04-001"];
+    let expected: Vec<&DataValue> = all.iter().filter(|v| wanted.contains(&v.descriptor.as_str())).collect();
+    assert_eq!(expected.len(), 5);
+    assert_eq!(selected.iter().collect::<Vec<_>>(), expected);
+    // the factor is read to walk the tree, but only kept when selected
+    assert_eq!(replications, [("1-01-000".to_string(), 3, None)]);
+
+    let (selected, replications) = decode(Some(ElementSelection::new(&["0-12-101", "0-31-1"])));
+    assert_eq!(selected.iter().map(|v| v.descriptor.as_str()).collect::<Vec<_>>(), ["0-31-001", "0-12-101", "0-12-101", "0-12-101"]);
+    assert_eq!(replications, all_replications);
+}
+
+fn corpus() -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = fs::read_dir(data_dir())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|e| e == "bufr"))
+        .collect();
+    files.sort();
+    files
+}
+
+// decode, encode, decode : same subsets. The encoder writes editions 3 and 4 without
+// compression nor Section 2, the other messages are re-encoded in that form, and
+// byte for byte the same when already in it.
+#[test]
+fn encode_round_trip() {
+    let tables = tables_dir();
+    for file in corpus() {
+        let name = file.file_name().unwrap().to_string_lossy().into_owned();
+        let message = fs::read(&file).unwrap();
+        let mut decoder = decoder();
+        decoder.decode_bufr_message(&mut BitReader::new(message.as_slice()), 8).unwrap().unwrap();
+        let mut header = decoder.header().unwrap().clone();
+        let subsets = decoder.subsets().to_vec();
+        let same_form = header.edition >= 3 && !header.compressed && !header.sect2;
+        header.edition = header.edition.max(3);
+        header.compressed = false;
+        header.sect2 = false;
+
+        let set = TableSet::load(&tables, decoder.table_files(), header.master_table_version, header.centre, header.local_table_version).unwrap();
+        let values: Vec<Vec<encode::Value>> = subsets.iter().map(|subset| subset.iter().map(encode::Value::from).collect()).collect();
+        if header.descriptors.iter().any(|d| d.starts_with("2-03-")) {
+            // the new reference values aren't part of the decoded values
+            let e = encode_message(&header, &set, &values).unwrap_err();
+            assert!(e.to_string().contains("2-03-"), "{} : {}", name, e);
+            continue;
+        }
+        let encoded = encode_message(&header, &set, &values).unwrap_or_else(|e| panic!("{} : {}", name, e));
+        if same_form {
+            assert_eq!(encoded, message, "{}", name);
+        }
+
+        decoder.decode_bufr_message(&mut BitReader::new(encoded.as_slice()), 8).unwrap().unwrap();
+        assert_eq!(decoder.subsets(), subsets.as_slice(), "{}", name);
+    }
+}
+
+#[test]
+fn encode_invalid_table_entry() {
+    let message = fs::read(data_dir().join("ed3_subsets.bufr")).unwrap();
+    let mut decoder = decoder();
+    decoder.decode_bufr_message(&mut BitReader::new(message.as_slice()), 8).unwrap().unwrap();
+    let header = decoder.header().unwrap().clone();
+    let values: Vec<Vec<encode::Value>> = decoder.subsets().iter().map(|subset| subset.iter().map(encode::Value::from).collect()).collect();
+    let tables = tables_dir();
+    let mut set = TableSet::load(&tables, decoder.table_files(), header.master_table_version, header.centre, header.local_table_version).unwrap();
+    set.master_b.get_mut("0-01-002").unwrap().insert("Data_width_bits".to_string(), "ten".to_string());
+
+    let e = encode_message(&header, &set, &values).unwrap_err();
+    match e.downcast_ref::<BufrError>() {
+        Some(BufrError::InvalidTableEntry { descriptor, field, .. }) => assert_eq!((descriptor.as_str(), *field), ("0-01-002", "Data_width_bits")),
+        _ => panic!("{}", e),
+    }
+}
+
+#[test]
+fn encode_wider_than_32_bits() {
+    let message = fs::read(data_dir().join("ed3_subsets.bufr")).unwrap();
+    let mut decoder = decoder();
+    decoder.decode_bufr_message(&mut BitReader::new(message.as_slice()), 8).unwrap().unwrap();
+    let mut header = decoder.header().unwrap().clone();
+    header.descriptors = vec!["2-01-255".to_string(), "0-12-101".to_string()];
+    let tables = tables_dir();
+    let set = TableSet::load(&tables, decoder.table_files(), header.master_table_version, header.centre, header.local_table_version).unwrap();
+
+    // 16 bits + 127
+    for value in [encode::Value::Missing, encode::Value::Number(273.15)] {
+        let e = encode_message(&header, &set, &[vec![value]]).unwrap_err();
+        match e.downcast_ref::<BufrError>() {
+            Some(BufrError::InvalidOperator { descriptor, .. }) => assert!(descriptor.starts_with("2-01-255"), "{}", descriptor),
+            _ => panic!("{}", e),
+        }
+    }
+    // and characters on whole octets
+    header.descriptors = vec!["2-01-131".to_string(), "0-01-015".to_string()];
+    let e = encode_message(&header, &set, &[vec![encode::Value::Text("PARIS".to_string())]]).unwrap_err();
+    assert!(matches!(e.downcast_ref::<BufrError>(), Some(BufrError::InvalidOperator { descriptor, .. }) if descriptor.starts_with("2-01-131")), "{}", e);
+}
+
+#[test]
+fn extracted_subsets_decode() {
+    for (name, keep) in [("ed3_subsets", vec![0, 2]), ("ed3_section2", vec![1])] {
+        let message = fs::read(data_dir().join(name).with_extension("bufr")).unwrap();
+        let mut decoder = decoder();
+        decoder.decode_bufr_message(&mut BitReader::new(message.as_slice()), 8).unwrap().unwrap();
+        let header = decoder.header().unwrap().clone();
+        let all = decoder.subsets().to_vec();
+
+        let extracted = encode::extract_subsets(&message, &header, decoder.subset_bits(), &keep).unwrap();
+        assert_eq!(messages(&extracted).count(), 1, "{}", name);
+        decoder.decode_bufr_message(&mut BitReader::new(extracted.as_slice()), 8).unwrap().unwrap();
+        let found = decoder.header().unwrap();
+        assert_eq!((found.number_of_subsets as usize, found.sect2), (keep.len(), header.sect2), "{}", name);
+        let expected: Vec<Vec<DataValue>> = keep.iter().map(|i| all[*i].clone()).collect();
+        assert_eq!(decoder.subsets(), expected.as_slice(), "{}", name);
+        assert!(decoder.warnings().is_empty(), "{} : {:?}", name, decoder.warnings());
+    }
+}
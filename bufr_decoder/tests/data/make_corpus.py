@@ -0,0 +1,256 @@
+#!/usr/bin/env python3
+# Builds the conformance corpus : every case is written as <name>.bufr with its expected
+# decoding <name>.json. The values are packed here from the WMO rules and the widths,
+# scales and references written below, independently of the Rust decoder.
+#   python3 tests/data/make_corpus.py
+import json
+import os
+import zlib
+
+HERE = os.path.dirname(os.path.abspath(__file__))
+
+
+class Bits:
+    def __init__(self):
+        self.bits = []
+
+    def put(self, value, width):
+        for i in range(width - 1, -1, -1):
+            self.bits.append((value >> i) & 1)
+
+    def octets(self, even):
+        bits = self.bits + [0] * (-len(self.bits) % 8)
+        out = bytes(int("".join(map(str, bits[i:i + 8])), 2) for i in range(0, len(bits), 8))
+        if even and len(out) % 2:
+            out += b"\0"
+        return out
+
+
+def ones(width):
+    return (1 << width) - 1
+
+
+# Elements : (fxy, width, scale, reference, value) for numbers, value None when missing,
+# (fxy, chars, text) for CCITT IA5. 2-03 new reference values : ("new_ref", fxy, bits, reference)
+def num(fxy, width, scale, ref, value):
+    return ("num", fxy, width, scale, ref, value)
+
+
+def txt(fxy, chars, text):
+    return ("txt", fxy, chars, text)
+
+
+def new_ref(fxy, bits, ref):
+    return ("new_ref", fxy, bits, ref)
+
+
+def raw_of(element):
+    _, fxy, width, scale, ref, value = element
+    if value is None:
+        return ones(width)
+    raw = round(value * 10 ** scale) - ref
+    assert 0 <= raw < ones(width) or fxy.startswith("0-31-"), element
+    return raw
+
+
+def expected_of(element):
+    if element[0] == "num":
+        _, fxy, width, scale, ref, value = element
+        if value is None:
+            return {"fxy": fxy, "missing": True}
+        return {"fxy": fxy, "value": (raw_of(element) + ref) / 10 ** scale}
+    _, fxy, chars, text = element
+    if text is None:
+        return {"fxy": fxy, "missing": True}
+    return {"fxy": fxy, "text": text.rstrip(" ")}
+
+
+def new_ref_raw(bits, ref):
+    return (1 << (bits - 1)) | -ref if ref < 0 else ref
+
+
+def section4_uncompressed(subsets, data):
+    for subset in subsets:
+        for element in subset:
+            if element[0] == "num":
+                data.put(raw_of(element), element[2])
+            elif element[0] == "txt":
+                _, fxy, chars, text = element
+                octets = b"\xff" * chars if text is None else text.ljust(chars).encode("ascii")
+                for octet in octets:
+                    data.put(octet, 8)
+            else:
+                _, fxy, bits, ref = element
+                data.put(new_ref_raw(bits, ref), bits)
+
+
+def section4_compressed(subsets, data):
+    # same elements in every subset, R0 + NBINC + increments
+    for column in zip(*subsets):
+        first = column[0]
+        if first[0] == "num":
+            width = first[2]
+            raws = [raw_of(e) for e in column]
+            present = [r for e, r in zip(column, raws) if e[5] is not None]
+            if all(e[5] is None for e in column):
+                data.put(ones(width), width)
+                data.put(0, 6)
+                continue
+            r0 = min(present)
+            increments = [None if e[5] is None else r - r0 for e, r in zip(column, raws)]
+            if all(i == increments[0] for i in increments):
+                data.put(r0 + increments[0], width)
+                data.put(0, 6)
+                continue
+            largest = max(i for i in increments if i is not None)
+            nbinc = largest.bit_length()
+            if largest == ones(nbinc):  # all ones is the missing increment
+                nbinc += 1
+            data.put(r0, width)
+            data.put(nbinc, 6)
+            for i in increments:
+                data.put(ones(nbinc) if i is None else i, nbinc)
+        elif first[0] == "txt":
+            chars = first[2]
+            texts = [b"\xff" * chars if e[3] is None else e[3].ljust(chars).encode("ascii") for e in column]
+            if all(t == texts[0] for t in texts):
+                for octet in texts[0]:
+                    data.put(octet, 8)
+                data.put(0, 6)
+                continue
+            for _ in range(chars):
+                data.put(0, 8)
+            data.put(chars, 6)
+            for text in texts:
+                for octet in text:
+                    data.put(octet, 8)
+        else:
+            _, fxy, bits, ref = first
+            data.put(new_ref_raw(bits, ref), bits)
+            data.put(0, 6)
+
+
+def descriptor(fxy):
+    f, x, y = map(int, fxy.split("-"))
+    return bytes([(f << 6) | x, y])
+
+
+# `section2` : local data of an optional Section 2, None without
+def message(edition, centre, master, local, descriptors, subsets, compressed=False, category=0, subcategory=0, sub_centre=0,
+            time=(2024, 12, 28, 12, 0, 0), section2=None):
+    year, month, day, hour, minute, second = time
+    optional = 0x80 if section2 is not None else 0
+    if edition == 4:
+        section1 = (22).to_bytes(3, "big") + bytes([0]) + centre.to_bytes(2, "big") + sub_centre.to_bytes(2, "big")
+        section1 += bytes([0, optional, category, subcategory, 0, master, local]) + year.to_bytes(2, "big")
+        section1 += bytes([month, day, hour, minute, second])
+    else:
+        section1 = (18).to_bytes(3, "big") + bytes([0, sub_centre, centre, 0, optional, category, subcategory, master, local])
+        section1 += bytes([year % 100, month, day, hour, minute, 0])
+    if section2 is not None:
+        if edition < 4 and (4 + len(section2)) % 2:
+            section2 += b"\0"
+        section1 += (4 + len(section2)).to_bytes(3, "big") + b"\0" + section2
+    described = b"".join(descriptor(d) for d in descriptors)
+    if edition < 4 and (7 + len(described)) % 2:
+        described += b"\0"
+    flags = 0x80 | (0x40 if compressed else 0)
+    section3 = (7 + len(described)).to_bytes(3, "big") + b"\0" + len(subsets).to_bytes(2, "big") + bytes([flags]) + described
+    data = Bits()
+    (section4_compressed if compressed else section4_uncompressed)(subsets, data)
+    octets = data.octets(even=edition < 4)
+    section4 = (4 + len(octets)).to_bytes(3, "big") + b"\0" + octets
+    body = section1 + section3 + section4 + b"7777"
+    bufr = b"BUFR" + (8 + len(body)).to_bytes(3, "big") + bytes([edition]) + body
+
+    expected = {
+        "edition": edition,
+        "centre": centre,
+        "sub_centre": sub_centre,
+        "category": category,
+        "subcategory": subcategory,
+        "master_table_version": master,
+        "local_table_version": local,
+        "number_of_subsets": len(subsets),
+        "compressed": compressed,
+        "optional_section": section2 is not None,
+        "reference_time": "%04d-%02d-%02dT%02d:%02d:%02d" % (year, month, day, hour, minute, second if edition == 4 else 0),
+        "descriptors": descriptors,
+        "subsets": [[expected_of(e) for e in subset if e[0] != "new_ref"] for subset in subsets],
+    }
+    return bufr, expected
+
+
+def date(year, month, day, hour):
+    return [num("0-04-001", 12, 0, 0, year), num("0-04-002", 4, 0, 0, month), num("0-04-003", 6, 0, 0, day), num("0-04-004", 5, 0, 0, hour)]
+
+
+CASES = {}
+
+# Edition 2, master 13, Météo-France local tables 12 : sequence and delayed replication
+CASES["ed2_replication_85"] = message(2, 85, 13, 12, ["3-01-011", "0-04-004", "1-01-000", "0-31-001", "0-12-101", "0-01-196", "0-01-195"], [
+    date(2009, 3, 14, 6)
+    + [num("0-31-001", 8, 0, 0, 3), num("0-12-101", 16, 2, 0, 271.35), num("0-12-101", 16, 2, 0, None), num("0-12-101", 16, 2, 0, 280.1)]
+    + [num("0-01-196", 8, 0, 0, 31), txt("0-01-195", 9, "FMOB42")],
+], category=0, subcategory=1, time=(2009, 3, 14, 6, 0, 0))
+
+# Edition 3, three uncompressed subsets : strings, fixed replication, missing values
+STATIONS = [(7, 149, "PARIS-MONTSOURIS", 48.82167, 2.3375, 75), (7, 481, "LYON-ST EXUPERY", 45.72639, 5.07778, 235), (7, 650, None, None, 6.36, 1)]
+CASES["ed3_subsets"] = message(3, 85, 16, 14, ["0-01-001", "0-01-002", "0-01-015", "0-05-001", "0-06-001", "0-07-001", "1-02-002", "0-11-001", "0-11-002"], [
+    [num("0-01-001", 7, 0, 0, block), num("0-01-002", 10, 0, 0, number), txt("0-01-015", 20, name),
+     num("0-05-001", 25, 5, -9000000, lat), num("0-06-001", 26, 5, -18000000, lon), num("0-07-001", 15, 0, -400, height),
+     num("0-11-001", 9, 0, 0, 270), num("0-11-002", 12, 1, 0, 3.4 + i), num("0-11-001", 9, 0, 0, None), num("0-11-002", 12, 1, 0, 0)]
+    for i, (block, number, name, lat, lon, height) in enumerate(STATIONS)
+], category=0, subcategory=2, time=(2024, 2, 29, 18, 30, 0))
+
+# Edition 4, four compressed subsets : varying, constant and missing columns, strings, delayed replication
+CASES["ed4_compressed"] = message(4, 85, 16, 14, ["0-01-001", "0-01-002", "0-01-015", "0-12-101", "0-10-004", "1-01-000", "0-31-001", "0-13-011"], [
+    [num("0-01-001", 7, 0, 0, 7), num("0-01-002", 10, 0, 0, number), txt("0-01-015", 20, name), num("0-12-101", 16, 2, 0, temperature),
+     num("0-10-004", 14, -1, 0, 101320), num("0-31-001", 8, 0, 0, 2), num("0-13-011", 14, 1, -1, None), num("0-13-011", 14, 1, -1, rain)]
+    for number, name, temperature, rain in [(149, "PARIS", 285.15, 0.2), (481, "LYON", None, 0), (650, "NICE", 290.05, 12.6), (761, "AJACCIO", 289.95, None)]
+], compressed=True, category=0, subcategory=0, sub_centre=5, time=(2024, 12, 28, 12, 0, 30))
+
+# Edition 4, Table C operators : 2-01 width, 2-02 scale, 2-08 string width, 2-03 new reference values
+CASES["ed4_operators"] = message(4, 85, 16, 14,
+    ["2-01-132", "0-12-101", "2-01-000", "2-02-129", "0-10-004", "2-02-000", "2-08-010", "0-01-015", "2-08-000",
+     "2-03-016", "0-12-101", "2-03-255", "0-12-101", "2-03-000", "0-12-101"], [
+    [num("0-12-101", 20, 2, 0, 301.27), num("0-10-004", 14, 0, 0, 9876), txt("0-01-015", 10, "TOULOUSE"),
+     new_ref("0-12-101", 16, -30000), num("0-12-101", 16, 2, -30000, -20.5), num("0-12-101", 16, 2, 0, 250)],
+])
+
+# Edition 3, three compressed subsets : 2-01 width change and extended delayed replication
+CASES["ed3_compressed"] = message(3, 85, 16, 14, ["3-01-011", "2-01-130", "0-12-101", "2-01-000", "1-01-000", "0-31-002", "0-11-002"], [
+    date(2023, 7, 1, hour)[:3] + [num("0-12-101", 18, 2, 0, temperature), num("0-31-002", 16, 0, 0, 2), num("0-11-002", 12, 1, 0, wind), num("0-11-002", 12, 1, 0, 2 * wind)]
+    for hour, temperature, wind in [(0, 293.5, 1.5), (0, 294.25, 0), (0, 292, 7.5)]
+], compressed=True, time=(2023, 7, 1, 0, 0, 0))
+
+# Edition 4, OPERA composite with the centre 247 local tables (3-01-192, 3-01-193, 3-21-206)
+def lat_lon(lat, lon):
+    return [num("0-05-002", 15, 2, -9000, lat), num("0-06-002", 16, 2, -18000, lon)]
+
+PIXELS = zlib.compress(bytes([0, 10, 20, 30, 40, 50, 60, 255, 1, 2, 3, 4]))
+CASES["ed4_opera_247"] = message(4, 247, 16, 8, ["3-01-192", "3-01-193", "0-21-198", "0-21-199", "3-21-206"], [
+    date(2024, 12, 28, 12) + [num("0-04-005", 6, 0, 0, 15)]
+    + lat_lon(51, 2) + lat_lon(51, 2.06) + lat_lon(50.97, 2.06) + lat_lon(50.97, 2)
+    + [num("0-29-201", 5, 0, 0, 1)] + lat_lon(90, 0)
+    + [num("0-05-033", 16, -1, 0, 1000), num("0-06-033", 16, -1, 0, 1000), num("0-30-021", 12, 0, 0, 4), num("0-30-022", 12, 0, 0, 3)]
+    + [num("0-29-199", 26, 0, 0, 6378137), num("0-29-200", 26, 0, 0, 6356752), num("0-29-193", 16, 2, -18000, 10), num("0-29-194", 15, 2, -9000, 90),
+       num("0-29-195", 26, 0, -33554432, -500000), num("0-29-196", 26, 0, -33554432, 250000), num("0-29-197", 15, 2, -9000, 45), num("0-29-198", 15, 2, -9000, 45)]
+    + [num("0-21-198", 14, 2, -6400, -32), num("0-21-199", 7, 1, 0, 0.5), num("0-30-197", 8, 0, 0, 0), num("0-31-002", 16, 0, 0, 1), num("0-31-002", 16, 0, 0, len(PIXELS))]
+    + [num("0-30-198", 8, 0, 0, octet) for octet in PIXELS],
+], category=6, subcategory=0)
+
+# Edition 3 with an optional Section 2 of local data, padded to an even length
+CASES["ed3_section2"] = message(3, 85, 16, 14, ["0-01-001", "0-01-002", "0-12-101"], [
+    [num("0-01-001", 7, 0, 0, 7), num("0-01-002", 10, 0, 0, 149), num("0-12-101", 16, 2, 0, 280.15)],
+    [num("0-01-001", 7, 0, 0, 7), num("0-01-002", 10, 0, 0, 481), num("0-12-101", 16, 2, 0, None)],
+], time=(2024, 12, 28, 6, 0, 0), section2=b"MF LOCAL\x01")
+
+if __name__ == "__main__":
+    for name, (bufr, expected) in CASES.items():
+        with open(os.path.join(HERE, name + ".bufr"), "wb") as f:
+            f.write(bufr)
+        with open(os.path.join(HERE, name + ".json"), "w") as f:
+            json.dump(expected, f, indent=1)
+            f.write("\n")
+        print(name, len(bufr), "octets")
@@ -0,0 +1,157 @@
+// Crafted messages that used to panic, loop or exhaust memory, found by the fuzz targets
+mod common;
+
+
+use bufr_decoder::error::BufrError;
+use bufr_decoder::options::{DecodeOptions, Limit, Limits};
+use bufr_decoder::BitReader;
+
+use common::{decoder};
+
+// Edition 4 message of centre 85, tables 16/14, with the given (F, X, Y) descriptors and data
+fn message(descriptors: &[(u8, u8, u8)], data: &[u8], subsets: u16, compressed: bool) -> Vec<u8> {
+    let mut body = vec![0, 0, 22, 0, 0, 85, 0, 0, 0, 0, 0, 0, 0, 16, 14, 0x07, 0xe8, 1, 1, 0, 0, 0];
+    body.extend(&(7 + 2 * descriptors.len() as u32).to_be_bytes()[1..]);
+    body.push(0);
+    body.extend(subsets.to_be_bytes());
+    body.push(if compressed { 0xc0 } else { 0x80 });
+    for (f, x, y) in descriptors {
+        body.extend([f << 6 | x, *y]);
+    }
+    body.extend(&(4 + data.len() as u32).to_be_bytes()[1..]);
+    body.push(0);
+    body.extend(data);
+    body.extend(b"7777");
+    let mut message = b"BUFR".to_vec();
+    message.extend(&(8 + body.len() as u32).to_be_bytes()[1..]);
+    message.push(4);
+    message.extend(body);
+    message
+}
+
+// Error the decoding of `message` stops with
+fn decode(message: &[u8]) -> Option<BufrError> {
+    decoder().decode_bufr_message(&mut BitReader::new(message), 8).err()
+}
+
+#[test]
+fn new_reference_values_wider_than_32_bits() {
+    let message = message(&[(2, 3, 200), (0, 12, 101), (2, 3, 255), (0, 12, 101)], &[0xff; 40], 1, false);
+    assert!(matches!(decode(&message), Some(BufrError::InvalidOperator { .. })));
+}
+
+#[test]
+fn elements_wider_than_32_bits() {
+    // 2-01-255 : 16 + 127 bits for 0-12-101, 2-08-010 : 80 bits
+    for compressed in [false, true] {
+        let wider = message(&[(2, 1, 255), (0, 12, 101)], &[0; 40], 1, compressed);
+        assert!(matches!(decode(&wider), Some(BufrError::InvalidOperator { ref descriptor, .. }) if descriptor.starts_with("2-01-255")));
+        let wider = message(&[(2, 8, 10), (0, 12, 101)], &[0; 40], 1, compressed);
+        assert!(matches!(decode(&wider), Some(BufrError::InvalidOperator { ref descriptor, .. }) if descriptor.starts_with("2-08-010")));
+    }
+    // still read within 32 bits
+    let message = message(&[(2, 1, 144), (0, 12, 101)], &[0; 4], 1, false);
+    assert!(decode(&message).is_none());
+}
+
+#[test]
+fn characters_on_part_of_an_octet() {
+    // 2-01-131 : 160 + 3 bits for the station name, 3 bits would shift every later field
+    for compressed in [false, true] {
+        let message = message(&[(2, 1, 131), (0, 1, 15), (2, 1, 0), (0, 12, 101)], &[0x41; 40], 1, compressed);
+        assert!(matches!(decode(&message), Some(BufrError::InvalidOperator { ref descriptor, .. }) if descriptor.starts_with("2-01-131 (163 bits")));
+    }
+}
+
+#[test]
+fn replication_of_zero_width_elements() {
+    // 2-01-001 takes 127 bits off every width : 255^3 repetitions reading nothing
+    let message = message(&[(2, 1, 1), (1, 3, 255), (1, 2, 255), (1, 1, 255), (0, 12, 101)], &[0; 8], 1, false);
+    assert!(matches!(decode(&message), Some(BufrError::InvalidReplication { .. })));
+}
+
+#[test]
+fn delayed_replication_beyond_section_4() {
+    let message = message(&[(1, 1, 0), (0, 31, 2), (0, 12, 101)], &[0xff, 0xfe, 0, 0], 1, false);
+    assert!(matches!(decode(&message), Some(BufrError::InvalidReplication { .. })));
+}
+
+#[test]
+fn compressed_subsets_amplification() {
+    // 65535 subsets sharing 6 bit elements : a few hundred octets would decode to gigabytes
+    let message = message(&[(1, 1, 255), (0, 12, 101), (1, 1, 255), (0, 12, 101)], &[0; 256], u16::MAX, true);
+    assert!(matches!(decode(&message), Some(BufrError::TooManyValues { .. })));
+}
+
+#[test]
+fn short_section_lengths() {
+    let mut message = message(&[(0, 12, 101)], &[0; 2], 1, false);
+    message[32] = 3; // Section 3 length
+    assert!(matches!(decode(&message), Some(BufrError::InvalidLength { section: 3, .. })));
+}
+
+// Error of decoding `message` within `limits`
+fn limited(limits: Limits, message: &[u8]) -> Option<BufrError> {
+    let mut decoder = decoder();
+    decoder.set_options(DecodeOptions { limits, ..DecodeOptions::default() });
+    decoder.decode_bufr_message(&mut BitReader::new(message), 8).err()
+}
+
+// 1-01-000 0-31-001 0-12-101 repeated 3 times
+fn delayed() -> Vec<u8> {
+    let delayed = message(&[(1, 1, 0), (0, 31, 1), (0, 12, 101)], &[3, 0, 0, 0, 0, 0, 0], 1, false);
+    assert!(decode(&delayed).is_none());
+    delayed
+}
+
+#[test]
+fn message_length_limit() {
+    let error = limited(Limits { max_message_length: 40, ..Limits::default() }, &delayed());
+    assert!(matches!(error, Some(BufrError::LimitExceeded { limit: Limit::MessageLength, max: 40, .. })));
+}
+
+#[test]
+fn replication_limit() {
+    let error = limited(Limits { max_replication: 2, ..Limits::default() }, &delayed());
+    assert!(matches!(error, Some(BufrError::LimitExceeded { limit: Limit::Replication, value: 3, max: 2, .. })));
+    // by default : 0-31-002 widened by 2-01-129 to count 65536 repetitions
+    let wide = message(&[(2, 1, 129), (1, 1, 0), (0, 31, 2), (0, 12, 101)], &[0x80, 0, 0, 0], 1, false);
+    assert!(matches!(decode(&wide), Some(BufrError::LimitExceeded { limit: Limit::Replication, value: 65536, max: 65535, .. })));
+}
+
+#[test]
+fn string_length_limit() {
+    let text = message(&[(0, 0, 1)], b"ABC\0", 1, false);
+    assert!(decode(&text).is_none());
+    let error = limited(Limits { max_string_length: 2, ..Limits::default() }, &text);
+    assert!(matches!(error, Some(BufrError::LimitExceeded { limit: Limit::StringLength, value: 3, max: 2, .. })));
+}
+
+#[test]
+fn depth_limit() {
+    let error = limited(Limits { max_depth: 0, ..Limits::default() }, &delayed());
+    match error {
+        Some(BufrError::LimitExceeded { limit: Limit::Depth, value: 1, max: 0, at }) => assert_eq!(at.path, ["1-01-000"]),
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn descriptors_limit() {
+    let error = limited(Limits { max_descriptors: 1, ..Limits::default() }, &delayed());
+    assert!(matches!(error, Some(BufrError::LimitExceeded { limit: Limit::Descriptors, value: 2, max: 1, .. })));
+}
+
+#[test]
+fn values_limit() {
+    // the factor, 3 values and the replication
+    let error = limited(Limits { max_values: 5, ..Limits::default() }, &delayed());
+    assert!(error.is_none());
+    let error = limited(Limits { max_values: 4, ..Limits::default() }, &delayed());
+    assert!(matches!(error, Some(BufrError::LimitExceeded { limit: Limit::Values, value: 5, max: 4, .. })));
+    // compressed subsets are counted one by one
+    let compressed = message(&[(0, 12, 101)], &[0; 4], 10, true);
+    assert!(decode(&compressed).is_none());
+    let error = limited(Limits { max_values: 9, ..Limits::default() }, &compressed);
+    assert!(matches!(error, Some(BufrError::LimitExceeded { limit: Limit::Values, value: 10, max: 9, .. })));
+}
@@ -0,0 +1,442 @@
+// Files written from decoded messages, parsed back : NetCDF headers, JSON trees and CSV / TSV
+// tables, and the listings and pipes of the command line
+mod common;
+
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use serde_json::{json, Value};
+
+use bufr_decoder::export::{CsvExporter, CsvOptions};
+use bufr_decoder::netcdf::{write_netcdf, Dataset};
+use bufr_decoder::{json, BitReader, DataValue};
+
+use common::{data_dir, decoder, subsets, tables_dir};
+
+// Header of a CDF-2 (64-bit offset) file
+#[derive(Debug, PartialEq)]
+enum Attribute {
+    Text(String),
+    Numbers(Vec<f64>),
+}
+
+struct Variable {
+    name: String,
+    dims: Vec<usize>,
+    attrs: Vec<(String, Attribute)>,
+    nc_type: u32,
+    begin: u64,
+}
+
+struct Header {
+    dims: Vec<(String, u32)>,
+    attrs: Vec<(String, Attribute)>,
+    vars: Vec<Variable>,
+}
+
+impl Header {
+    fn var(&self, name: &str) -> &Variable {
+        self.vars.iter().find(|v| v.name == name).unwrap_or_else(|| panic!("no variable {}", name))
+    }
+
+    fn dim_names(&self, var: &str) -> Vec<&str> {
+        self.var(var).dims.iter().map(|d| self.dims[*d].0.as_str()).collect()
+    }
+}
+
+impl Variable {
+    fn attr(&self, name: &str) -> &Attribute {
+        &self.attrs.iter().find(|(n, _)| n == name).unwrap_or_else(|| panic!("no attribute {} on {}", name, self.name)).1
+    }
+
+    fn text(&self, name: &str) -> &str {
+        match self.attr(name) {
+            Attribute::Text(text) => text,
+            other => panic!("{:?}", other),
+        }
+    }
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    at: usize,
+}
+
+impl Parser<'_> {
+    fn u32(&mut self) -> u32 {
+        self.at += 4;
+        u32::from_be_bytes(self.bytes[self.at - 4..self.at].try_into().unwrap())
+    }
+
+    fn padded(&mut self, len: usize) -> &[u8] {
+        let start = self.at;
+        self.at += len.next_multiple_of(4);
+        &self.bytes[start..start + len]
+    }
+
+    fn name(&mut self) -> String {
+        let len = self.u32() as usize;
+        String::from_utf8(self.padded(len).to_vec()).unwrap()
+    }
+
+    // absent lists are ZERO ZERO
+    fn list(&mut self, tag: u32) -> usize {
+        let found = self.u32();
+        let count = self.u32() as usize;
+        assert!(found == tag || (found == 0 && count == 0), "tag {} instead of {}", found, tag);
+        count
+    }
+
+    fn attrs(&mut self) -> Vec<(String, Attribute)> {
+        (0..self.list(0x0C))
+            .map(|_| {
+                let name = self.name();
+                let nc_type = self.u32();
+                let count = self.u32() as usize;
+                let size = [0, 1, 1, 2, 4, 4, 8][nc_type as usize];
+                let bytes = self.padded(count * size).to_vec();
+                let value = match nc_type {
+                    2 => Attribute::Text(String::from_utf8(bytes).unwrap()),
+                    4 => Attribute::Numbers(bytes.chunks(4).map(|c| i32::from_be_bytes(c.try_into().unwrap()) as f64).collect()),
+                    5 => Attribute::Numbers(bytes.chunks(4).map(|c| f32::from_be_bytes(c.try_into().unwrap()) as f64).collect()),
+                    6 => Attribute::Numbers(bytes.chunks(8).map(|c| f64::from_be_bytes(c.try_into().unwrap())).collect()),
+                    _ => panic!("type {}", nc_type),
+                };
+                (name, value)
+            })
+            .collect()
+    }
+
+    fn header(&mut self) -> Header {
+        assert_eq!(self.padded(4), b"CDF\x02");
+        assert_eq!(self.u32(), 0, "no record");
+        let dims = (0..self.list(0x0A)).map(|_| (self.name(), self.u32())).collect();
+        let attrs = self.attrs();
+        let vars = (0..self.list(0x0B))
+            .map(|_| {
+                let name = self.name();
+                let dims = (0..self.u32()).map(|_| self.u32() as usize).collect();
+                let attrs = self.attrs();
+                let nc_type = self.u32();
+                let _vsize = self.u32();
+                let begin = u64::from_be_bytes(self.padded(8).try_into().unwrap());
+                Variable { name, dims, attrs, nc_type, begin }
+            })
+            .collect();
+        Header { dims, attrs, vars }
+    }
+}
+
+fn netcdf(subsets: &[Vec<DataValue>]) -> (Vec<u8>, Header) {
+    let mut bytes = Vec::new();
+    write_netcdf(&mut bytes, subsets).unwrap();
+    let header = Parser { bytes: &bytes, at: 0 }.header();
+    (bytes, header)
+}
+
+fn doubles(bytes: &[u8], var: &Variable, count: usize) -> Vec<f64> {
+    assert_eq!(var.nc_type, 6);
+    bytes[var.begin as usize..][..8 * count].chunks(8).map(|c| f64::from_be_bytes(c.try_into().unwrap())).collect()
+}
+
+#[test]
+fn netcdf_radar_grid() {
+    let (bytes, header) = netcdf(&subsets("ed4_opera_247"));
+    assert_eq!(header.dims, [("y".to_string(), 3), ("x".to_string(), 4)]);
+    assert!(header.attrs.contains(&("Conventions".to_string(), Attribute::Text("CF-1.8".to_string()))));
+
+    let image = header.var("radar_image");
+    assert_eq!(header.dim_names("radar_image"), ["y", "x"]);
+    assert_eq!(image.nc_type, 5);
+    assert_eq!(image.text("units"), "dBZ");
+    assert_eq!(image.text("grid_mapping"), "projection");
+    assert_eq!(image.text("coordinates"), "time latitude longitude");
+    assert_eq!(*image.attr("_FillValue"), Attribute::Numbers(vec![95.5]));
+
+    let projection = header.var("projection");
+    assert!(projection.dims.is_empty());
+    assert_eq!(projection.text("grid_mapping_name"), "polar_stereographic");
+    assert_eq!(*projection.attr("straight_vertical_longitude_from_pole"), Attribute::Numbers(vec![10.0]));
+    assert_eq!(*projection.attr("standard_parallel"), Attribute::Numbers(vec![45.0]));
+    assert_eq!(*projection.attr("false_easting"), Attribute::Numbers(vec![-500000.0]));
+
+    // scalar CF time coordinate : 2024-12-28 12:15
+    let time = header.var("time");
+    assert!(time.dims.is_empty());
+    assert_eq!((time.text("standard_name"), time.text("units")), ("time", "seconds since 1970-01-01 00:00:00"));
+    assert_eq!(doubles(&bytes, time, 1), [1735388100.0]);
+
+    assert_eq!(header.dim_names("x"), ["x"]);
+    assert_eq!(header.var("x").text("standard_name"), "projection_x_coordinate");
+    assert_eq!(header.dim_names("latitude"), ["y", "x"]);
+    let x = doubles(&bytes, header.var("x"), 4);
+    assert_eq!(x[1] - x[0], 1000.0);
+}
+
+#[test]
+fn netcdf_observations_ragged_array() {
+    // every station twice : contiguous ragged array of 3 stations x 2 observations
+    let subsets: Vec<Vec<DataValue>> = subsets("ed3_subsets").into_iter().flat_map(|s| [s.clone(), s]).collect();
+    let (bytes, header) = netcdf(&subsets);
+    assert!(header.attrs.contains(&("featureType".to_string(), Attribute::Text("timeSeries".to_string()))));
+    let dims: Vec<(&str, u32)> = header.dims.iter().take(2).map(|(n, l)| (n.as_str(), *l)).collect();
+    assert_eq!(dims, [("station", 3), ("obs", 6)]);
+
+    let row_size = header.var("row_size");
+    assert_eq!(header.dim_names("row_size"), ["station"]);
+    assert_eq!((row_size.nc_type, row_size.text("sample_dimension")), (4, "obs"));
+    let counts: Vec<i32> = bytes[row_size.begin as usize..][..12].chunks(4).map(|c| i32::from_be_bytes(c.try_into().unwrap())).collect();
+    assert_eq!(counts, [2, 2, 2]);
+
+    assert_eq!(header.var("station_id").text("cf_role"), "timeseries_id");
+    assert_eq!(header.dim_names("station_id"), ["station", "station_id_strlen"]);
+    let ids = &bytes[header.var("station_id").begin as usize..][..15];
+    assert_eq!(ids, b"071490748107650".as_slice());
+    assert_eq!(header.dim_names("lat"), ["station"]);
+    assert_eq!(header.dim_names("time"), ["obs"]);
+
+    // element variables along obs, located by time lat lon
+    let numbers: Vec<&Variable> = header.vars.iter().filter(|v| v.attrs.iter().any(|(n, _)| n == "bufr_descriptor") && v.nc_type == 6).collect();
+    assert!(!numbers.is_empty());
+    for var in numbers {
+        assert_eq!(header.dim_names(&var.name), ["obs"]);
+        assert_eq!(var.text("coordinates"), "time lat lon");
+    }
+    assert!(header.vars.iter().all(|v| v.name != "radar_image"));
+}
+
+#[test]
+fn netcdf_dimensions_keep_their_length() {
+    let mut dataset = Dataset::default();
+    assert_eq!(dataset.add_dim("obs", 3).unwrap(), 0);
+    assert_eq!(dataset.add_dim("station", 2).unwrap(), 1);
+    assert_eq!(dataset.add_dim("obs", 3).unwrap(), 0);
+    let error = dataset.add_dim("obs", 4).unwrap_err();
+    assert!(error.to_string().contains("already defined with length 3"), "{}", error);
+}
+
+fn json(name: &str) -> Value {
+    let message = fs::read(data_dir().join(name).with_extension("bufr")).unwrap();
+    let mut decoder = decoder();
+    decoder.decode_bufr_message(&mut BitReader::new(message.as_slice()), 8).unwrap().unwrap();
+    json::last_message(&decoder).unwrap()
+}
+
+#[test]
+fn json_descriptor_tree() {
+    let element = |fxy: &str, name: &str, unit: &str, value: Value| {
+        json!({ "type": "element", "fxy": fxy, "name": name, "unit": unit, "missing": value.is_null(), "value": value })
+    };
+    let temperature = |value: Value| element("0-12-101", "Temperature/dry-bulb temperature", "K", value);
+    let expected = json!({
+        "edition": 2,
+        "total_length": 78,
+        "section1": {
+            "master_table": 0,
+            "centre": 85,
+            "sub_centre": 0,
+            "update_sequence": 0,
+            "optional_section": false,
+            "category": 0,
+            "subcategory": 1,
+            "master_table_version": 13,
+            "local_table_version": 12,
+            "reference_time": "2009-03-14T06:00:00",
+        },
+        "section3": {
+            "number_of_subsets": 1,
+            "observed": true,
+            "compressed": false,
+            "descriptors": ["3-01-011", "0-04-004", "1-01-000", "0-31-001", "0-12-101", "0-01-196", "0-01-195"],
+        },
+        "subsets": [[
+            {
+                "type": "sequence",
+                "fxy": "3-01-011",
+                "children": [
+                    element("0-04-001", "Year", "Year", json!(2009.0)),
+                    element("0-04-002", "Month", "Month", json!(3.0)),
+                    element("0-04-003", "Day", "Day", json!(14.0)),
+                ],
+            },
+            element("0-04-004", "Hour", "Hour", json!(6.0)),
+            {
+                "type": "replication",
+                "fxy": "1-01-000",
+                "factor": element("0-31-001", "Delayed descriptor replication factor", "Numeric", json!(3.0)),
+                "count": 3,
+                "repetitions": [[temperature(json!(271.35))], [temperature(Value::Null)], [temperature(json!(280.1))]],
+            },
+            element("0-01-196", "NUMERO DE DEPARTEMENT", "Numeric", json!(31.0)),
+            element("0-01-195", "MOBIL LAND STATION IDENTIFIER", "CCITT IA5", json!("FMOB42")),
+        ]],
+    });
+    assert_eq!(json("ed2_replication_85"), expected);
+}
+
+// Every node of a JSON subset, depth first
+fn flatten<'a>(nodes: &'a Value, found: &mut Vec<&'a Value>) {
+    for node in nodes.as_array().unwrap() {
+        found.push(node);
+        match node["type"].as_str().unwrap() {
+            "sequence" => flatten(&node["children"], found),
+            "replication" => node["repetitions"].as_array().unwrap().iter().for_each(|r| flatten(r, found)),
+            _ => {}
+        }
+    }
+}
+
+#[test]
+fn json_code_tables_and_operators() {
+    // the tables carry no code table meanings : the code is given with the "Code table" unit
+    let message = json("ed4_opera_247");
+    let mut nodes = Vec::new();
+    flatten(&message["subsets"][0], &mut nodes);
+    let codes: Vec<(&str, &Value)> = nodes.iter().filter(|n| n["unit"] == "Code table").map(|n| (n["fxy"].as_str().unwrap(), &n["value"])).collect();
+    assert_eq!(codes, [("0-29-201", &json!(1.0)), ("0-30-197", &json!(0.0))]);
+
+    let message = json("ed4_operators");
+    let mut nodes = Vec::new();
+    flatten(&message["subsets"][0], &mut nodes);
+    let operators: Vec<&Value> = nodes.iter().filter(|n| n["type"] == "operator").map(|n| &n["fxy"]).collect();
+    assert_eq!(operators, ["2-01-132", "2-01-000", "2-02-129", "2-02-000", "2-08-010", "2-08-000", "2-03-016", "2-03-255", "2-03-000"]);
+    // the new reference value of 2-03 has no Table B entry
+    let element = nodes.iter().find(|n| n["type"] == "element" && n["name"].is_null()).unwrap();
+    assert_eq!((&element["fxy"], &element["missing"]), (&json!("0-12-101"), &json!(true)));
+}
+
+fn csv(messages: &[&str], options: CsvOptions) -> String {
+    let mut bytes = Vec::new();
+    let mut exporter = CsvExporter::new(&mut bytes, options);
+    for name in messages {
+        exporter.write_subsets(&subsets(name)).unwrap();
+    }
+    exporter.flush().unwrap();
+    drop(exporter);
+    String::from_utf8(bytes).unwrap()
+}
+
+#[test]
+fn csv_selected_columns() {
+    // written as users type them, in the order asked, absent ones skipped
+    let columns = ["0-1-15", "0-1-1", "0-01-002", "0-12-4"].map(String::from).to_vec();
+    let text = csv(&["ed3_subsets"], CsvOptions { columns: Some(columns), ..Default::default() });
+    assert_eq!(
+        text,
+        "message,subset,Station or site name,WMO block number,WMO station number\n\
+         1,1,PARIS-MONTSOURIS,7,149\n\
+         1,2,LYON-ST EXUPERY,7,481\n\
+         1,3,,7,650\n"
+    );
+}
+
+#[test]
+fn tsv_units_and_repeated_elements() {
+    let text = csv(&["ed3_subsets"], CsvOptions { delimiter: b'\t', units_in_header: true, ..Default::default() });
+    let lines: Vec<Vec<&str>> = text.lines().map(|l| l.split('\t').collect()).collect();
+    assert_eq!(lines.len(), 4);
+    assert!(lines.iter().all(|l| l.len() == lines[0].len()));
+    assert_eq!(lines[0][..5], ["message", "subset", "WMO block number (Numeric)", "WMO station number (Numeric)", "Station or site name"]);
+    assert!(lines[0].contains(&"Height of station (m)"));
+    assert!(lines[0].contains(&"Wind speed #1 (m s-1)") && lines[0].contains(&"Wind speed #2 (m s-1)"));
+    assert_eq!(lines[1][..6], ["1", "1", "7", "149", "PARIS-MONTSOURIS", "48.82167"]);
+    assert_eq!(lines[3][..5], ["1", "3", "7", "650", ""]);
+}
+
+#[test]
+fn csv_columns_of_the_first_message() {
+    // the second message has other elements : same columns, left empty
+    let text = csv(&["ed3_subsets", "ed4_opera_247"], CsvOptions::default());
+    let lines: Vec<&str> = text.lines().collect();
+    let width = lines[0].split(',').count();
+    assert_eq!(lines.len(), 5);
+    assert_eq!(lines[4].split(',').count(), width);
+    assert!(lines[4].starts_with("2,1,"));
+}
+
+#[test]
+fn closed_pipe_is_a_clean_exit() {
+    // bufr_decoder ... | head : far more output than the pipe holds
+    let message = fs::read(data_dir().join("ed3_subsets.bufr")).unwrap();
+    let path = Path::new(env!("CARGO_TARGET_TMPDIR")).join("closed_pipe.bufr");
+    fs::write(&path, message.repeat(2000)).unwrap();
+    let tables = tables_dir();
+    let path = path.to_str().unwrap();
+    let tables = tables.to_str().unwrap();
+    for args in [
+        vec!["decode", path, "--tables", tables, "--format", "json"],
+        vec!["decode", path, "--tables", tables, "--format", "csv"],
+        vec!["decode", path, "--tables", tables],
+        vec!["ls", path],
+        vec!["dump", path, "--tables", tables],
+    ] {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_bufr_decoder"))
+            .args(&args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut head = [0; 100];
+        child.stdout.take().unwrap().read_exact(&mut head).unwrap();
+        let output = child.wait_with_output().unwrap();
+        assert!(output.status.success(), "{:?} : {:?}", args, output.status);
+        assert_eq!(String::from_utf8_lossy(&output.stderr), "", "{:?}", args);
+    }
+}
+
+// stdout of the command line, which must succeed
+fn run(args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_bufr_decoder")).args(args).output().unwrap();
+    assert!(output.status.success(), "{:?} : {}", args, String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn ls_lists_every_message() {
+    let mut file = Vec::new();
+    for name in ["ed3_subsets", "ed4_compressed", "ed2_replication_85"] {
+        file.extend(fs::read(data_dir().join(name).with_extension("bufr")).unwrap());
+    }
+    let path = Path::new(env!("CARGO_TARGET_TMPDIR")).join("ls.bufr");
+    fs::write(&path, file).unwrap();
+    let path = path.to_str().unwrap();
+
+    let listing = run(&["ls", path]);
+    let expected = [
+        path,
+        "    #     offset   length  ed    centre   categ  tables reference time      subsets comp",
+        "    1          0      168   3      85/0     0/2   16/14 2024-02-29T18:30:00       3   no",
+        "    2        168      191   4      85/5     0/0   16/14 2024-12-28T12:00:30       4  yes",
+        "    3        359       78   2      85/0     0/1   13/12 2009-03-14T06:00:00       1   no",
+    ];
+    assert_eq!(listing.lines().collect::<Vec<_>>(), expected);
+
+    // the filtered out messages keep their number
+    let listing = run(&["ls", path, "--master", "16", "--min-subsets", "4"]);
+    assert_eq!(listing.lines().collect::<Vec<_>>(), [expected[0], expected[1], expected[3]]);
+}
+
+#[test]
+fn dump_traces_every_element() {
+    // 3-01-011 expanded, 0-12-101 two bits wider under 2-01-130, the delayed replication factor
+    // then both wind speeds of the replication
+    let path = data_dir().join("ed3_compressed.bufr");
+    let tables = tables_dir();
+    let dump = run(&["dump", path.to_str().unwrap(), "--tables", tables.to_str().unwrap()]);
+    let expected = format!(
+        "{} message 1\n{}",
+        path.display(),
+        "  sub      bit wid fxy             raw scal   reference value                    description
+    0       32  12 0-04-001       2023    0           0 2023 a                   Year
+    0       50   4 0-04-002          7    0           0 7 mon                    Month
+    0       60   6 0-04-003          1    0           0 1 d                      Day
+    0       72  18 0-12-101      29200    2           0 293.5 K                  Temperature/air temperature
+    0      120  16 0-31-002          2    0           0 2 Numeric                Extended delayed descriptor replication factor
+    0      142  12 0-11-002          0    1           0 1.5 m s-1                Wind speed
+    0      181  12 0-11-002          0    1           0 3 m s-1                  Wind speed
+"
+    );
+    assert_eq!(dump, expected);
+}
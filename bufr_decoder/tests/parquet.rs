@@ -0,0 +1,95 @@
+// Arrow batches and Parquet files of decoded messages, with the `arrow` feature only
+#![cfg(feature = "arrow")]
+mod common;
+
+use std::fs::File;
+use std::path::Path;
+
+use arrow::array::{Array, AsArray, Float64Array, StringArray, UInt32Array};
+use arrow::datatypes::{DataType, Int32Type, Int64Type, UInt32Type};
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+use bufr_decoder::arrow_export::{BatchBuilder, ParquetExporter};
+
+use common::subsets;
+
+#[test]
+fn batches_across_messages() {
+    // 3 subsets per message, batches of 4 rows
+    let subsets = subsets("ed3_subsets");
+    let mut builder = BatchBuilder::new(4, None);
+    let mut batches = builder.push_subsets(&subsets).unwrap();
+    assert!(batches.is_empty());
+    batches.extend(builder.push_subsets(&subsets).unwrap());
+    batches.extend(builder.finish().unwrap());
+    assert_eq!(batches.iter().map(|b| b.num_rows()).collect::<Vec<_>>(), [4, 2]);
+
+    let column = |index: usize, name: &str| batches[index].column_by_name(name).unwrap().as_primitive::<UInt32Type>().values().to_vec();
+    assert_eq!(column(0, "message"), [1, 1, 1, 2]);
+    assert_eq!(column(0, "subset"), [1, 2, 3, 1]);
+    assert_eq!(column(1, "message"), [2, 2]);
+    assert_eq!(column(1, "subset"), [2, 3]);
+
+    let schema = builder.schema().unwrap();
+    let field = schema.field_with_name("Station or site name").unwrap();
+    assert_eq!(field.data_type(), &DataType::Utf8);
+    assert_eq!(field.metadata().get("bufr_descriptor").map(String::as_str), Some("0-01-015"));
+    let names = batches[0].column_by_name("Station or site name").unwrap().as_any().downcast_ref::<StringArray>().unwrap();
+    assert_eq!(names.value(3), "PARIS-MONTSOURIS");
+}
+
+#[test]
+fn missing_values_are_null() {
+    let mut builder = BatchBuilder::new(10, None);
+    assert!(builder.push_subsets(&subsets("ed3_subsets")).unwrap().is_empty());
+    let batch = builder.finish().unwrap().unwrap();
+    // the second wind direction is missing in every subset, the second speed is not
+    let direction = batch.column_by_name("Wind direction (degree true) #2").unwrap();
+    assert_eq!(direction.null_count(), 3);
+    let speed = batch.column_by_name("Wind speed #2").unwrap().as_any().downcast_ref::<Float64Array>().unwrap();
+    assert_eq!((speed.null_count(), speed.value(0)), (0, 0.0));
+    assert!(builder.finish().unwrap().is_none());
+}
+
+#[test]
+fn code_tables_are_dictionary_encoded() {
+    let mut builder = BatchBuilder::new(10, None);
+    builder.push_subsets(&subsets("ed4_opera_247")).unwrap();
+    let batch = builder.finish().unwrap().unwrap();
+    let projection = batch.column_by_name("Projection type").unwrap();
+    assert_eq!(projection.data_type(), &DataType::Dictionary(Box::new(DataType::Int32), Box::new(DataType::Int64)));
+    let dictionary = projection.as_dictionary::<Int32Type>();
+    let codes = dictionary.values().as_primitive::<Int64Type>();
+    assert_eq!(codes.value(dictionary.keys().value(0) as usize), 1);
+    let field = batch.schema_ref().field_with_name("Projection type").unwrap().clone();
+    assert_eq!(field.metadata().get("units").map(String::as_str), Some("Code table"));
+}
+
+#[test]
+fn parquet_round_trip() {
+    let messages = [subsets("ed3_subsets"), subsets("ed4_compressed"), subsets("ed3_subsets")];
+    let path = Path::new(env!("CARGO_TARGET_TMPDIR")).join("round_trip.parquet");
+    let mut exporter = ParquetExporter::new(File::create(&path).unwrap(), 4, None);
+    for subsets in &messages {
+        exporter.write_subsets(subsets).unwrap();
+    }
+    exporter.close().unwrap();
+
+    let mut builder = BatchBuilder::new(4, None);
+    let mut expected = Vec::new();
+    for subsets in &messages {
+        expected.extend(builder.push_subsets(subsets).unwrap());
+    }
+    expected.extend(builder.finish().unwrap());
+
+    let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap()).unwrap().with_batch_size(4).build().unwrap();
+    let found = reader.collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(found.len(), expected.len());
+    for (found, expected) in found.iter().zip(&expected) {
+        assert_eq!(found.schema(), expected.schema());
+        assert_eq!(found, expected);
+    }
+    // the last message has the layout of the first one
+    let messages = found.iter().flat_map(|b| b.column_by_name("message").unwrap().as_any().downcast_ref::<UInt32Array>().unwrap().values().to_vec()).collect::<Vec<_>>();
+    assert_eq!(messages, [1, 1, 1, 2, 2, 2, 2, 3, 3, 3]);
+}
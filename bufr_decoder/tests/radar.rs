@@ -0,0 +1,169 @@
+// Radar products of tests/data/ed4_opera_247.bufr : a 3 x 4 OPERA composite, zlib compressed,
+// dBZ = -32 + 0.5 * pixel and pixel 255 for no data (see tests/data/make_corpus.py)
+mod common;
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+
+use flate2::write::ZlibEncoder;
+
+use bufr_decoder::geotiff::write_geotiff;
+use bufr_decoder::projection::{GridGeometry, ProjectionKind};
+use bufr_decoder::radar::{self, Compression};
+use bufr_decoder::units::Unit;
+use bufr_decoder::{BitReader, BufrDecoder, DataValue};
+
+use common::{data_dir, decoder};
+
+fn opera() -> BufrDecoder {
+    let message = fs::read(data_dir().join("ed4_opera_247.bufr")).unwrap();
+    let mut decoder = decoder();
+    decoder.decode_bufr_message(&mut BitReader::new(message.as_slice()), 8).unwrap().unwrap();
+    decoder
+}
+
+fn value(descriptor: &str, value: f64) -> DataValue {
+    DataValue { descriptor: descriptor.to_string(), description: String::new(), unit: Unit::Numeric, value: Some(value), text: None }
+}
+
+#[test]
+fn opera_image() {
+    let decoder = opera();
+    let image = radar::image(&decoder.subsets()[0]).unwrap();
+    assert_eq!(image.shape(), (3, 4));
+    assert_eq!(image.compression, Compression::Zlib);
+    assert_eq!(image.bytes_per_pixel, 1);
+    assert!(image.scaled);
+    assert_eq!(image.row(0).unwrap(), [-32.0, -27.0, -22.0, -17.0]);
+    assert_eq!(image.get(2, 3), Some(-30.0));
+    assert_eq!(image[(1, 2)], -2.0);
+    // pixel 255 : no data
+    assert!(image.get(1, 3).unwrap().is_nan());
+    assert_eq!(image.nodata, -32.0 + 0.5 * 255.0);
+    assert_eq!(image.get(3, 0), None);
+    assert_eq!(image.get(0, 4), None);
+    assert_eq!(image.row(3), None);
+    assert_eq!(image.rows().count(), 3);
+}
+
+#[test]
+fn uncompressed_doubles_are_big_endian() {
+    let mut values = vec![value("0-30-021", 2.0), value("0-30-022", 1.0)];
+    values.push(DataValue { value: None, ..value("0-30-197", 0.0) });
+    for octet in [1.5f64.to_be_bytes(), (-2.25f64).to_be_bytes()].concat() {
+        values.push(value("0-30-198", octet as f64));
+    }
+    let image = radar::image(&values).unwrap();
+    assert_eq!((image.compression, image.bytes_per_pixel, image.scaled), (Compression::None, 8, false));
+    assert_eq!(image.data, [1.5, -2.25]);
+}
+
+#[test]
+fn oversized_dimensions() {
+    let uncompressed = DataValue { value: None, ..value("0-30-197", 0.0) };
+    let values = [value("0-30-021", 4294967295.0), value("0-30-022", 4294967295.0 * 4294967295.0), uncompressed.clone()];
+    let error = radar::image(&values).unwrap_err();
+    assert!(error.to_string().contains("too large"), "{}", error);
+    let values = [value("0-30-021", 2.0), value("0-30-022", 0.0), uncompressed];
+    assert!(radar::image(&values).is_err());
+}
+
+#[test]
+fn inflated_data_larger_than_the_image() {
+    let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::best());
+    encoder.write_all(&vec![0; 1 << 20]).unwrap();
+    let mut values = vec![value("0-30-021", 2.0), value("0-30-022", 1.0), value("0-30-197", 0.0)];
+    values.extend(encoder.finish().unwrap().into_iter().map(|octet| value("0-30-198", octet as f64)));
+    let error = radar::image(&values).unwrap_err();
+    assert!(error.to_string().contains("larger than a 1 x 2 image"), "{}", error);
+}
+
+#[test]
+fn opera_geometry() {
+    let decoder = opera();
+    let subset = &decoder.subsets()[0];
+    let geometry = GridGeometry::from_values(subset, &radar::image(subset).unwrap()).unwrap();
+    let projection = &geometry.projection;
+    assert_eq!((projection.kind, projection.lon_0, projection.lat_0, projection.lat_1), (ProjectionKind::PolarStereographic, 10.0, 90.0, 45.0));
+    assert_eq!((projection.x_0, projection.y_0), (-500000.0, 250000.0));
+    assert_eq!((geometry.nx, geometry.ny, geometry.dx, geometry.dy), (4, 3, 1000.0, 1000.0));
+    // NW corner 51N 2E : rho = a m_c t / t_c (Snyder 21-34) worked out by hand
+    assert!((geometry.x_min - -1037763.086).abs() < 1e-3 && (geometry.y_max - -3576383.177).abs() < 1e-3, "{:?}", geometry);
+    let (lon, lat) = projection.inverse(geometry.x_min, geometry.y_max);
+    assert!((lon - 2.0).abs() < 1e-9 && (lat - 51.0).abs() < 1e-9);
+    // pixel centres go east along a row and south down a column
+    let (lons, lats) = geometry.lonlats();
+    assert_eq!(lons.len(), 12);
+    assert!(lons[1] > lons[0] && lats[4] < lats[0]);
+    assert!(lats.iter().all(|lat| (50.95..51.0).contains(lat)) && lons.iter().all(|lon| (2.0..2.07).contains(lon)), "{:?} {:?}", lons, lats);
+}
+
+// Little-endian TIFF : tag -> (type, count, value octets)
+fn ifd(tiff: &[u8]) -> HashMap<u16, (u16, u32, Vec<u8>)> {
+    let u16_at = |at: usize| u16::from_le_bytes([tiff[at], tiff[at + 1]]);
+    let u32_at = |at: usize| u32::from_le_bytes(tiff[at..at + 4].try_into().unwrap());
+    assert_eq!((&tiff[..2], u16_at(2)), (&b"II"[..], 42));
+    let start = u32_at(4) as usize;
+    let count = u16_at(start) as usize;
+    assert_eq!(u32_at(start + 2 + 12 * count), 0, "single IFD");
+    let mut entries = HashMap::new();
+    let mut previous = 0;
+    for n in 0..count {
+        let at = start + 2 + 12 * n;
+        let (tag, kind, count) = (u16_at(at), u16_at(at + 2), u32_at(at + 4));
+        assert!(tag > previous, "tags in ascending order");
+        previous = tag;
+        let size = count as usize * [0, 1, 1, 2, 4, 8, 1, 1, 2, 4, 8, 4, 8][kind as usize];
+        let value = if size <= 4 { tiff[at + 8..at + 8 + size].to_vec() } else { tiff[u32_at(at + 8) as usize..][..size].to_vec() };
+        entries.insert(tag, (kind, count, value));
+    }
+    entries
+}
+
+fn numbers(entry: &(u16, u32, Vec<u8>)) -> Vec<f64> {
+    match entry.0 {
+        3 => entry.2.chunks(2).map(|c| u16::from_le_bytes([c[0], c[1]]) as f64).collect(),
+        4 => entry.2.chunks(4).map(|c| u32::from_le_bytes(c.try_into().unwrap()) as f64).collect(),
+        12 => entry.2.chunks(8).map(|c| f64::from_le_bytes(c.try_into().unwrap())).collect(),
+        kind => panic!("type {}", kind),
+    }
+}
+
+#[test]
+fn opera_geotiff() {
+    let decoder = opera();
+    let subset = &decoder.subsets()[0];
+    let image = radar::image(subset).unwrap();
+    let geometry = GridGeometry::from_values(subset, &image).unwrap();
+    let mut tiff = Vec::new();
+    write_geotiff(&mut tiff, &image, &geometry).unwrap();
+
+    let tags = ifd(&tiff);
+    let number = |tag| numbers(&tags[&tag]);
+    assert_eq!((number(256), number(257)), (vec![4.0], vec![3.0]));
+    assert_eq!((number(258), number(259), number(277), number(339)), (vec![32.0], vec![1.0], vec![1.0], vec![3.0]));
+    // one strip of rows north to south, no data written as the scaled missing pixel
+    let (offset, length) = (number(273)[0] as usize, number(279)[0] as usize);
+    let pixels: Vec<f32> = tiff[offset..offset + length].chunks(4).map(|c| f32::from_le_bytes(c.try_into().unwrap())).collect();
+    assert_eq!(pixels, [-32.0, -27.0, -22.0, -17.0, -12.0, -7.0, -2.0, 95.5, -31.5, -31.0, -30.5, -30.0]);
+    assert_eq!(String::from_utf8_lossy(&tags[&42113].2), "95.5\0");
+    assert_eq!(number(33550), [1000.0, 1000.0, 0.0]);
+    assert_eq!(number(33922), [0.0, 0.0, 0.0, geometry.x_min, geometry.y_max, 0.0]);
+
+    // GeoKeyDirectory : version 1.1.0 then (key, location, count, value or index)
+    let directory = number(34735);
+    let doubles = number(34736);
+    assert_eq!(directory[..3], [1.0, 1.0, 0.0]);
+    assert_eq!(directory.len(), 4 + 4 * directory[3] as usize);
+    let keys: HashMap<u16, f64> = directory[4..]
+        .chunks(4)
+        .map(|k| (k[0] as u16, if k[1] == 34736.0 { doubles[k[3] as usize] } else { k[3] }))
+        .collect();
+    assert!(directory[4..].chunks(4).map(|k| k[0]).is_sorted());
+    assert_eq!((keys[&1024], keys[&1025], keys[&3075], keys[&3076]), (1.0, 1.0, 15.0, 9001.0)); // projected, area, polar stereographic, metre
+    assert_eq!((keys[&2057], keys[&2058]), (6378137.0, 6356752.0));
+    assert_eq!((keys[&3081], keys[&3095]), (90.0, 10.0));
+    assert_eq!((keys[&3082], keys[&3083]), (-500000.0, 250000.0));
+    assert!((keys[&3092] - geometry.projection.pole_scale()).abs() < 1e-12 && keys[&3092] < 1.0);
+}